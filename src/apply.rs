@@ -0,0 +1,684 @@
+//! Applying the code changes in an answer to files.
+//!
+//! Three forms of edits are found in an answer: fenced blocks annotated with a file path,
+//! which replace the whole file; unified diffs; and search/replace blocks:
+//!
+//! ```text
+//! src/main.rs
+//! <<<<<<< SEARCH
+//! fn main() {}
+//! =======
+//! fn main() { run() }
+//! >>>>>>> REPLACE
+//! ```
+//!
+//! Diff hunks are matched by their lines rather than their line numbers, which answers often
+//! get wrong. The changes are reviewed hunk by hunk in the TUI, and every write is recorded
+//! in a journal so `@undo-apply` can restore the files.
+use crate::project;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// Applied changes kept for `@undo-apply`
+const JOURNAL_LIMIT: usize = 20;
+/// Unchanged lines shown around each hunk
+const CONTEXT_LINES: usize = 3;
+
+lazy_static! {
+    // Changes waiting to be reviewed in the TUI
+    static ref PENDING_PLAN: Mutex<Option<ApplyPlan>> = Mutex::new(None);
+    static ref HUNK_HEADER: Regex = Regex::new(r"^@@ -(\d+)(?:,\d+)? \+\d+(?:,\d+)? @@").unwrap();
+}
+
+/// An edit of one file found in an answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// The whole new content of the file
+    Whole { path: String, content: String },
+    /// Replace the lines `search` with `replace`. An empty `search` creates the file, or
+    /// inserts before line `near_line` when one is known.
+    Replace {
+        path: String,
+        search: String,
+        replace: String,
+        /// Where the lines are expected, from a diff hunk header
+        near_line: Option<usize>,
+    },
+}
+
+impl Edit {
+    pub fn path(&self) -> &str {
+        match self {
+            Edit::Whole { path, .. } | Edit::Replace { path, .. } => path,
+        }
+    }
+}
+
+/// The new content of a file, with what it was before. `old` is `None` for a new file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    pub path: String,
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// The changes of an answer, ready for review.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApplyPlan {
+    pub answer_id: String,
+    /// The directory the paths of the changes are relative to
+    pub root: PathBuf,
+    pub changes: Vec<FileChange>,
+    /// Edits that could not be used, with the reason
+    pub problems: Vec<String>,
+}
+
+/// A group of changed lines with the unchanged lines around it, as in a unified diff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub header: String,
+    /// Lines with `' '`, `'-'` or `'+'` and without their line ending
+    pub lines: Vec<(char, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    answer_id: String,
+    date: DateTime<Utc>,
+    files: Vec<JournalFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalFile {
+    /// The file that was written, after following symbolic links
+    path: String,
+    /// The content before the change; `None` when the file was created
+    previous: Option<String>,
+    /// Of the content that was written, to notice later edits
+    written_sha256: String,
+}
+
+/// A fenced block of an answer.
+struct Fence<'a> {
+    info: &'a str,
+    /// The last non-empty line before the block
+    before: Option<&'a str>,
+    body: Vec<&'a str>,
+}
+
+/// The edits in `answer`, in the order they appear.
+pub fn parse_edits(answer: &str) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut outside: Vec<&str> = Vec::new();
+    let mut lines = answer.lines();
+    let mut before: Option<&str> = None;
+
+    while let Some(line) = lines.next() {
+        let Some((fence_char, fence_len, info)) = fence_open(line) else {
+            outside.push(line);
+            if !line.trim().is_empty() {
+                before = Some(line);
+            }
+            continue;
+        };
+        let mut body = Vec::new();
+        for line in lines.by_ref() {
+            if fence_close(line, fence_char, fence_len) {
+                break;
+            }
+            body.push(line);
+        }
+        // Text between blocks is scanned for unfenced edits on its own
+        edits.extend(text_edits(&outside, None));
+        outside.clear();
+        edits.extend(fence_edits(&Fence { info, before, body }));
+        before = None;
+    }
+    edits.extend(text_edits(&outside, None));
+    edits
+}
+
+/// The character, length and info string of a line opening a fenced block.
+fn fence_open(line: &str) -> Option<(char, usize, &str)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let fence_char = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let fence_len = trimmed.len() - trimmed.trim_start_matches(fence_char).len();
+    (fence_len >= 3).then(|| (fence_char, fence_len, trimmed[fence_len..].trim()))
+}
+
+fn fence_close(line: &str, fence_char: char, fence_len: usize) -> bool {
+    let trimmed = line.trim();
+    trimmed.len() >= fence_len && trimmed.chars().all(|c| c == fence_char)
+}
+
+fn fence_edits(fence: &Fence) -> Vec<Edit> {
+    let path = fence
+        .info
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .map(|word| word.trim_start_matches("path=").trim_start_matches("file=").trim_start_matches("title="))
+        .map(|word| word.trim_matches(['"', '\'']))
+        .find(|word| looks_like_path(word))
+        .map(String::from)
+        .or_else(|| fence.before.and_then(path_in_line));
+
+    let has_edits = (0..fence.body.len()).any(|index| is_diff_start(&fence.body, index) || fence.body[index].starts_with("<<<<<<< SEARCH"));
+    if has_edits {
+        return text_edits(&fence.body, path.as_deref());
+    }
+    match path {
+        Some(path) if !fence.info.eq_ignore_ascii_case("diff") && !fence.info.eq_ignore_ascii_case("patch") => {
+            let mut content = fence.body.join("\n");
+            content.push('\n');
+            vec![Edit::Whole { path, content }]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// The unified diffs and search/replace blocks in `lines`. `default_path` is the file of
+/// search/replace blocks without a path line before them.
+fn text_edits(lines: &[&str], default_path: Option<&str>) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        if line.starts_with("<<<<<<< SEARCH") {
+            let path = lines[..index]
+                .iter()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .and_then(|line| path_in_line(line))
+                .or_else(|| default_path.map(String::from));
+            let (search, replace, next) = search_replace_block(lines, index + 1);
+            if let Some(path) = path {
+                edits.push(Edit::Replace { path, search, replace, near_line: None });
+            }
+            index = next;
+        } else if is_diff_start(lines, index) {
+            let (diff_edits, next) = file_diff(lines, index);
+            edits.extend(diff_edits);
+            index = next;
+        } else {
+            index += 1;
+        }
+    }
+    edits
+}
+
+/// Whether the `---` and `+++` lines of a file diff start at `index`.
+fn is_diff_start(lines: &[&str], index: usize) -> bool {
+    lines[index].starts_with("--- ") && lines.get(index + 1).is_some_and(|next| next.starts_with("+++ "))
+}
+
+/// The texts of a search/replace block whose SEARCH part starts at `start`, and the line
+/// after the block.
+fn search_replace_block(lines: &[&str], start: usize) -> (String, String, usize) {
+    let mut search = Vec::new();
+    let mut replace = Vec::new();
+    let mut in_replace = false;
+    let mut index = start;
+    while index < lines.len() {
+        let line = lines[index];
+        index += 1;
+        if line.starts_with(">>>>>>> REPLACE") {
+            break;
+        }
+        if !in_replace && line.trim_end() == "=======" {
+            in_replace = true;
+        } else if in_replace {
+            replace.push(line);
+        } else {
+            search.push(line);
+        }
+    }
+    (join_lines(&search), join_lines(&replace), index)
+}
+
+/// The hunks of one file of a unified diff starting with its `---` line at `start`, as
+/// replacements, and the line after them.
+fn file_diff(lines: &[&str], start: usize) -> (Vec<Edit>, usize) {
+    let old_path = diff_path(lines[start], "--- ");
+    let new_path = diff_path(lines[start + 1], "+++ ");
+    let created = old_path == "/dev/null";
+    // A deleted file is left empty
+    let (path, other) = if new_path == "/dev/null" { (&old_path, &new_path) } else { (&new_path, &old_path) };
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .filter(|_| other.starts_with("a/") || other.starts_with("b/") || other == "/dev/null")
+        .unwrap_or(path)
+        .to_string();
+
+    let mut edits = Vec::new();
+    let mut index = start + 2;
+    while index < lines.len() {
+        let Some(captures) = lines[index].starts_with("@@").then(|| HUNK_HEADER.captures(lines[index])) else {
+            break;
+        };
+        let near_line = captures.and_then(|captures| captures[1].parse::<usize>().ok()).filter(|&line| line > 0);
+        index += 1;
+
+        let mut search = Vec::new();
+        let mut replace = Vec::new();
+        while let Some(line) = lines.get(index) {
+            match line.chars().next() {
+                Some(' ') => {
+                    search.push(&line[1..]);
+                    replace.push(&line[1..]);
+                }
+                // Blank context lines often lose their leading space
+                None => {
+                    search.push("");
+                    replace.push("");
+                }
+                Some('-') if !is_diff_start(lines, index) => search.push(&line[1..]),
+                Some('+') => replace.push(&line[1..]),
+                Some('\\') => {}
+                _ => break,
+            }
+            index += 1;
+        }
+        while search.last() == Some(&"") && replace.last() == Some(&"") {
+            search.pop();
+            replace.pop();
+        }
+        edits.push(Edit::Replace {
+            path: path.clone(),
+            search: if created { String::new() } else { join_lines(&search) },
+            replace: join_lines(&replace),
+            near_line,
+        });
+    }
+    (edits, index)
+}
+
+/// The path of a `---` or `+++` line, without the timestamp some tools add.
+fn diff_path(line: &str, prefix: &str) -> String {
+    let path = line[prefix.len()..].split('\t').next().unwrap_or_default();
+    path.trim().trim_matches('"').to_string()
+}
+
+fn join_lines(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// The path a line before a block names, like `src/main.rs`, `**src/main.rs**`,
+/// `` `src/main.rs`: `` or `File: src/main.rs`.
+fn path_in_line(line: &str) -> Option<String> {
+    let line = line.trim().trim_start_matches('#').trim();
+    let line = ["File:", "file:", "Path:", "path:"]
+        .iter()
+        .find_map(|label| line.strip_prefix(label))
+        .unwrap_or(line);
+    let path = line.trim().trim_end_matches(':').trim_matches(['*', '`', '"', '\'']).trim_end_matches(':');
+    looks_like_path(path).then(|| path.to_string())
+}
+
+fn looks_like_path(word: &str) -> bool {
+    !word.is_empty()
+        && !word.contains(char::is_whitespace)
+        && (word.contains('/') || word.contains('.'))
+        && !word.ends_with('.')
+        && !word.contains("://")
+}
+
+/// Work out the new content of each file the edits change, for files below the working
+/// directory.
+pub fn plan(answer_id: &str, edits: &[Edit]) -> ApplyPlan {
+    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    plan_in(&root, answer_id, edits)
+}
+
+/// Work out the new content of each file below `root` the edits change. Edits of the same
+/// file apply in order; those that cannot be applied are listed as problems.
+pub fn plan_in(root: &Path, answer_id: &str, edits: &[Edit]) -> ApplyPlan {
+    let mut changes: Vec<FileChange> = Vec::new();
+    let mut problems = Vec::new();
+
+    for edit in edits {
+        let target = match check_path(root, edit.path()) {
+            Ok(target) => target,
+            Err(problem) => {
+                problems.push(problem);
+                continue;
+            }
+        };
+        let index = match changes.iter().position(|change| change.path == edit.path()) {
+            Some(index) => index,
+            None => {
+                let old = match read_text(edit.path(), &target) {
+                    Ok(old) => old,
+                    Err(problem) => {
+                        problems.push(problem);
+                        continue;
+                    }
+                };
+                changes.push(FileChange {
+                    path: edit.path().to_string(),
+                    new: old.clone().unwrap_or_default(),
+                    old,
+                });
+                changes.len() - 1
+            }
+        };
+        let change = &mut changes[index];
+        match apply_edit(change, edit) {
+            Ok(new) => change.new = new,
+            Err(problem) => problems.push(format!("{}: {}", edit.path(), problem)),
+        }
+    }
+    changes.retain(|change| change.old.as_deref() != Some(change.new.as_str()));
+    ApplyPlan {
+        answer_id: answer_id.to_string(),
+        root: root.to_path_buf(),
+        changes,
+        problems,
+    }
+}
+
+/// Edits may only change files below `root`; returns the file a change of `path` writes.
+fn check_path(root: &Path, path: &str) -> Result<PathBuf, String> {
+    let outside = || format!("{}: only files below the working directory are changed", path);
+    let escapes = Path::new(path)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(outside());
+    }
+
+    // A symbolic link below the root may point out of it; resolve the part of the path that
+    // exists, dangling links included, and check where it ends up
+    let root = root.canonicalize().map_err(|e| format!("{}: {}", path, e))?;
+    let full = root.join(path);
+    let mut existing = full.clone();
+    while fs::symlink_metadata(&existing).is_err() && existing.pop() {}
+    match existing.canonicalize() {
+        Ok(resolved) if resolved.starts_with(&root) => {
+            // Joining an empty rest would add a trailing slash
+            match full.strip_prefix(&existing) {
+                Ok(rest) if !rest.as_os_str().is_empty() => Ok(resolved.join(rest)),
+                _ => Ok(resolved),
+            }
+        }
+        _ => Err(outside()),
+    }
+}
+
+/// The content of the file `target` a change of `path` writes, `None` when it does not exist
+/// yet. Files that are not UTF-8 text are refused rather than rewritten with their invalid
+/// bytes replaced.
+fn read_text(path: &str, target: &Path) -> Result<Option<String>, String> {
+    match fs::read(target) {
+        Ok(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| format!("{}: not UTF-8 text, so it is not changed", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+fn apply_edit(change: &FileChange, edit: &Edit) -> Result<String, String> {
+    let (search, replace, near_line) = match edit {
+        Edit::Whole { content, .. } => return Ok(content.clone()),
+        Edit::Replace { search, replace, near_line, .. } => (search, replace, *near_line),
+    };
+    let exists = change.old.is_some() || !change.new.is_empty();
+    if search.is_empty() {
+        return match near_line {
+            _ if !exists => Ok(replace.clone()),
+            Some(line) => {
+                let lines: Vec<&str> = change.new.split_inclusive('\n').collect();
+                let at = (line - 1).min(lines.len());
+                Ok([lines[..at].concat(), replace.clone(), lines[at..].concat()].concat())
+            }
+            None => Err("an empty SEARCH part only creates new files".to_string()),
+        };
+    }
+
+    let lines: Vec<&str> = change.new.split_inclusive('\n').collect();
+    let wanted: Vec<&str> = search.lines().collect();
+    let mut starts = matching_starts(&lines, &wanted, |a, b| a.trim_end_matches(['\n', '\r']) == b);
+    if starts.is_empty() {
+        starts = matching_starts(&lines, &wanted, |a, b| a.trim_end() == b.trim_end());
+    }
+    let start = match (starts.len(), near_line) {
+        (0, _) => return Err(format!("the lines to replace were not found: {}", wanted.first().unwrap_or(&"").trim())),
+        (1, _) => starts[0],
+        (_, Some(line)) => *starts.iter().min_by_key(|start| start.abs_diff(line - 1)).unwrap(),
+        (count, None) => return Err(format!("the lines to replace were found {} times", count)),
+    };
+
+    let end = start + wanted.len();
+    let line_ending = if lines[start].ends_with("\r\n") { "\r\n" } else { "\n" };
+    let mut replacement: String = replace.lines().map(|line| format!("{}{}", line, line_ending)).collect();
+    if end == lines.len() && !lines[end - 1].ends_with('\n') {
+        replacement.truncate(replacement.len() - line_ending.len().min(replacement.len()));
+    }
+    Ok([lines[..start].concat(), replacement, lines[end..].concat()].concat())
+}
+
+fn matching_starts(lines: &[&str], wanted: &[&str], same: impl Fn(&str, &str) -> bool) -> Vec<usize> {
+    if wanted.len() > lines.len() {
+        return Vec::new();
+    }
+    (0..=lines.len() - wanted.len())
+        .filter(|&start| wanted.iter().enumerate().all(|(offset, line)| same(lines[start + offset], line)))
+        .collect()
+}
+
+/// The hunks of the change from `old` to `new`.
+pub fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    diff.unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+        .map(|hunk| Hunk {
+            header: hunk.header().to_string(),
+            lines: hunk
+                .iter_changes()
+                .map(|change| {
+                    let sign = match change.tag() {
+                        ChangeTag::Equal => ' ',
+                        ChangeTag::Delete => '-',
+                        ChangeTag::Insert => '+',
+                    };
+                    (sign, change.value().trim_end_matches(['\n', '\r']).to_string())
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// `old` with only the hunks of the change to `new` that are `accepted` applied.
+pub fn merge(old: &str, new: &str, accepted: &[bool]) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let groups = diff.grouped_ops(CONTEXT_LINES);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+
+    let mut merged = String::with_capacity(new.len());
+    for op in diff.ops() {
+        let take_new = op.tag() != DiffTag::Equal
+            && groups
+                .iter()
+                .position(|group| group.contains(op))
+                .is_some_and(|index| accepted.get(index).copied().unwrap_or(false));
+        if take_new {
+            merged.extend(new_lines[op.new_range()].iter().copied());
+        } else {
+            merged.extend(old_lines[op.old_range()].iter().copied());
+        }
+    }
+    merged
+}
+
+/// Leave a plan behind for the TUI to review.
+pub fn show_in_preview(plan: ApplyPlan) {
+    *PENDING_PLAN.lock().unwrap() = Some(plan);
+}
+
+pub fn take_pending_plan() -> Option<ApplyPlan> {
+    PENDING_PLAN.lock().unwrap().take()
+}
+
+pub fn journal_path() -> PathBuf {
+    project::current_scope().data_dir().join("apply-journal.json")
+}
+
+/// Write the accepted hunks of each change of `plan`, `accepted[i]` being those of
+/// `plan.changes[i]`, and journal the previous contents. A file edited since the plan was
+/// made is left alone. When a write fails, the files written before it are restored.
+pub fn write_changes(plan: &ApplyPlan, accepted: &[Vec<bool>], journal: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let mut written = Vec::new();
+    let mut paths = Vec::new();
+    let mut notes = Vec::new();
+
+    for (change, accepted) in plan.changes.iter().zip(accepted) {
+        if !accepted.contains(&true) {
+            continue;
+        }
+        // The plan checked the path as well, but links may have changed since
+        let target = match check_path(&plan.root, &change.path) {
+            Ok(target) => target,
+            Err(problem) => {
+                notes.push(problem);
+                continue;
+            }
+        };
+        match read_text(&change.path, &target) {
+            Ok(current) if current == change.old => {}
+            Ok(_) => {
+                notes.push(format!("{} changed since the answer was read and was not written", change.path));
+                continue;
+            }
+            Err(problem) => {
+                notes.push(problem);
+                continue;
+            }
+        }
+        let content = match &change.old {
+            Some(old) => merge(old, &change.new, accepted),
+            None => change.new.clone(),
+        };
+        if let Err(e) = write_atomic(&target, &content) {
+            let failures = restore(&written);
+            if failures.is_empty() {
+                return Err(format!("{}: {}; the files written before it were restored", change.path, e).into());
+            }
+            return Err(format!("{}: {}; restoring the files written before it failed:\n{}", change.path, e, failures.join("\n")).into());
+        }
+        written.push(JournalFile {
+            path: target.display().to_string(),
+            previous: change.old.clone(),
+            written_sha256: sha256_hex(&content),
+        });
+        paths.push(change.path.as_str());
+    }
+    if written.is_empty() {
+        notes.insert(0, "No changes were written".to_string());
+        return Ok(notes.join("\n"));
+    }
+
+    let mut entries = read_journal(journal)?;
+    entries.push(JournalEntry {
+        answer_id: plan.answer_id.clone(),
+        date: Utc::now(),
+        files: written,
+    });
+    let excess = entries.len().saturating_sub(JOURNAL_LIMIT);
+    entries.drain(..excess);
+    write_journal(journal, &entries)?;
+
+    notes.insert(0, format!("Applied {} to {} (@undo-apply() restores them)", plan.answer_id, paths.join(", ")));
+    Ok(notes.join("\n"))
+}
+
+/// Restore the files of the last change in the journal. Files edited since are only
+/// restored with `force`. When a file cannot be restored, the change stays in the journal.
+pub fn undo(journal: &Path, force: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let mut entries = read_journal(journal)?;
+    let entry = entries.pop().ok_or("There are no applied changes to undo")?;
+
+    let edited: Vec<&str> = entry
+        .files
+        .iter()
+        .filter(|file| fs::read(&file.path).map_or(true, |bytes| sha256_hex(&String::from_utf8_lossy(&bytes)) != file.written_sha256))
+        .map(|file| file.path.as_str())
+        .collect();
+    if !edited.is_empty() && !force {
+        return Err(format!("{} changed since it was applied; @undo-apply(force) restores it anyway", edited.join(", ")).into());
+    }
+
+    let failures = restore(&entry.files);
+    if !failures.is_empty() {
+        return Err(format!("{}\n@undo-apply(force) tries again", failures.join("\n")).into());
+    }
+    write_journal(journal, &entries)?;
+    let paths: Vec<&str> = entry.files.iter().map(|file| file.path.as_str()).collect();
+    Ok(format!("Restored {} as before @apply({})", paths.join(", "), entry.answer_id))
+}
+
+/// Put back the previous contents of `files`; returns those that could not be restored.
+fn restore(files: &[JournalFile]) -> Vec<String> {
+    let mut failures = Vec::new();
+    for file in files.iter().rev() {
+        let result = match &file.previous {
+            Some(previous) => write_atomic(Path::new(&file.path), previous),
+            None => fs::remove_file(&file.path),
+        };
+        if let Err(e) = result {
+            failures.push(format!("Could not restore {}: {}", file.path, e));
+        }
+    }
+    failures
+}
+
+/// Write `content` to a temporary file next to `path` and rename it over `path`, so the file
+/// is never seen half written. A symbolic link is followed and its target replaced, so the
+/// link stays. The permissions of an existing file are kept.
+fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    let path = &path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temporary = parent.join(format!(".{}.cai-{}", name, uuid::Uuid::new_v4()));
+
+    let result = (|| {
+        fs::write(&temporary, content)?;
+        fs::File::open(&temporary)?.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temporary, metadata.permissions())?;
+        }
+        fs::rename(&temporary, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+fn read_journal(journal: &Path) -> Result<Vec<JournalEntry>, Box<dyn std::error::Error>> {
+    if !journal.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(journal)?)?)
+}
+
+fn write_journal(journal: &Path, entries: &[JournalEntry]) -> Result<(), Box<dyn std::error::Error>> {
+    write_atomic(journal, &serde_json::to_string_pretty(entries)?)?;
+    Ok(())
+}
+
+fn sha256_hex(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
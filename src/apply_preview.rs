@@ -0,0 +1,198 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Cell, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::{Style, Stylize, palette::tailwind::{AMBER, GREEN, RED, SKY, SLATE}},
+};
+
+use crate::apply::{self, ApplyPlan, Hunk};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApplyPreviewState {
+    Apply,
+    Cancel,
+    Open,
+}
+
+/// Popup showing the changes `@apply` found in an answer, file by file. Each hunk can be
+/// left out before the files are written.
+pub struct ApplyPreview {
+    plan: ApplyPlan,
+    hunks: Vec<Vec<Hunk>>,
+    accepted: Vec<Vec<bool>>,
+    selected_file: TableState,
+    selected_hunk: usize,
+    scroll: u16,
+}
+
+impl ApplyPreview {
+    pub fn new(plan: ApplyPlan) -> Self {
+        let hunks: Vec<Vec<Hunk>> = plan
+            .changes
+            .iter()
+            .map(|change| apply::hunks(change.old.as_deref().unwrap_or_default(), &change.new))
+            .collect();
+        let accepted = hunks.iter().map(|file_hunks| vec![true; file_hunks.len()]).collect();
+        let selected_file = TableState::default().with_selected((!hunks.is_empty()).then_some(0));
+        Self {
+            plan,
+            hunks,
+            accepted,
+            selected_file,
+            selected_hunk: 0,
+            scroll: 0,
+        }
+    }
+
+    /// Write the accepted hunks; returns what was done.
+    pub fn apply(&self) -> String {
+        match apply::write_changes(&self.plan, &self.accepted, &apply::journal_path()) {
+            Ok(message) => message,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> ApplyPreviewState {
+        if key.kind != KeyEventKind::Press {
+            return ApplyPreviewState::Open;
+        }
+        let file = self.selected_file.selected();
+        match key.code {
+            KeyCode::Esc => return ApplyPreviewState::Cancel,
+            KeyCode::Enter => return ApplyPreviewState::Apply,
+            KeyCode::Up => {
+                self.selected_file.select_previous();
+                self.select_hunk(0);
+            }
+            KeyCode::Down if file.is_some_and(|index| index + 1 < self.hunks.len()) => {
+                self.selected_file.select_next();
+                self.select_hunk(0);
+            }
+            KeyCode::Left => self.select_hunk(self.selected_hunk.saturating_sub(1)),
+            KeyCode::Right | KeyCode::Tab => self.select_hunk(self.selected_hunk + 1),
+            KeyCode::Char(' ') => {
+                if let Some(accepted) = file.and_then(|index| self.accepted[index].get_mut(self.selected_hunk)) {
+                    *accepted = !*accepted;
+                }
+            }
+            // Take or leave the whole file
+            KeyCode::Char('f') => {
+                if let Some(index) = file {
+                    let all = !self.accepted[index].iter().all(|accepted| *accepted);
+                    self.accepted[index].iter_mut().for_each(|accepted| *accepted = all);
+                }
+            }
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            _ => {}
+        }
+        ApplyPreviewState::Open
+    }
+
+    fn select_hunk(&mut self, hunk: usize) {
+        let count = self.selected_file.selected().map_or(0, |index| self.hunks[index].len());
+        self.selected_hunk = hunk.min(count.saturating_sub(1));
+        // Bring the hunk into view
+        self.scroll = self.hunk_offset(self.selected_hunk);
+    }
+
+    pub fn render_apply_popup(&mut self, frame: &mut Frame) {
+        let popup_area = self.popup_area(frame.area(), 90, 90);
+        frame.render_widget(Clear, popup_area);
+        let outer = Block::bordered().title(format!(
+            "Apply {} ('Enter' to write, 'Space' to take or leave a hunk, 'f' for the whole file, 'Left'/'Right' to pick a hunk, 'Esc' to cancel)",
+            self.plan.answer_id
+        ));
+        let inner = outer.inner(popup_area);
+        frame.render_widget(outer, popup_area);
+
+        let list_height = (self.hunks.len() as u16 + 3).min(10);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(list_height),
+                Constraint::Min(3),
+                Constraint::Length(self.plan.problems.len() as u16),
+            ])
+            .split(inner);
+
+        let file_rows = self.plan.changes.iter().enumerate().map(|(index, change)| {
+            let (added, removed) = self.hunks[index].iter().flat_map(|hunk| &hunk.lines).fold((0, 0), |(added, removed), (sign, _)| match sign {
+                '+' => (added + 1, removed),
+                '-' => (added, removed + 1),
+                _ => (added, removed),
+            });
+            let taken = self.accepted[index].iter().filter(|accepted| **accepted).count();
+            let status = if change.old.is_none() { "new" } else { "" };
+            Row::new(vec![
+                Cell::from(change.path.clone()),
+                Cell::from(status),
+                Cell::from(format!("+{} -{}", added, removed)),
+                Cell::from(format!("{} of {} hunks", taken, self.accepted[index].len())),
+            ])
+        });
+        let files = Table::new(
+            file_rows,
+            [Constraint::Min(20), Constraint::Length(4), Constraint::Length(12), Constraint::Length(16)],
+        )
+        .header(Row::new(vec!["File", "", "Lines", "Taken"]).bold())
+        .row_highlight_style(Style::new().bg(SKY.c800))
+        .block(Block::bordered().title("Files"));
+        frame.render_stateful_widget(files, rows[0], &mut self.selected_file);
+
+        let diff = Paragraph::new(self.diff_lines())
+            .scroll((self.scroll, 0))
+            .block(Block::bordered().title("Changes"));
+        frame.render_widget(diff, rows[1]);
+
+        let problems: Vec<Line> = self
+            .plan
+            .problems
+            .iter()
+            .map(|problem| Line::styled(format!("Not applied: {}", problem), Style::new().fg(AMBER.c400)))
+            .collect();
+        frame.render_widget(Paragraph::new(problems), rows[2]);
+    }
+
+    /// The diff of the selected file. Hunks that are left out are dimmed.
+    fn diff_lines(&self) -> Vec<Line<'_>> {
+        let Some(file) = self.selected_file.selected() else {
+            return vec![Line::from("No changes found")];
+        };
+        let mut lines = Vec::new();
+        for (index, hunk) in self.hunks[file].iter().enumerate() {
+            let taken = self.accepted[file][index];
+            let mark = if taken { "[x]" } else { "[ ]" };
+            let header = Line::styled(format!("{} {}", mark, hunk.header), Style::new().fg(AMBER.c400));
+            lines.push(if index == self.selected_hunk { header.bg(SKY.c800) } else { header });
+            for (sign, text) in &hunk.lines {
+                let style = match (taken, sign) {
+                    (false, _) => Style::new().fg(SLATE.c500),
+                    (true, '+') => Style::new().fg(GREEN.c400),
+                    (true, '-') => Style::new().fg(RED.c400),
+                    _ => Style::new(),
+                };
+                lines.push(Line::styled(format!("{}{}", sign, text), style));
+            }
+        }
+        lines
+    }
+
+    /// The line of the diff where `hunk` of the selected file starts.
+    fn hunk_offset(&self, hunk: usize) -> u16 {
+        let Some(file) = self.selected_file.selected() else {
+            return 0;
+        };
+        self.hunks[file].iter().take(hunk).map(|hunk| hunk.lines.len() as u16 + 1).sum()
+    }
+
+    fn popup_area(&self, area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+        let vertical = Layout::vertical([Constraint::Percentage(percent_y)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        area
+    }
+}
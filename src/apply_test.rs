@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod tests {
+    use crate::apply::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_edits() {
+        let answer = r#"Here is the new file:
+
+```rust src/new.rs
+fn new() {}
+```
+
+**src/lib.py**
+```python
+print("hi")
+```
+
+```diff
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -10,2 +10,2 @@ fn main() {
+     let a = 1;
+-    let b = 2;
++    let b = 3;
+```
+
+src/util.rs
+```rust
+<<<<<<< SEARCH
+fn old() {}
+=======
+fn renamed() {}
+>>>>>>> REPLACE
+```
+
+```rust
+fn example() {}
+```
+"#;
+        assert_eq!(parse_edits(answer), vec![
+            Edit::Whole { path: "src/new.rs".to_string(), content: "fn new() {}\n".to_string() },
+            Edit::Whole { path: "src/lib.py".to_string(), content: "print(\"hi\")\n".to_string() },
+            Edit::Replace {
+                path: "src/main.rs".to_string(),
+                search: "    let a = 1;\n    let b = 2;\n".to_string(),
+                replace: "    let a = 1;\n    let b = 3;\n".to_string(),
+                near_line: Some(10),
+            },
+            Edit::Replace {
+                path: "src/util.rs".to_string(),
+                search: "fn old() {}\n".to_string(),
+                replace: "fn renamed() {}\n".to_string(),
+                near_line: None,
+            },
+        ]);
+
+        // New files in a diff, and paths that leave the working directory
+        let answer = "--- /dev/null\n+++ b/notes.md\n@@ -0,0 +1,1 @@\n+# Notes\n";
+        assert_eq!(parse_edits(answer), vec![Edit::Replace {
+            path: "notes.md".to_string(),
+            search: String::new(),
+            replace: "# Notes\n".to_string(),
+            near_line: None,
+        }]);
+        let plan = plan("a1", &parse_edits("```sh ../outside.sh\nrm -rf /\n```\n"));
+        assert!(plan.changes.is_empty());
+        assert_eq!(plan.problems.len(), 1);
+    }
+
+    #[test]
+    fn test_hunks_and_merge() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nL\n";
+        let hunks = hunks(old, new);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header, "@@ -1,4 +1,4 @@");
+        assert_eq!(hunks[0].lines[..2], [('-', "a".to_string()), ('+', "A".to_string())]);
+
+        assert_eq!(merge(old, new, &[true, true]), new);
+        assert_eq!(merge(old, new, &[false, true]), "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nL\n");
+        assert_eq!(merge(old, new, &[false, false]), old);
+    }
+
+    #[test]
+    fn test_apply_and_undo() {
+        let root = TempDir::new("apply");
+        let journal = root.join("journal.json");
+        let (main, created) = ("main.rs", "sub/new.rs");
+        fs::write(root.join(main), "fn main() {\n    let a = 1;\n    let b = 2;\n}\n\nfn other() {\n    let b = 2;\n}\n").unwrap();
+
+        // The hunk header is off by a few lines; the lines themselves decide
+        let answer = format!(
+            "--- a/{main}\n+++ b/{main}\n@@ -1,3 +1,3 @@\n     let a = 1;\n-    let b = 2;\n+    let b = 3;\n\n{created}\n<<<<<<< SEARCH\n=======\nfn new() {{}}\n>>>>>>> REPLACE\n\n{main}\n<<<<<<< SEARCH\n    missing\n=======\n>>>>>>> REPLACE\n"
+        );
+        let plan = plan_in(&root, "a1", &parse_edits(&answer));
+        assert_eq!(plan.changes.len(), 2);
+        assert_eq!(plan.changes[0].new, "fn main() {\n    let a = 1;\n    let b = 3;\n}\n\nfn other() {\n    let b = 2;\n}\n");
+        assert_eq!(plan.changes[1].old, None);
+        assert_eq!(plan.problems.len(), 1);
+
+        let message = write_changes(&plan, &[vec![true], vec![true]], &journal).unwrap();
+        assert!(message.starts_with("Applied a1 to main.rs, sub/new.rs"));
+        assert!(fs::read_to_string(root.join(main)).unwrap().contains("let b = 3;"));
+        assert_eq!(fs::read_to_string(root.join(created)).unwrap(), "fn new() {}\n");
+
+        // A file edited after the change is only restored with force
+        fs::write(root.join(created), "fn edited() {}\n").unwrap();
+        assert!(undo(&journal, false).is_err());
+        undo(&journal, true).unwrap();
+        assert!(fs::read_to_string(root.join(main)).unwrap().contains("let b = 2;"));
+        assert!(!root.join(created).exists());
+        assert!(undo(&journal, false).is_err());
+
+        // Files changed since the plan was made are left alone
+        let plan = plan_for(&root, main);
+        fs::write(root.join(main), "changed meanwhile\n").unwrap();
+        let message = write_changes(&plan, &[vec![true]], &journal).unwrap();
+        assert!(message.contains("changed since the answer was read"));
+        assert_eq!(fs::read_to_string(root.join(main)).unwrap(), "changed meanwhile\n");
+
+        // Files that are not UTF-8 and links out of the root are refused
+        fs::write(root.join("data.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        let mut refused = vec!["data.bin", "../outside.rs"];
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(std::env::temp_dir(), root.join("link")).unwrap();
+            refused.push("link/escaped.rs");
+        }
+        for path in refused {
+            let plan = plan_for(&root, path);
+            assert!(plan.changes.is_empty(), "{}", path);
+            assert_eq!(plan.problems.len(), 1, "{}", path);
+        }
+        assert_eq!(fs::read(root.join("data.bin")).unwrap(), [0xff, 0xfe, 0x00]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_through_a_link() {
+        let root = TempDir::new("apply");
+        let journal = root.join("journal.json");
+        fs::write(root.join("target.rs"), "old\n").unwrap();
+        std::os::unix::fs::symlink("target.rs", root.join("alias.rs")).unwrap();
+
+        // The target of the link is written and restored, and the link stays a link
+        let plan = plan_for(&root, "alias.rs");
+        write_changes(&plan, &[vec![true]], &journal).unwrap();
+        assert!(fs::symlink_metadata(root.join("alias.rs")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(root.join("target.rs")).unwrap(), "replaced\n");
+
+        undo(&journal, false).unwrap();
+        assert!(fs::symlink_metadata(root.join("alias.rs")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(root.join("target.rs")).unwrap(), "old\n");
+    }
+
+    fn plan_for(root: &Path, path: &str) -> ApplyPlan {
+        plan_in(root, "a2", &[Edit::Whole { path: path.to_string(), content: "replaced\n".to_string() }])
+    }
+}
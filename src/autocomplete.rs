@@ -4,7 +4,7 @@ use rustyline::completion::Pair as Completion;
 use rustyline::error::ReadlineError;
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::{commands_registry};
+use crate::{commands_registry, project};
 use lazy_static::lazy_static;
 use regex::Regex;
 use rustyline::highlight::CmdKind;
@@ -26,7 +26,7 @@ lazy_static! {
 
         rl.set_helper(Some(ColoredPrompt::default()));
         rl.set_auto_add_history(true);
-        if rl.load_history("history.txt").is_err() {
+        if rl.load_history(&project::current_scope().history_path()).is_err() {
             println!("No previous history.");
         }
         Mutex::new(rl)
@@ -405,8 +405,12 @@ pub fn autocomplete_model_id(
 pub fn save_history() {
     let mut editor_guard = RL_EDITOR.lock().unwrap(); // unwrap() panics if Mutex is poisoned
 
+    let history_path = project::current_scope().history_path();
+    if let Some(parent) = history_path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
     editor_guard
-        .save_history("history.txt")
+        .save_history(&history_path)
         .unwrap_or_else(|err| {
             eprintln!("Failed to save history: {}", err);
         });
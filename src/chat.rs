@@ -1,26 +1,23 @@
-use crate::commands_registry::{CommandHandlerResult, CommandType};
-use crate::{autocomplete, commands, commands_registry, configuration, openrouter, terminal};
+use crate::command_output::{CommandOutput, OutputFormat};
+use crate::command_parser::PromptScan;
+use crate::commands_registry::{CommandHandlerResult, CommandNotFound, CommandType};
+use crate::{command_parser, commands_registry, configuration, session, terminal};
 use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use signal_hook::consts::SIGINT;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Style, ThemeSet};
-use syntect::parsing::SyntaxSet;
-use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
 
 // In-memory context
 lazy_static! {
     static ref MEMORY: Mutex<HashMap<String, Prompt>> = Mutex::new(HashMap::new());
-    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PromptType {
     #[default]
     QUESTION,
@@ -29,16 +26,40 @@ pub enum PromptType {
     WORKFLOW,
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Prompt {
     pub id: String,
     pub date: DateTime<Utc>,
     pub value: String,
     pub ptype: PromptType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<PromptStats>,
+}
+
+/// Usage details recorded with a prompt, used by `@session-stats`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// Bytes added to a question by expanding embedded commands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expansion_bytes: Option<usize>,
 }
 
 impl Prompt {
     pub fn new(value: String, ptype: PromptType) -> Self {
+        Self::with_stats(value, ptype, None)
+    }
+
+    pub fn with_stats(value: String, ptype: PromptType, stats: Option<PromptStats>) -> Self {
         let prompt = Prompt {
             id: uuid::Uuid::new_v4()
                 .to_string()
@@ -49,9 +70,13 @@ impl Prompt {
             date: Utc::now(),
             value,
             ptype,
+            stats,
         };
-        let mut memory = get_memory().lock().unwrap();
-        memory.insert(prompt.id.clone(), prompt.clone());
+        {
+            let mut memory = get_memory().lock().unwrap();
+            memory.insert(prompt.id.clone(), prompt.clone());
+        }
+        session::journal_prompt(&prompt);
         prompt
     }
 }
@@ -66,16 +91,16 @@ pub struct Command {
     pub parameter: String,
 }
 
-pub fn execute_command(
+pub async fn execute_command(
     command: &str,
 ) -> Result<Option<CommandHandlerResult>, Box<dyn std::error::Error>> {
     // First try to execute with the command registry
-    let registry_result = commands_registry::execute_command(command);
+    let registry_result = commands_registry::execute_command(command).await;
     if registry_result.is_ok()
         || registry_result
             .as_ref()
             .err()
-            .map_or(false, |e| e.to_string() != "Command not found")
+            .is_some_and(|e| !e.is::<CommandNotFound>())
     {
         return registry_result;
     }
@@ -87,127 +112,177 @@ pub fn execute_command(
     Ok(None)
 }
 
-pub fn highlight_code(code: &str) -> String {
-    let ss = &*SYNTAX_SET;
-    let ts = &*THEME_SET;
-
-    let ps = SyntaxSet::load_defaults_newlines();
-    let mut highlighted_code = String::new();
-    let syntax = ss
-        .find_syntax_by_extension("py")
-        .unwrap_or_else(|| ss.find_syntax_plain_text());
-
-    let mut h = HighlightLines::new(syntax, &ts.themes["InspiredGitHub"]);
-
-    const CSI: &str = "\x1B[";
-    const RESET_ALL: &str = "\x1B[0m";
-    const FG_BLACK: &str = "30";
-    const BG_WHITE: &str = "47"; // Or "47" for standard white
-    const ERASE_LINE: &str = "2K"; // Erases the entire line, cursor does not move (typically stays at column 1)
-
-    for line in LinesWithEndings::from(code) {
-        // LinesWithEndings enables use of newlines mode
-        let ranges: Vec<(Style, &str)> = h.highlight_line(line, &ps).unwrap();
-        let highlighted_line = as_24_bit_terminal_escaped(&ranges[..], true);
-        let highlighted_line_wh_bg = terminal::white_bg(highlighted_line);
-        highlighted_code.push_str(&format!(
-            "{}{};{}m{}{}{}{}",
-            CSI,      // Start sequence
-            BG_WHITE, // Set background to white
-            FG_BLACK, // Set foreground to black (separated by ';')
-            CSI,
-            ERASE_LINE,             // Erase the line (fills with current background)
-            highlighted_line_wh_bg, // Your actual text
-            RESET_ALL
-        ));
+/// A prompt with its embedded commands run.
+pub struct Expansion {
+    /// The text for the LLM, with command output rendered as clean text
+    pub prompt: String,
+    /// The same text with command output rendered for the screen
+    pub display: String,
+    /// Set when a command's output is only to be shown, not sent
+    pub offline: bool,
+    /// The prompt split into the text as typed and the command expansions in it
+    pub parts: Vec<PromptPart>,
+    /// Text that looked like a command but was not run
+    pub warnings: Vec<String>,
+    /// Commands that failed; their text is kept in the prompt
+    pub errors: Vec<String>,
+}
+
+/// A piece of an expanded prompt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptPart {
+    Text(String),
+    /// An embedded command and the output that replaced it
+    Command { command: String, output: String },
+}
+
+impl Expansion {
+    /// The prompt with the output of the commands at `dropped` left out. Indices count
+    /// command parts only.
+    pub fn prompt_without(&self, dropped: &[usize]) -> String {
+        let mut prompt = String::with_capacity(self.prompt.len());
+        let mut index = 0;
+        for part in &self.parts {
+            match part {
+                PromptPart::Text(text) => prompt.push_str(text),
+                PromptPart::Command { output, .. } => {
+                    if !dropped.contains(&index) {
+                        prompt.push_str(output);
+                    }
+                    index += 1;
+                }
+            }
+        }
+        prompt
     }
+}
+
+/// A rough token count: about four characters per token for English text and code.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
 
-    highlighted_code
+/// Output of the commands of a question that already ran, by where they start in it. Kept
+/// while the question waits for a confirmation, so running it again does not repeat them.
+#[derive(Default)]
+pub struct CompletedCommands {
+    question: String,
+    outputs: HashMap<usize, (CommandType, CommandOutput)>,
 }
 
-pub fn check_embedded_commands(input: &str) -> (String, bool) {
-    // Check for embedded commands
-    let mut enriched_input = input.to_string();
+pub async fn check_embedded_commands(input: &str) -> Expansion {
+    check_embedded_commands_with(input, &mut CompletedCommands::default()).await
+}
+
+/// `check_embedded_commands`, reusing the output of commands in `completed` and adding
+/// the ones that run now.
+pub async fn check_embedded_commands_with(input: &str, completed: &mut CompletedCommands) -> Expansion {
+    if completed.question != input {
+        *completed = CompletedCommands {
+            question: input.to_string(),
+            outputs: HashMap::new(),
+        };
+    }
+    // A question starting with `>` and the command is one shell command; otherwise every
+    // call of a known @name(...) / !name(...) command and pipeline of them in the text is run
+    let mut warnings = Vec::new();
+    let mut errors = Vec::new();
+    let mut parse_errors = Vec::new();
+    let scan = match command_parser::shell_command(input) {
+        Some(_) => PromptScan {
+            pipelines: vec![command_parser::parse_pipeline(input)],
+            ..Default::default()
+        },
+        None => command_parser::scan_prompt(input, |sigil, name| commands_registry::find_command(sigil, name).is_some()),
+    };
+    for span in &scan.unknown {
+        let token = &input[span.clone()];
+        warnings.push(format!("{} is not a command and is sent as text (write \\{} to mark it as text)", token, token));
+    }
+
+    let display_format = configuration::get_effective_config()
+        .map(|config| OutputFormat::for_display(&config.ui))
+        .unwrap_or(OutputFormat::Tui);
+    let mut enriched_input = String::with_capacity(input.len());
+    let mut display = String::with_capacity(input.len());
+    let mut parts = Vec::new();
     let mut pos = 0;
     let mut offline = false;
-    while pos < enriched_input.len() {
-        if enriched_input[pos..].starts_with("@") {
-            // Find the end of the command (space, newline, or end of string)
-            let end = enriched_input[pos..]
-                .find(|c: char| c == ' ' || c == '\n')
-                .map(|x| x + pos)
-                .unwrap_or(enriched_input.len());
-
-            // Extract the full command including parameters
-            // Need to find the closing parenthesis for commands with the new format
-            let command_start = pos;
-            let command_text = &enriched_input[pos..];
-
-            // Find the end of the command - either at the next newline or after the closing parenthesis
-            let command_end = if command_text.contains('(') {
-                let paren_pos = command_text.find('(').unwrap_or(0) + pos;
-                let remaining = &enriched_input[paren_pos..];
-                let closing_paren = remaining.find(')').map(|x| x + paren_pos + 1);
-
-                match closing_paren {
-                    Some(end) => end,
-                    None => enriched_input[pos..]
-                        .find('\n')
-                        .map(|x| x + pos)
-                        .unwrap_or(enriched_input.len()),
+    for pipeline in &scan.pipelines {
+        let span = match pipeline {
+            Ok(pipeline) => pipeline.span.clone(),
+            Err(e) => {
+                parse_errors.push(e.render(input));
+                continue;
+            }
+        };
+        let text = scan.literal(input, pos..span.start);
+        enriched_input.push_str(&text);
+        display.push_str(&text);
+        parts.push(PromptPart::Text(text));
+        pos = span.end;
+        let start = span.start;
+        let command = &input[span];
+
+        let result = match completed.outputs.get(&start) {
+            Some((command_type, output)) => Ok(Some((command_type.clone(), Ok(Some(output.clone()))))),
+            None => commands_registry::execute_command(command)
+                .await
+                .map(|result| result.map(|result| (result.command.command_type, result.command_output))),
+        };
+        let error = match result {
+            Ok(Some((command_type, command_output))) => {
+                // Inject the output into the prompt
+                if command_type == CommandType::Terminal || command_type == CommandType::NotLLM {
+                    offline = true;
                 }
-            } else {
-                enriched_input[pos..]
-                    .find('\n')
-                    .map(|x| x + pos)
-                    .unwrap_or(enriched_input.len())
-            };
-
-            let command = &enriched_input[command_start..command_end];
-
-            //println!("Executing command: {}", command);
-
-            match execute_command(command) {
-                Ok(Some(output)) => {
-                    // Inject the output into the prompt
-                    if output.command.command_type == CommandType::Terminal
-                        || output.command.command_type == CommandType::NotLLM
-                    {
-                        offline = true;
-                    }
-                    match output.command_output {
-                        Ok(Some(s)) => {
-                            let formated_output = &format!("{}", s);
-                            enriched_input.replace_range(pos..end, formated_output);
-                            pos += formated_output.len();
-                        }
-                        Ok(None) => {
-                            println!(
-                                "Calling command succeeded, but no returned value was present."
-                            );
-                            pos = end;
-                        }
-                        Err(e) => {
-                            println!("An error occurred while calling command: {}", e);
-                            pos = end;
-                        }
+                match command_output {
+                    Ok(Some(output)) => {
+                        let rendered = output.render(OutputFormat::Llm);
+                        enriched_input.push_str(&rendered);
+                        display.push_str(&output.render(display_format));
+                        parts.push(PromptPart::Command {
+                            command: command.to_string(),
+                            output: rendered,
+                        });
+                        completed.outputs.insert(start, (command_type, output));
+                        continue;
                     }
-                }
-                Ok(None) => {
-                    pos = end;
-                }
-                Err(e) => {
-                    println!(
-                        "{}",
-                        terminal::format_error(&format!("Error executing command: {}", e))
-                    );
-                    pos = end;
+                    Ok(None) => None,
+                    Err(e) => Some(e.to_string()),
                 }
             }
-        } else {
-            pos += 1;
+            Ok(None) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        // The prompt keeps the command as typed; the screen shows why it did not run
+        enriched_input.push_str(command);
+        match error {
+            Some(error) => {
+                display.push_str(&CommandOutput::Error(error.clone()).render(display_format));
+                errors.push(error);
+            }
+            None => display.push_str(command),
         }
+        parts.push(PromptPart::Text(command.to_string()));
+    }
+    let text = scan.literal(input, pos..input.len());
+    enriched_input.push_str(&text);
+    display.push_str(&text);
+    parts.push(PromptPart::Text(text));
+    // Calls that could not be parsed have no place in the text; they are listed after it
+    for error in parse_errors {
+        display.push_str("\n\n");
+        display.push_str(&CommandOutput::Error(error.clone()).render(display_format));
+        errors.push(error);
+    }
+    Expansion {
+        prompt: enriched_input,
+        display,
+        offline,
+        parts,
+        warnings,
+        errors,
     }
-    (enriched_input, offline)
 }
 
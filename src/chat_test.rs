@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use crate::chat::{CompletedCommands, PromptPart, check_embedded_commands, check_embedded_commands_with, estimate_tokens};
+    use crate::commands_registry::*;
+    use crate::permissions::{self, Effect};
+    use crate::prompt_preview::{PromptPreview, PromptPreviewState};
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_expansion_parts_can_be_dropped() {
+        register_command(Command::new(
+            "test-expand-echo".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Echo the argument".to_string(),
+            "@test-expand-echo(text)".to_string(),
+            sync_handler(|params| Ok(Some(format!("<{}>", params[0])))),
+            "test".to_string(),
+        ));
+
+        let expansion = check_embedded_commands("Compare @test-expand-echo(a) with @test-expand-echo(bb).").await;
+        assert_eq!(expansion.prompt, "Compare <a> with <bb>.");
+        assert_eq!(
+            expansion.parts[1],
+            PromptPart::Command {
+                command: "@test-expand-echo(a)".to_string(),
+                output: "<a>".to_string(),
+            }
+        );
+        assert_eq!(expansion.prompt_without(&[]), expansion.prompt);
+        assert_eq!(expansion.prompt_without(&[0]), "Compare  with <bb>.");
+        assert_eq!(expansion.prompt_without(&[0, 1]), "Compare  with .");
+
+        // The hooks get the prompt without the dropped expansions; going back from their
+        // result keeps the expansions as they were
+        let mut preview = PromptPreview::new(check_embedded_commands("Compare @test-expand-echo(a).").await, None);
+        preview.handle_key(KeyEvent::from(KeyCode::Char(' ')));
+        assert_eq!(preview.prompt(), "Compare .");
+        preview.show_hooked("Compare . Be brief.".to_string(), vec![]);
+        assert_eq!(preview.handle_key(KeyEvent::from(KeyCode::Char(' '))), PromptPreviewState::Open);
+        assert_eq!(preview.hooked_prompt(), Some("Compare . Be brief."));
+        assert_eq!(preview.handle_key(KeyEvent::from(KeyCode::Esc)), PromptPreviewState::Open);
+        assert_eq!(preview.hooked_prompt(), None);
+        preview.handle_key(KeyEvent::from(KeyCode::Char(' ')));
+        assert_eq!(preview.prompt(), "Compare <a>.");
+
+        // Escaped, unknown and fenced calls are text
+        let expansion = check_embedded_commands("\\@test-expand-echo(a) @not-a-command(b)\n```\n@test-expand-echo(c)\n```").await;
+        assert_eq!(expansion.prompt, "@test-expand-echo(a) @not-a-command(b)\n```\n@test-expand-echo(c)\n```");
+        assert_eq!(expansion.warnings.len(), 1);
+
+        // Only `>` directly followed by a command is a shell line
+        let expansion = check_embedded_commands("> quoted from an e-mail").await;
+        assert_eq!(expansion.prompt, "> quoted from an e-mail");
+        assert!(expansion.warnings.is_empty() && expansion.errors.is_empty());
+
+        // A failing call is reported, not printed, and stays in the prompt as typed
+        let expansion = check_embedded_commands("Compare @test-expand-echo(a, b).").await;
+        assert_eq!(expansion.prompt, "Compare @test-expand-echo(a, b).");
+        assert_eq!(expansion.errors.len(), 1);
+        assert!(expansion.display.contains("Error: "));
+
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_questions_run_each_command_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        for name in ["test-confirm-first", "test-confirm-second"] {
+            let counter = calls.clone();
+            register_command(Command {
+                effects: vec![Effect::WriteFs],
+                ..Command::new(
+                    name.to_string(),
+                    Sigil::At,
+                    vec![],
+                    "Count calls".to_string(),
+                    format!("@{}()", name),
+                    closure_handler(move |_, _| {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        Ok(Some("done".to_string()))
+                    }),
+                    "test".to_string(),
+                )
+            });
+        }
+
+        // Answer every confirmation with "allow for this question" like the chat UI does
+        let question = "@test-confirm-first() and @test-confirm-second()";
+        let mut completed = CompletedCommands::default();
+        let mut expansion = check_embedded_commands_with(question, &mut completed).await;
+        for _ in 0..4 {
+            match permissions::take_confirmation_request() {
+                Some(request) => permissions::grant(&request, false),
+                None => break,
+            }
+            expansion = check_embedded_commands_with(question, &mut completed).await;
+        }
+        permissions::forget_question_grants();
+        assert_eq!(expansion.prompt, "done and done");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -12,15 +12,22 @@ use ratatui::{
 use ratatui::widgets::Clear;
 use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind};
 use tui_textarea::{ TextArea };
-use crate::{autocomplete, commands, commands_registry, commands_selector, configuration, openrouter, terminal};
+use crate::{apply, autocomplete, commands, commands_registry, commands_selector, configuration, openrouter, permissions, project, scripting, session, session_stats, terminal};
 use commands_selector::CommandSelector;
-use crate::chat::{check_embedded_commands, highlight_code, Prompt, PromptType};
+use crate::apply_preview::{ApplyPreview, ApplyPreviewState};
+use crate::chat::{check_embedded_commands_with, CompletedCommands, Expansion, Prompt, PromptStats, PromptType};
 use crate::commands_selector::CommandSelectorState;
+use crate::confirm_prompt::{ConfirmPrompt, ConfirmPromptState};
 use crate::files_selector::{FileSelector, FileSelectorState};
+use crate::passphrase_prompt::{PassphrasePrompt, PassphrasePromptState};
+use crate::prompt_preview::{PromptPreview, PromptPreviewState};
+use crate::stats_panel::StatsPanel;
+use crate::session::{PassphraseAction, PassphraseRequest};
 use std::time::Duration;
 use ratatui::crossterm::terminal::{disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::layout::{Position, Rect};
 use tokio::sync::oneshot;
+use crate::openrouter::LlmResponse;
 
 pub enum FocusedInputArea {
     Question,
@@ -37,6 +44,10 @@ pub fn main_ui() -> Result<()> {
         "{}",
         terminal::format_info(&format!("Loaded config: {:?}", config))
     );
+    println!(
+        "{}",
+        terminal::format_info(&format!("Scope: {}", project::current_scope().label()))
+    );
 
     let terminal = ratatui::init();
     let app_result = ChatUIApp::new().run(terminal);
@@ -45,6 +56,18 @@ pub fn main_ui() -> Result<()> {
     app_result
 }
 
+/// Shown under the question box so it is always clear where sessions and history go.
+/// Let the `pre_send` hooks of scripts rewrite `prompt`. Returns the prompt to send and
+/// the failures of the hooks.
+fn run_pre_send(prompt: String) -> (String, Vec<String>) {
+    let prompt = tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(scripting::pre_send(prompt)));
+    (prompt, scripting::take_hook_errors())
+}
+
+fn scope_title() -> String {
+    format!(" scope: {} ", project::current_scope().label())
+}
+
 struct ChatUIApp<'a> {
     show_commands_popup: bool,
     show_files_popup: bool,
@@ -56,8 +79,18 @@ struct ChatUIApp<'a> {
     answer_text_rect: Rect,
     question_prompt: Prompt,
     answer_prompt: Prompt,
-    llm_rx: Option<oneshot::Receiver<String>>, // Add this field
+    // Hook failures of the last answer, shown below it
+    answer_warnings: Vec<String>,
+    llm_rx: Option<oneshot::Receiver<LlmResponse>>, // Add this field
     current_focus_area: FocusedInputArea,
+    passphrase_prompt: Option<(PassphraseRequest, PassphrasePrompt<'a>)>,
+    // With whether the question was being previewed
+    confirm_prompt: Option<(ConfirmPrompt, bool)>,
+    // Commands of the question that ran before it stopped for a confirmation
+    completed_commands: CompletedCommands,
+    prompt_preview: Option<PromptPreview>,
+    apply_preview: Option<ApplyPreview>,
+    stats_panel: Option<StatsPanel>,
 }
 
 impl ChatUIApp<'_> {
@@ -73,8 +106,15 @@ impl ChatUIApp<'_> {
             answer_text_rect: Rect::default(),
             question_prompt: Prompt::default(),
             answer_prompt: Prompt::default(),
+            answer_warnings: Vec::new(),
             llm_rx: None,
             current_focus_area: FocusedInputArea::Question,
+            passphrase_prompt: None,
+            confirm_prompt: None,
+            completed_commands: CompletedCommands::default(),
+            prompt_preview: None,
+            apply_preview: None,
+            stats_panel: None,
         }
     }
 }
@@ -108,7 +148,8 @@ impl ChatUIApp<'_> {
         self.question_text_widget.set_block(
             Block::default()
                 .borders(Borders::ALL)
-                .title("YOU:"),
+                .title("YOU:")
+                .title_bottom(scope_title()),
         );
         
 
@@ -142,11 +183,26 @@ impl ChatUIApp<'_> {
                 if self.show_files_popup {
                     self.file_sel.render_files_popup(frame)
                 }
+                if let Some((_, prompt)) = &self.passphrase_prompt {
+                    prompt.render_passphrase_popup(frame);
+                }
+                if let Some(preview) = &mut self.prompt_preview {
+                    preview.render_preview_popup(frame);
+                }
+                if let Some(preview) = &mut self.apply_preview {
+                    preview.render_apply_popup(frame);
+                }
+                if let Some((prompt, _)) = &self.confirm_prompt {
+                    prompt.render_confirm_popup(frame);
+                }
+                if let Some(panel) = &self.stats_panel {
+                    panel.render_stats_popup(frame);
+                }
             })?;
 
             if self.answer_text_widget.lines().len() > 0 {
 
-                let wrapped_str = textwrap::wrap(self.answer_prompt.value.as_str(), self.answer_text_rect.width as usize).join("\n");
+                let wrapped_str = textwrap::wrap(self.answer_text().as_str(), self.answer_text_rect.width as usize).join("\n");
                 //let highlighted_response = highlight_code(ans_prompt.value.as_str());
                 self.answer_text_widget = TextArea::default();
                 self.answer_text_widget.set_block(
@@ -162,8 +218,9 @@ impl ChatUIApp<'_> {
             // Check for LLM response non-blockingly
             if let Some(rx) = self.llm_rx.as_mut() { // Borrow mutably to call try_recv
                 match rx.try_recv() {
-                    Ok(response_text) => {
-                        self.answer_prompt = Prompt::new(response_text, PromptType::ANSWER);
+                    Ok(response) => {
+                        self.answer_prompt = Prompt::with_stats(response.content, PromptType::ANSWER, Some(response.stats));
+                        self.answer_warnings = scripting::take_hook_errors();
 
                         self.answer_text_widget = TextArea::default();
                         self.answer_text_widget.set_block(
@@ -172,7 +229,7 @@ impl ChatUIApp<'_> {
                                 .title(format!("LLM: [ID:{}]",self.answer_prompt.id))
                         );
 
-                        let wrapped_str = textwrap::wrap(self.answer_prompt.value.as_str(), self.answer_text_rect.width as usize).join("\n");
+                        let wrapped_str = textwrap::wrap(self.answer_text().as_str(), self.answer_text_rect.width as usize).join("\n");
                         //let highlighted_response = highlight_code(ans_prompt.value.as_str());
                         self.answer_text_widget.insert_str(wrapped_str);
 
@@ -196,6 +253,95 @@ impl ChatUIApp<'_> {
             // allowing the llm_rx check above to run.
             if ratatui::crossterm::event::poll(Duration::from_millis(100))? {
                 match ratatui::crossterm::event::read()? {
+                    Event::Key(key) if self.stats_panel.as_mut().is_some_and(|panel| panel.handle_key(key)) => {
+                        self.stats_panel = None;
+                    }
+                    Event::Key(_) if self.stats_panel.is_some() => {}
+                    Event::Key(key) if self.prompt_preview.is_some() => {
+                        match self.prompt_preview.as_mut().unwrap().handle_key(key) {
+                            PromptPreviewState::Send => {
+                                let preview = self.prompt_preview.as_mut().unwrap();
+                                // Hooks run once the expansions to send are chosen; when they change
+                                // the prompt or fail, the result is shown before it is sent
+                                let prompt = match preview.hooked_prompt() {
+                                    Some(hooked) => Some(hooked.to_string()),
+                                    None => {
+                                        let prompt = preview.prompt();
+                                        let (hooked, errors) = run_pre_send(prompt.clone());
+                                        if hooked == prompt && errors.is_empty() {
+                                            Some(hooked)
+                                        } else {
+                                            preview.show_hooked(hooked, errors);
+                                            None
+                                        }
+                                    }
+                                };
+                                if let Some(prompt) = prompt {
+                                    self.prompt_preview = None;
+                                    if self.llm_rx.is_none() {
+                                        self.send_prompt(prompt);
+                                    }
+                                }
+                            }
+                            PromptPreviewState::Cancel => {
+                                self.prompt_preview = None;
+                            }
+                            PromptPreviewState::Open => {}
+                        }
+                    }
+                    Event::Key(key) if self.apply_preview.is_some() => {
+                        match self.apply_preview.as_mut().unwrap().handle_key(key) {
+                            ApplyPreviewState::Apply => {
+                                let preview = self.apply_preview.take().unwrap();
+                                self.show_local_output(&preview.apply());
+                            }
+                            ApplyPreviewState::Cancel => {
+                                self.apply_preview = None;
+                                self.show_local_output("No changes were written");
+                            }
+                            ApplyPreviewState::Open => {}
+                        }
+                    }
+                    Event::Key(key) if self.confirm_prompt.is_some() => {
+                        let state = self.confirm_prompt.as_mut().unwrap().0.handle_key(key);
+                        match state {
+                            ConfirmPromptState::Allow | ConfirmPromptState::AllowAlways => {
+                                let (prompt, preview) = self.confirm_prompt.take().unwrap();
+                                permissions::grant(&prompt.request, state == ConfirmPromptState::AllowAlways);
+                                // Run the question again, now with the command allowed
+                                if preview {
+                                    self.preview_llm_command();
+                                } else {
+                                    self.execute_llm_command();
+                                }
+                            }
+                            ConfirmPromptState::Deny => {
+                                let (prompt, _) = self.confirm_prompt.take().unwrap();
+                                self.finish_question();
+                                self.show_local_output(&format!("Not run: {}", prompt.request.action));
+                            }
+                            ConfirmPromptState::Open => {}
+                        }
+                    }
+                    Event::Key(key) if self.passphrase_prompt.is_some() => {
+                        // The passphrase popup takes every key while it is open
+                        let (passphrase, state) = self.passphrase_prompt.as_mut().unwrap().1.handle_key(key);
+                        match state {
+                            PassphrasePromptState::Entered => {
+                                let (request, _) = self.passphrase_prompt.take().unwrap();
+                                let result = match session::submit_passphrase(&request, &passphrase.unwrap_or_default()) {
+                                    Ok(message) => message,
+                                    Err(e) => format!("Error: {}", e),
+                                };
+                                self.show_local_output(&result);
+                            }
+                            PassphrasePromptState::Exit => {
+                                self.passphrase_prompt = None;
+                                self.show_local_output("Passphrase entry cancelled");
+                            }
+                            PassphrasePromptState::Editing => {}
+                        }
+                    }
                     Event::Key(key) => {
                             match key.code {
                                 KeyCode::Char('?') => {
@@ -229,6 +375,11 @@ impl ChatUIApp<'_> {
                                         }
                                     }
                                 }
+                                KeyCode::F(2) => {
+                                    if key.kind == KeyEventKind::Press && self.llm_rx.is_none() {
+                                        self.preview_llm_command();
+                                    }
+                                }
                                 _ => {
                                     if self.show_commands_popup {
                                         let (command, state) = self.cmd_sel.handle_key(key);
@@ -287,28 +438,125 @@ impl ChatUIApp<'_> {
 
     }
 
+    /// The answer as shown, followed by the hook failures it had.
+    fn answer_text(&self) -> String {
+        let mut text = self.answer_prompt.value.clone();
+        for warning in &self.answer_warnings {
+            text.push_str(&format!("\n\n{}", warning));
+        }
+        text
+    }
+
+    fn show_local_output(&mut self, text: &str) {
+        self.answer_text_widget = TextArea::default();
+        self.answer_text_widget.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("LLM: [LOCAL]")
+        );
+
+        self.answer_text_widget.insert_str(text);
+    }
+
     fn execute_llm_command(&mut self) {
+        if let Some(mut expansion) = self.expand_question(false) {
+            let (prompt, errors) = run_pre_send(expansion.prompt);
+            expansion.warnings.extend(errors);
+            // Shown until the answer arrives; the preview lists them as well
+            if !expansion.warnings.is_empty() {
+                self.show_local_output(&expansion.warnings.join("\n"));
+            }
+            self.send_prompt(prompt);
+        }
+    }
+
+    /// Expand the question and show it in the preview instead of sending it.
+    fn preview_llm_command(&mut self) {
+        if let Some(expansion) = self.expand_question(true) {
+            let context_length = configuration::get_effective_config()
+                .ok()
+                .and_then(|config| commands::set_model::context_length(&config.llm.model));
+            self.prompt_preview = Some(PromptPreview::new(expansion, context_length));
+        }
+    }
+
+    /// Run the commands embedded in the question. Returns `None` when there is nothing to
+    /// send: the output was only shown, or a dialog was opened. `preview` is where to go
+    /// back to once a confirmation dialog is answered.
+    fn expand_question(&mut self, preview: bool) -> Option<Expansion> {
         let content: Vec<String> = self.question_text_widget.lines().to_vec();
         let content = content.join(&"\n");
 
-        let (enriched_input, _offline) = check_embedded_commands(content.as_str());
-        if _offline {
-            self.answer_text_widget = TextArea::default();
-            self.answer_text_widget.set_block(
-                Block::default()
-                    .borders(Borders::ALL)
-                    .title("LLM: [LOCAL]")
-            );
-
-            self.answer_text_widget.insert_str(enriched_input.as_str());
-            return;
+        // Drop requests and plans left behind by commands run outside of a question, e.g. by a workflow
+        permissions::take_confirmation_request();
+        apply::take_pending_plan();
+        // The UI loop is synchronous; run the (possibly async) commands on the runtime it lives in
+        let completed = &mut self.completed_commands;
+        let mut expansion = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(check_embedded_commands_with(content.as_str(), completed))
+        });
+        // Hooks of workflow steps run by the commands
+        expansion.warnings.extend(scripting::take_hook_errors());
+        // A command the permission policy holds back is asked about before anything is sent;
+        // the commands that already ran are not run again afterwards
+        if let Some(request) = permissions::take_confirmation_request() {
+            self.show_local_output(expansion.display.as_str());
+            self.confirm_prompt = Some((ConfirmPrompt::new(request), preview));
+            return None;
+        }
+        self.finish_question();
+        // A failed command is shown where it was typed, and the question is not sent
+        if !expansion.errors.is_empty() {
+            self.show_local_output(expansion.display.as_str());
+            return None;
+        }
+        if expansion.offline {
+            let mut display = expansion.display.clone();
+            for warning in &expansion.warnings {
+                display.push_str(&format!("\n{}", warning));
+            }
+            self.show_local_output(&display);
+
+            // A session command may need a passphrase before it can finish
+            if let Some(request) = session::take_passphrase_request() {
+                let prompt = match request.action {
+                    PassphraseAction::Unlock => PassphrasePrompt::new(&format!("Passphrase for '{}'", request.session), false),
+                    PassphraseAction::Encrypt => PassphrasePrompt::new(&format!("New passphrase for '{}'", request.session), true),
+                };
+                self.passphrase_prompt = Some((request, prompt));
+            }
+            if let Some(stats) = session_stats::take_panel_stats() {
+                self.stats_panel = Some(StatsPanel::new(stats));
+            }
+            if let Some(plan) = apply::take_pending_plan() {
+                self.apply_preview = Some(ApplyPreview::new(plan));
+            }
+            return None;
         }
-        self.question_prompt = Prompt::new(enriched_input.clone(), PromptType::QUESTION);
+        Some(expansion)
+    }
+
+    /// Forget what was allowed and run for the question so far.
+    fn finish_question(&mut self) {
+        self.completed_commands = CompletedCommands::default();
+        permissions::forget_question_grants();
+    }
+
+    fn send_prompt(&mut self, enriched_input: String) {
+        let content = self.question_text_widget.lines().join("\n");
+        // Remember how much context the embedded commands added, for @session-stats
+        let expansion_bytes = enriched_input.len().saturating_sub(content.len());
+        let stats = (expansion_bytes > 0).then(|| PromptStats {
+            expansion_bytes: Some(expansion_bytes),
+            ..Default::default()
+        });
+        self.question_prompt = Prompt::with_stats(enriched_input.clone(), PromptType::QUESTION, stats);
 
         self.question_text_widget.set_block(
             Block::default()
                 .borders(Borders::ALL)
                 .title(format!("YOU: [ID:{}]",self.question_prompt.id))
+                .title_bottom(scope_title())
         );
 
 
@@ -317,18 +565,19 @@ impl ChatUIApp<'_> {
 
         // This tokio::spawn will use the existing runtime (e.g., from #[tokio::main])
         tokio::spawn(async move {
-            match openrouter::call_openrouter_api(&enriched_input).await {
-                Ok(response_text) => {
-                    if tx.send(response_text).is_err() {
-                        // Receiver was dropped, maybe UI closed or another command started
-                        eprintln!("LLM task: Receiver for response was dropped.");
-                    }
-                },
+            let mut response = match openrouter::call_openrouter_api_with_usage(&enriched_input).await {
+                Ok(response) => response,
                 Err(e) => {
                     let error_msg = format!("Error calling OpenRouter API: {}", e);
                     eprintln!("{}", error_msg); // Log to console
                     eprintln!("LLM task: Receiver for error response was dropped.");
+                    return;
                 }
+            };
+            response.content = scripting::post_answer(enriched_input, response.content).await;
+            if tx.send(response).is_err() {
+                // Receiver was dropped, maybe UI closed or another command started
+                eprintln!("LLM task: Receiver for response was dropped.");
             }
         });
 
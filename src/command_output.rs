@@ -0,0 +1,273 @@
+//! Structured command results and their renderings.
+//!
+//! Commands return a `CommandOutput` instead of preformatted text. Where the output goes
+//! decides how it is rendered: the TUI gets aligned, readable text, prompts get clean text
+//! without terminal escapes, and the JSON output mode gets the structure itself.
+use crate::configuration::UiSettings;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+
+lazy_static! {
+    static ref ANSI_ESCAPE: Regex = Regex::new(r"\x1B\[[0-9;?]*[ -/]*[@-~]").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum CommandOutput {
+    Text(String),
+    Table {
+        headers: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    FileList(Vec<String>),
+    /// Files that were read, and the ones left out with the reason
+    FileContents {
+        files: Vec<FileContent>,
+        skipped: Vec<SkippedFile>,
+    },
+    Json(serde_json::Value),
+    /// A failure the command reports as its result, e.g. an unknown memory id
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileContent {
+    pub path: String,
+    pub content: String,
+    /// Set when `content` is part of the file starting at this line; it is then shown with
+    /// line numbers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_line: Option<usize>,
+}
+
+impl FileContent {
+    pub fn new(path: String, content: String) -> Self {
+        Self { path, content, first_line: None }
+    }
+
+    /// The path, with the line range for part of a file: `src/main.rs:10-20`.
+    pub fn label(&self) -> String {
+        match self.first_line {
+            Some(first) => {
+                let last = first + self.content.trim_end_matches('\n').lines().count().max(1) - 1;
+                format!("{}:{}-{}", self.path, first, last)
+            }
+            None => self.path.clone(),
+        }
+    }
+
+    /// The content without terminal escapes, each line numbered for part of a file.
+    pub fn text(&self) -> String {
+        let content = strip_ansi(&self.content);
+        let Some(first) = self.first_line else {
+            return content;
+        };
+        let lines: Vec<&str> = content.trim_end_matches('\n').lines().collect();
+        let width = (first + lines.len()).to_string().len();
+        lines
+            .iter()
+            .enumerate()
+            .map(|(index, line)| format!("{:>width$} | {}", first + index, line, width = width).trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+/// Where command output is shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Tui,
+    Llm,
+    Json,
+}
+
+impl OutputFormat {
+    /// How local output is shown: `response_format = "json"` selects the JSON output mode.
+    pub fn for_display(ui: &UiSettings) -> Self {
+        if ui.response_format.eq_ignore_ascii_case("json") {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Tui
+        }
+    }
+}
+
+impl From<String> for CommandOutput {
+    fn from(text: String) -> Self {
+        CommandOutput::Text(text)
+    }
+}
+
+impl From<&str> for CommandOutput {
+    fn from(text: &str) -> Self {
+        CommandOutput::Text(text.to_string())
+    }
+}
+
+impl CommandOutput {
+    pub fn table(headers: &[&str], rows: Vec<Vec<String>>) -> Self {
+        CommandOutput::Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows,
+        }
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Tui => self.render_tui(),
+            OutputFormat::Llm => self.render_llm(),
+            OutputFormat::Json => self.render_json(),
+        }
+    }
+
+    /// Text for the answer area of the TUI.
+    pub fn render_tui(&self) -> String {
+        match self {
+            CommandOutput::Text(text) => strip_ansi(text),
+            CommandOutput::Table { headers, rows } => {
+                let widths = column_widths(headers, rows);
+                let mut lines = vec![pad_row(headers, &widths)];
+                lines.push(widths.iter().map(|width| "─".repeat(*width)).collect::<Vec<_>>().join("  "));
+                lines.extend(rows.iter().map(|row| pad_row(row, &widths)));
+                lines.join("\n")
+            }
+            CommandOutput::FileList(paths) if paths.is_empty() => "No files found".to_string(),
+            CommandOutput::FileList(paths) => paths.join("\n"),
+            CommandOutput::FileContents { files, skipped } => {
+                let mut sections: Vec<String> = files
+                    .iter()
+                    .map(|file| format!("── {} ──\n{}", file.label(), file.text()))
+                    .collect();
+                sections.extend(skipped_summary(skipped));
+                sections.join("\n\n")
+            }
+            CommandOutput::Json(value) => serde_json::to_string_pretty(value).unwrap_or_default(),
+            CommandOutput::Error(message) => format!("Error: {}", strip_ansi(message)),
+        }
+    }
+
+    /// Text to put into a prompt: no terminal escapes, file contents fenced.
+    pub fn render_llm(&self) -> String {
+        match self {
+            CommandOutput::Text(text) => strip_ansi(text),
+            CommandOutput::Table { headers, rows } => {
+                let mut lines = vec![markdown_row(headers)];
+                lines.push(markdown_row(&vec!["---".to_string(); headers.len()]));
+                lines.extend(rows.iter().map(|row| markdown_row(row)));
+                lines.join("\n")
+            }
+            CommandOutput::FileList(paths) => paths.join("\n"),
+            CommandOutput::FileContents { files, skipped } => {
+                let mut sections: Vec<String> = files
+                    .iter()
+                    .map(|file| {
+                        let content = file.text();
+                        let fence = fence_for(&content);
+                        let language = file.path.rsplit_once('.').map_or("", |(_, extension)| extension);
+                        format!("File: {}\n{}{}\n{}\n{}", file.label(), fence, language, content.trim_end_matches('\n'), fence)
+                    })
+                    .collect();
+                sections.extend(skipped_summary(skipped));
+                sections.join("\n\n")
+            }
+            CommandOutput::Json(value) => serde_json::to_string_pretty(value).unwrap_or_default(),
+            CommandOutput::Error(message) => format!("Error: {}", strip_ansi(message)),
+        }
+    }
+
+    /// The output with its structure, for the JSON output mode.
+    pub fn render_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// What pipeline filters work on: the paths of a file list, the rows of a table and the
+    /// lines of anything else.
+    pub fn items(&self) -> Vec<String> {
+        match self {
+            CommandOutput::FileList(paths) => paths.clone(),
+            CommandOutput::Table { rows, .. } => rows.iter().map(|row| row.join(" | ")).collect(),
+            other => other
+                .render_llm()
+                .trim_matches(['\n', '\r'])
+                .lines()
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// The output reduced to the items at `indices`, in that order. File lists and tables
+    /// stay what they are; other output becomes text.
+    pub fn select(&self, indices: &[usize]) -> CommandOutput {
+        match self {
+            CommandOutput::FileList(paths) => CommandOutput::FileList(indices.iter().map(|&i| paths[i].clone()).collect()),
+            CommandOutput::Table { headers, rows } => CommandOutput::Table {
+                headers: headers.clone(),
+                rows: indices.iter().map(|&i| rows[i].clone()).collect(),
+            },
+            other => {
+                let items = other.items();
+                CommandOutput::Text(indices.iter().map(|&i| items[i].as_str()).collect::<Vec<_>>().join("\n"))
+            }
+        }
+    }
+}
+
+/// Remove terminal color and cursor escapes.
+pub fn strip_ansi(text: &str) -> String {
+    ANSI_ESCAPE.replace_all(text, "").into_owned()
+}
+
+/// One line per file that was not read, after a count.
+fn skipped_summary(skipped: &[SkippedFile]) -> Option<String> {
+    if skipped.is_empty() {
+        return None;
+    }
+    let mut lines = vec![format!("Skipped {} file{}:", skipped.len(), if skipped.len() == 1 { "" } else { "s" })];
+    lines.extend(skipped.iter().map(|file| format!("- {}: {}", file.path, file.reason)));
+    Some(lines.join("\n"))
+}
+
+fn column_widths(headers: &[String], rows: &[Vec<String>]) -> Vec<usize> {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(index) {
+                Some(current) => *current = (*current).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    widths
+}
+
+fn pad_row(cells: &[String], widths: &[usize]) -> String {
+    let padded: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect();
+    padded.join("  ").trim_end().to_string()
+}
+
+fn markdown_row(cells: &[String]) -> String {
+    let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|").replace('\n', " ")).collect();
+    format!("| {} |", cells.join(" | "))
+}
+
+/// A code fence longer than any run of backticks in `content`.
+fn fence_for(content: &str) -> String {
+    let longest = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use crate::command_output::*;
+    use serde_json::json;
+
+    fn files() -> CommandOutput {
+        CommandOutput::FileContents {
+            files: vec![
+                FileContent::new("src/main.rs".to_string(), "fn main() {}\n".to_string()),
+                FileContent::new("README.md".to_string(), "Use ```code``` blocks".to_string()),
+            ],
+            skipped: vec![],
+        }
+    }
+
+    #[test]
+    fn test_llm_rendering_is_clean() {
+        let text = CommandOutput::from("\x1B[48;5;240m\x1B[2Ktotal 8\x1B[0m");
+        assert_eq!(text.render_llm(), "total 8");
+
+        let table = CommandOutput::table(&["Setting", "Value"], vec![
+            vec!["llm.model".to_string(), "a|b".to_string()],
+        ]);
+        assert_eq!(table.render_llm(), "| Setting | Value |\n| --- | --- |\n| llm.model | a\\|b |");
+
+        assert_eq!(
+            files().render_llm(),
+            "File: src/main.rs\n```rs\nfn main() {}\n```\n\nFile: README.md\n````md\nUse ```code``` blocks\n````"
+        );
+        assert_eq!(CommandOutput::Error("not found".to_string()).render(OutputFormat::Llm), "Error: not found");
+    }
+
+    #[test]
+    fn test_tui_and_json_rendering() {
+        let table = CommandOutput::table(&["Plan", "Status"], vec![
+            vec!["a1".to_string(), "Running".to_string()],
+            vec!["b22222".to_string(), "Done".to_string()],
+        ]);
+        assert_eq!(table.render_tui(), "Plan    Status\n──────  ───────\na1      Running\nb22222  Done");
+        assert_eq!(CommandOutput::FileList(vec![]).render_tui(), "No files found");
+        assert!(files().render_tui().starts_with("── src/main.rs ──\nfn main() {}"));
+
+        let list = CommandOutput::FileList(vec!["a.rs".to_string()]);
+        let value: serde_json::Value = serde_json::from_str(&list.render(OutputFormat::Json)).unwrap();
+        assert_eq!(value, json!({"type": "file_list", "value": ["a.rs"]}));
+        let output = CommandOutput::Json(json!({"ok": true}));
+        assert_eq!(output.render_llm(), "{\n  \"ok\": true\n}");
+    }
+
+    #[test]
+    fn test_items_and_select() {
+        let list = CommandOutput::FileList(vec!["a.rs".to_string(), "b.rs".to_string(), "c.rs".to_string()]);
+        assert_eq!(list.items(), vec!["a.rs", "b.rs", "c.rs"]);
+        assert_eq!(list.select(&[2, 0]), CommandOutput::FileList(vec!["c.rs".to_string(), "a.rs".to_string()]));
+
+        let table = CommandOutput::table(&["Key", "Value"], vec![vec!["k".to_string(), "v".to_string()]]);
+        assert_eq!(table.items(), vec!["k | v"]);
+        assert_eq!(table.select(&[]), CommandOutput::table(&["Key", "Value"], vec![]));
+
+        let text = CommandOutput::from("\none\ntwo\n\n");
+        assert_eq!(text.items(), vec!["one", "two"]);
+        assert_eq!(text.select(&[1]), CommandOutput::from("two"));
+    }
+}
@@ -0,0 +1,688 @@
+//! Parser for the command language:
+//!
+//! ```text
+//! @name(arg, "quoted, with \"escapes\"", key=value, @nested(call))
+//! !name(args)
+//! >shell command line
+//! @list-files(src/**/*.rs) | @filter(test) | @read-files
+//! ```
+//!
+//! Arguments are separated by commas. An unquoted argument runs to the next top-level `,`
+//! or `)`; brackets inside it must balance, so JSON and `f(x)` style text need no quoting.
+//! A backslash makes the next character literal in both quoted and unquoted arguments.
+//! Calls joined with `|` form a pipeline; after the first stage the `()` may be left out.
+//!
+//! In free text, `\@` and `\!` stand for the sigil itself, as does `\>` at the start of a line,
+//! and fenced code blocks are literal.
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sigil {
+    At,
+    Bang,
+    Shell,
+}
+
+impl Sigil {
+    pub fn as_char(self) -> char {
+        match self {
+            Sigil::At => '@',
+            Sigil::Bang => '!',
+            Sigil::Shell => '>',
+        }
+    }
+
+    fn from_call_char(c: char) -> Option<Sigil> {
+        match c {
+            '@' => Some(Sigil::At),
+            '!' => Some(Sigil::Bang),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed command call. Spans are byte ranges into the parsed input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub sigil: Sigil,
+    pub name: String,
+    pub args: Vec<Argument>,
+    pub span: Range<usize>,
+}
+
+/// Calls joined with `|`. Each stage reads the output of the stage before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub stages: Vec<Invocation>,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    /// Set for `key=value` arguments
+    pub name: Option<String>,
+    pub value: ArgValue,
+    /// Source of the value, without the `key=` part
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Text(String),
+    Call(Invocation),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// The error with the offending line of `input` and a marker under the span.
+    pub fn render(&self, input: &str) -> String {
+        let start = self.span.start.min(input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+        let line = &input[line_start..line_end];
+
+        let column = input[line_start..start].chars().count();
+        let end = self.span.end.clamp(start, line_end);
+        let width = input[start..end].chars().count().max(1);
+        format!(
+            "{}\n  {}\n  {}{}",
+            self,
+            line,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The command of a shell line, written as `>` directly followed by the command, e.g.
+/// `>git status`. A `>` followed by a space is a quote, like a line pasted from an e-mail.
+pub fn shell_command(line: &str) -> Option<&str> {
+    line.trim_start()
+        .strip_prefix('>')
+        .filter(|command| command.starts_with(|c: char| !c.is_whitespace()))
+}
+
+/// Parse input that is a single command: `@name(...)`, `!name(...)` or `>shell command`.
+/// Surrounding whitespace is allowed, anything else is an error.
+pub fn parse_invocation(input: &str) -> Result<Invocation, ParseError> {
+    let start = input.len() - input.trim_start().len();
+    let end = input.trim_end().len();
+    if start >= end {
+        return Err(ParseError::new("empty command", 0..input.len()));
+    }
+
+    if input[start..].starts_with('>') {
+        if shell_command(input).is_none() {
+            return Err(ParseError::new("the shell command has to follow `>` directly, e.g. `>ls`", start..end));
+        }
+        let command_start = start + 1;
+        return Ok(Invocation {
+            sigil: Sigil::Shell,
+            name: String::new(),
+            args: vec![Argument {
+                name: None,
+                value: ArgValue::Text(input[command_start..end].to_string()),
+                span: command_start..end,
+            }],
+            span: start..end,
+        });
+    }
+
+    let mut parser = Parser { input, pos: start };
+    let invocation = parser.parse_call(false)?;
+    parser.expect_end(end)?;
+    Ok(invocation)
+}
+
+/// Parse input that is a single command or a pipeline of `@`/`!` calls. A shell line is
+/// always one stage: its `|` belong to the shell.
+pub fn parse_pipeline(input: &str) -> Result<Pipeline, ParseError> {
+    let start = input.len() - input.trim_start().len();
+    let end = input.trim_end().len();
+    if input[start..end].starts_with('>') {
+        let invocation = parse_invocation(input)?;
+        return Ok(Pipeline {
+            span: invocation.span.clone(),
+            stages: vec![invocation],
+        });
+    }
+
+    let mut parser = Parser { input, pos: start };
+    let pipeline = parser.parse_pipeline()?;
+    parser.expect_end(end)?;
+    Ok(pipeline)
+}
+
+/// What `scan_prompt` found in free text.
+#[derive(Debug, Default, PartialEq)]
+pub struct PromptScan {
+    pub pipelines: Vec<Result<Pipeline, ParseError>>,
+    /// Spans of `@name` and `!name` followed by `(` where the name is not a command, like a
+    /// pasted Python decorator
+    pub unknown: Vec<Range<usize>>,
+    /// Positions of backslashes that escape a sigil, or a `>` at the start of a line; they
+    /// are not part of the text
+    pub escapes: Vec<usize>,
+}
+
+impl PromptScan {
+    /// `input[range]` with the escaping backslashes removed.
+    pub fn literal(&self, input: &str, range: Range<usize>) -> String {
+        let mut text = String::with_capacity(range.len());
+        let mut pos = range.start;
+        for &escape in self.escapes.iter().filter(|&&escape| range.contains(&escape)) {
+            text.push_str(&input[pos..escape]);
+            pos = escape + 1;
+        }
+        text.push_str(&input[pos..range.end]);
+        text
+    }
+}
+
+/// Find the calls and pipelines embedded in free text, in order. A sigil only starts a call
+/// when it is not preceded by a letter or digit (so e-mail addresses are left alone), is
+/// directly followed by a name and `(`, and `is_command` knows the name. A `|` after a call
+/// only continues it when a call follows on the same line. Fenced code blocks are skipped
+/// and a backslash before a sigil, or before a `>` that starts a line, escapes it.
+pub fn scan_prompt(input: &str, is_command: impl Fn(Sigil, &str) -> bool) -> PromptScan {
+    let mut scan = PromptScan::default();
+    let fences = fenced_blocks(input);
+    let mut pos = 0;
+
+    while let Some(offset) = input[pos..].find(['@', '!', '\\']) {
+        let start = pos + offset;
+        if let Some(fence) = fences.iter().find(|fence| fence.contains(&start)) {
+            pos = fence.end;
+            continue;
+        }
+        if input[start..].starts_with('\\') {
+            let at_line_start = input[..start].rsplit('\n').next().unwrap_or_default().trim().is_empty();
+            if input[start + 1..].starts_with(['@', '!']) || (at_line_start && input[start + 1..].starts_with('>')) {
+                scan.escapes.push(start);
+                pos = start + 2;
+            } else {
+                pos = start + 1;
+            }
+            continue;
+        }
+        let preceded_by_word = input[..start].chars().next_back().is_some_and(|c| c.is_alphanumeric());
+        if preceded_by_word || !starts_call(&input[start..]) {
+            pos = start + 1;
+            continue;
+        }
+        let sigil = if input[start..].starts_with('@') { Sigil::At } else { Sigil::Bang };
+        let name_end = start + 1 + input[start + 1..].find(|c: char| !is_name_char(c)).unwrap_or(input.len() - start - 1);
+        if !is_command(sigil, &input[start + 1..name_end]) {
+            scan.unknown.push(start..name_end);
+            pos = name_end;
+            continue;
+        }
+
+        let mut parser = Parser { input, pos: start };
+        match parser.parse_pipeline() {
+            Ok(pipeline) => {
+                pos = pipeline.span.end;
+                scan.pipelines.push(Ok(pipeline));
+            }
+            Err(error) => {
+                pos = start + 1;
+                scan.pipelines.push(Err(error));
+            }
+        }
+    }
+    scan
+}
+
+/// The spans of the fenced code blocks in `input`: from a line opening with three or more
+/// backticks or tildes to the line closing it with at least as many, or to the end.
+fn fenced_blocks(input: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(usize, char, usize)> = None;
+    let mut line_start = 0;
+
+    for line in input.split_inclusive('\n') {
+        let trimmed = line.trim_start_matches(' ');
+        let fence_char = trimmed.chars().next().filter(|&c| c == '`' || c == '~');
+        let fence_len = fence_char.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+        let line_end = line_start + line.len();
+
+        match (open, fence_char) {
+            (None, Some(c)) if fence_len >= 3 && line.len() - trimmed.len() <= 3 => open = Some((line_start, c, fence_len)),
+            (Some((start, c, len)), Some(close)) if close == c && fence_len >= len && trimmed[fence_len..].trim().is_empty() => {
+                blocks.push(start..line_end);
+                open = None;
+            }
+            _ => {}
+        }
+        line_start = line_end;
+    }
+    if let Some((start, _, _)) = open {
+        blocks.push(start..input.len());
+    }
+    blocks
+}
+
+/// Whether `text` begins with a sigil, a command name and `(`.
+fn starts_call(text: &str) -> bool {
+    let mut chars = text.chars();
+    if chars.next().and_then(Sigil::from_call_char).is_none() {
+        return false;
+    }
+    let rest = chars.as_str();
+    let name_len = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+    name_len > 0 && rest[name_len..].starts_with('(')
+}
+
+/// Whether `text` begins with a sigil and a command name, as a stage after `|` does.
+fn starts_stage(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().and_then(Sigil::from_call_char).is_some() && chars.next().is_some_and(is_name_char)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.')
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Skip spaces and tabs, but not line breaks.
+    fn skip_blanks(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.bump();
+        }
+    }
+
+    fn expect_end(&mut self, end: usize) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(ParseError::new("unexpected text after command", self.pos..end));
+        }
+        Ok(())
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let first = self.parse_call(false)?;
+        let start = first.span.start;
+        let mut stages = vec![first];
+
+        loop {
+            let stage_end = self.pos;
+            self.skip_blanks();
+            if self.peek() != Some('|') {
+                self.pos = stage_end;
+                break;
+            }
+            self.bump();
+            self.skip_blanks();
+            if !starts_stage(&self.input[self.pos..]) {
+                // A `|` in the text around the call, like `a | b` or `||`
+                self.pos = stage_end;
+                break;
+            }
+            stages.push(self.parse_call(true)?);
+        }
+
+        Ok(Pipeline {
+            stages,
+            span: start..self.pos,
+        })
+    }
+
+    /// A call `@name(...)`. With `bare_allowed`, as for later pipeline stages, a name
+    /// without `(` is a call without arguments.
+    fn parse_call(&mut self, bare_allowed: bool) -> Result<Invocation, ParseError> {
+        let start = self.pos;
+        let sigil = match self.bump().and_then(Sigil::from_call_char) {
+            Some(sigil) => sigil,
+            None => return Err(ParseError::new("expected '@' or '!'", start..self.pos)),
+        };
+
+        let name_start = self.pos;
+        while self.peek().is_some_and(is_name_char) {
+            self.bump();
+        }
+        let mut name = self.input[name_start..self.pos].to_string();
+        if name.is_empty() {
+            return Err(ParseError::new("expected a command name", start..self.pos));
+        }
+        if bare_allowed && self.peek() != Some('(') {
+            // Punctuation ending a sentence is not part of the name
+            let trimmed = name.trim_end_matches(['.', ':']).len();
+            self.pos -= name.len() - trimmed;
+            name.truncate(trimmed);
+            if name.is_empty() {
+                return Err(ParseError::new("expected a command name", start..self.pos));
+            }
+            return Ok(Invocation {
+                sigil,
+                name,
+                args: Vec::new(),
+                span: start..self.pos,
+            });
+        }
+        if self.peek() != Some('(') {
+            return Err(ParseError::new(
+                format!("expected '(' after {}{}", sigil.as_char(), name),
+                start..self.pos,
+            ));
+        }
+        self.bump();
+
+        let mut args = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(')') {
+            self.bump();
+        } else {
+            loop {
+                args.push(self.parse_argument(start)?);
+                self.skip_whitespace();
+                match self.bump() {
+                    Some(',') => continue,
+                    Some(')') => break,
+                    Some(_) => {
+                        let at = self.pos - 1;
+                        return Err(ParseError::new("expected ',' or ')'", at..self.pos));
+                    }
+                    None => return Err(unclosed(sigil, &name, start, self.pos)),
+                }
+            }
+        }
+
+        Ok(Invocation {
+            sigil,
+            name,
+            args,
+            span: start..self.pos,
+        })
+    }
+
+    fn parse_argument(&mut self, call_start: usize) -> Result<Argument, ParseError> {
+        self.skip_whitespace();
+        let name = self.parse_argument_name();
+        self.skip_whitespace();
+
+        let start = self.pos;
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => ArgValue::Text(self.parse_quoted(quote)?),
+            Some(_) if starts_call(&self.input[self.pos..]) => ArgValue::Call(self.parse_call(false)?),
+            _ => ArgValue::Text(self.parse_bare(call_start)?),
+        };
+        let end = self.pos;
+
+        // Only a separator may follow a quoted string or a nested call
+        if !matches!(value, ArgValue::Text(_)) || self.input[start..end].starts_with(['"', '\'']) {
+            self.skip_whitespace();
+            if !matches!(self.peek(), Some(',') | Some(')') | None) {
+                return Err(ParseError::new("expected ',' or ')' after argument", self.pos..self.pos + 1));
+            }
+        }
+
+        Ok(Argument {
+            name,
+            value,
+            span: start..end,
+        })
+    }
+
+    /// `key=` before a value; the parser is left after the `=`.
+    fn parse_argument_name(&mut self) -> Option<String> {
+        let rest = &self.input[self.pos..];
+        let mut chars = rest.char_indices();
+        match chars.next() {
+            Some((_, c)) if c.is_alphabetic() || c == '_' => {}
+            _ => return None,
+        }
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(rest.len());
+        let after = rest[name_len..].trim_start();
+        if after.starts_with('=') && !after.starts_with("==") {
+            let name = rest[..name_len].to_string();
+            self.pos += rest.len() - after.len() + 1;
+            Some(name)
+        } else {
+            None
+        }
+    }
+
+    fn parse_quoted(&mut self, quote: char) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => return Ok(value),
+                Some('\\') => value.push(self.parse_escape()?),
+                Some(c) => value.push(c),
+                None => return Err(ParseError::new("unterminated string", start..self.pos)),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, ParseError> {
+        let start = self.pos - 1;
+        match self.bump() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some(c) if !c.is_alphanumeric() => Ok(c),
+            Some(c) => Err(ParseError::new(format!("unknown escape '\\{}'", c), start..self.pos)),
+            None => Err(ParseError::new("unfinished escape", start..self.pos)),
+        }
+    }
+
+    /// Unquoted text up to the next top-level `,` or `)`, with surrounding whitespace removed.
+    fn parse_bare(&mut self, call_start: usize) -> Result<String, ParseError> {
+        let mut value = String::new();
+        let mut closers: Vec<char> = Vec::new();
+        let mut quote: Option<char> = None;
+
+        loop {
+            let Some(c) = self.peek() else {
+                let message = match closers.last() {
+                    Some(closer) => format!("missing '{}'", closer),
+                    None => "missing ')'".to_string(),
+                };
+                return Err(ParseError::new(message, call_start..self.pos));
+            };
+
+            if let Some(q) = quote {
+                // Quotes inside unquoted text (e.g. JSON) are kept as they are
+                self.bump();
+                value.push(c);
+                if c == '\\' {
+                    if let Some(escaped) = self.bump() {
+                        value.push(escaped);
+                    }
+                } else if c == q {
+                    quote = None;
+                }
+                continue;
+            }
+
+            match c {
+                ',' | ')' if closers.is_empty() => break,
+                '\\' => {
+                    self.bump();
+                    value.push(self.parse_escape()?);
+                    continue;
+                }
+                '(' => closers.push(')'),
+                '[' => closers.push(']'),
+                '{' => closers.push('}'),
+                ')' | ']' | '}' => {
+                    if closers.last() != Some(&c) {
+                        return Err(ParseError::new(format!("unbalanced '{}'", c), self.pos..self.pos + 1));
+                    }
+                    closers.pop();
+                }
+                '"' | '\'' if !value.is_empty() || !closers.is_empty() => quote = Some(c),
+                _ => {}
+            }
+            self.bump();
+            value.push(c);
+        }
+
+        Ok(value.trim().to_string())
+    }
+}
+
+fn unclosed(sigil: Sigil, name: &str, start: usize, end: usize) -> ParseError {
+    ParseError::new(format!("missing ')' for {}{}", sigil.as_char(), name), start..end)
+}
+
+/// One parameter a command accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgSpec {
+    pub name: String,
+    pub required: bool,
+    /// Takes the source text of all remaining positional arguments, commas included
+    pub rest: bool,
+    /// A file system path, matched by the `path` of permission rules
+    pub path: bool,
+}
+
+impl ArgSpec {
+    pub fn required(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            required: true,
+            rest: false,
+            path: false,
+        }
+    }
+
+    pub fn optional(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            required: false,
+            rest: false,
+            path: false,
+        }
+    }
+
+    /// Make this (last) parameter take the rest of the argument list.
+    pub fn rest(mut self) -> Self {
+        self.rest = true;
+        self
+    }
+
+    /// Mark this parameter as a path the command reads or writes.
+    pub fn path(mut self) -> Self {
+        self.path = true;
+        self
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name.replace('_', "-") == name.replace('_', "-")
+    }
+}
+
+/// Match the arguments of `invocation` against `specs`, giving the parameters in spec order.
+/// `values` are the evaluated argument values (nested calls already replaced by their
+/// output), `input` is the text the invocation was parsed from. Trailing optional parameters
+/// that were not given are left out; one skipped before a given parameter is passed as "".
+pub fn bind_arguments(
+    specs: &[ArgSpec],
+    invocation: &Invocation,
+    values: Vec<String>,
+    input: &str,
+) -> Result<Vec<String>, ParseError> {
+    let mut slots: Vec<Option<String>> = vec![None; specs.len()];
+    let mut positional = Vec::new();
+
+    for (argument, value) in invocation.args.iter().zip(values) {
+        let Some(name) = &argument.name else {
+            positional.push((argument, value));
+            continue;
+        };
+        let index = specs.iter().position(|spec| spec.matches(name)).ok_or_else(|| {
+            ParseError::new(format!("unknown argument '{}'", name), argument.span.clone())
+        })?;
+        if slots[index].is_some() {
+            return Err(ParseError::new(
+                format!("argument '{}' given more than once", specs[index].name),
+                argument.span.clone(),
+            ));
+        }
+        slots[index] = Some(value);
+    }
+
+    let mut positional = positional.into_iter().peekable();
+    let mut index = 0;
+    while let Some((argument, value)) = positional.next() {
+        while index < specs.len() && slots[index].is_some() {
+            index += 1;
+        }
+        if index == specs.len() {
+            return Err(ParseError::new(
+                format!("too many arguments for {}{}", invocation.sigil.as_char(), invocation.name),
+                argument.span.clone(),
+            ));
+        }
+
+        slots[index] = Some(if specs[index].rest && positional.peek().is_some() {
+            let end = positional.by_ref().last().map_or(argument.span.end, |(last, _)| last.span.end);
+            input[argument.span.start..end].to_string()
+        } else {
+            value
+        });
+        index += 1;
+    }
+
+    if let Some(missing) = specs.iter().zip(&slots).find(|(spec, slot)| spec.required && slot.is_none()) {
+        return Err(ParseError::new(
+            format!(
+                "missing argument '{}' for {}{}",
+                missing.0.name,
+                invocation.sigil.as_char(),
+                invocation.name
+            ),
+            invocation.span.clone(),
+        ));
+    }
+
+    let given = slots.iter().rposition(Option::is_some).map_or(0, |last| last + 1);
+    Ok(slots.into_iter().take(given).map(Option::unwrap_or_default).collect())
+}
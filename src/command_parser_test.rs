@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use crate::command_parser::*;
+
+    fn text(argument: &Argument) -> &str {
+        match &argument.value {
+            ArgValue::Text(text) => text,
+            ArgValue::Call(_) => panic!("expected a text argument"),
+        }
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        let input = r#"@read-files( src/*.rs , "a, \"quoted\"\n", alias = 'x\'y', {"k": [1, 2]}, f(g(x)) )"#;
+        let invocation = parse_invocation(input).unwrap();
+        assert_eq!(invocation.sigil, Sigil::At);
+        assert_eq!(invocation.name, "read-files");
+        assert_eq!(invocation.span, 0..input.len());
+
+        let args = &invocation.args;
+        assert_eq!(args.len(), 5);
+        assert_eq!(text(&args[0]), "src/*.rs");
+        assert_eq!(text(&args[1]), "a, \"quoted\"\n");
+        assert_eq!(args[2].name.as_deref(), Some("alias"));
+        assert_eq!(text(&args[2]), "x'y");
+        assert_eq!(text(&args[3]), r#"{"k": [1, 2]}"#);
+        assert_eq!(text(&args[4]), "f(g(x))");
+
+        assert!(parse_invocation("!config-get()").unwrap().args.is_empty());
+
+        let shell = parse_invocation("  >ls -la | wc -l ").unwrap();
+        assert_eq!(shell.sigil, Sigil::Shell);
+        assert_eq!(text(&shell.args[0]), "ls -la | wc -l");
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let input = "@export(@get-memory(id=last), out\\,file.md)";
+        let invocation = parse_invocation(input).unwrap();
+        let ArgValue::Call(nested) = &invocation.args[0].value else {
+            panic!("expected a nested call");
+        };
+        assert_eq!(nested.name, "get-memory");
+        assert_eq!(&input[nested.span.clone()], "@get-memory(id=last)");
+        assert_eq!(nested.args[0].name.as_deref(), Some("id"));
+        assert_eq!(text(&invocation.args[1]), "out,file.md");
+    }
+
+    #[test]
+    fn test_errors_have_spans() {
+        let error = parse_invocation("@read-file(src/main.rs").unwrap_err();
+        assert_eq!(error.message, "missing ')'");
+        assert_eq!(error.span, 0..22);
+
+        let input = r#"@help("topic" extra)"#;
+        let error = parse_invocation(input).unwrap_err();
+        assert_eq!(&input[error.span.clone()], "e");
+        assert_eq!(error.render(input).lines().nth(2), Some("                ^"));
+
+        let error = parse_invocation(r#"@help("open)"#).unwrap_err();
+        assert_eq!(error.message, "unterminated string");
+        assert_eq!(error.span.start, 6);
+
+        let error = parse_invocation(r"@help(\q)").unwrap_err();
+        assert_eq!(error.span, 6..8);
+
+        assert!(parse_invocation("@help() trailing").is_err());
+        assert!(parse_invocation("@help(a]").is_err());
+    }
+
+    /// The calls in `input`, taking any name for a command.
+    fn find_pipelines(input: &str) -> Vec<Result<Pipeline, ParseError>> {
+        scan_prompt(input, |_, _| true).pipelines
+    }
+
+    #[test]
+    fn test_find_pipelines() {
+        let input = "Mail me@example.com, then see @read-file(a.rs) and !set-model(x) @nope";
+        let found: Vec<_> = find_pipelines(input).into_iter().map(Result::unwrap).collect();
+        assert_eq!(found.len(), 2);
+        assert_eq!(&input[found[0].span.clone()], "@read-file(a.rs)");
+        assert_eq!(&input[found[1].span.clone()], "!set-model(x)");
+
+        let found = find_pipelines("@read-file(unclosed and @help()");
+        assert!(found[0].is_err());
+        assert!(found[1].is_ok());
+
+        // A `|` only continues a call when another call follows on the same line
+        let input = "Check @list-files(src/*.rs) | @filter(test) | @read-files. Then @help() | grep\n| @help()";
+        let found: Vec<_> = find_pipelines(input).into_iter().map(Result::unwrap).collect();
+        assert_eq!(found.len(), 3);
+        assert_eq!(&input[found[0].span.clone()], "@list-files(src/*.rs) | @filter(test) | @read-files");
+        let names: Vec<&str> = found[0].stages.iter().map(|stage| stage.name.as_str()).collect();
+        assert_eq!(names, vec!["list-files", "filter", "read-files"]);
+        assert!(found[0].stages[2].args.is_empty());
+        assert_eq!(found[1].stages.len(), 1);
+        assert_eq!(found[2].stages.len(), 1);
+    }
+
+    #[test]
+    fn test_scan_prompt() {
+        let is_command = |_: Sigil, name: &str| name == "read-file";
+        let input = "Use \\@read-file(x) for @pytest.fixture() and @read-file(a.rs).\n```py\n@read-file(b.rs)\n```\n@read-file(c.rs)";
+        let scan = scan_prompt(input, is_command);
+
+        let found: Vec<&str> = scan.pipelines.iter().map(|p| &input[p.as_ref().unwrap().span.clone()]).collect();
+        assert_eq!(found, vec!["@read-file(a.rs)", "@read-file(c.rs)"]);
+        let unknown: Vec<&str> = scan.unknown.iter().map(|span| &input[span.clone()]).collect();
+        assert_eq!(unknown, vec!["@pytest.fixture"]);
+        assert_eq!(scan.literal(input, 0..20), "Use @read-file(x) f");
+
+        // An unclosed fence runs to the end; a shorter fence does not close a longer one
+        let scan = scan_prompt("````\n@read-file(a)\n```\n@read-file(b)", is_command);
+        assert!(scan.pipelines.is_empty());
+
+        // `\>` is an escape at the start of any line, elsewhere it is text
+        let input = "a \\> b\n  \\>ls";
+        let scan = scan_prompt(input, is_command);
+        assert_eq!(scan.literal(input, 0..input.len()), "a \\> b\n  >ls");
+    }
+
+    #[test]
+    fn test_parse_pipeline() {
+        let pipeline = parse_pipeline(" @list-files(*.rs) |@head(2)| @sort ").unwrap();
+        assert_eq!(pipeline.stages.len(), 3);
+        assert_eq!(pipeline.span, 1..35);
+        assert_eq!(text(&pipeline.stages[1].args[0]), "2");
+
+        // The shell owns the `|` of a shell line
+        let pipeline = parse_pipeline(">ls | wc -l").unwrap();
+        assert_eq!(pipeline.stages.len(), 1);
+        assert_eq!(text(&pipeline.stages[0].args[0]), "ls | wc -l");
+        assert_eq!(shell_command("  >ls -l"), Some("ls -l"));
+        assert_eq!(shell_command("> ls"), None);
+        assert!(parse_pipeline("> ls").is_err());
+        assert!(parse_invocation(">").is_err());
+
+        // The first stage needs its parentheses
+        assert!(parse_pipeline("@list-files | @head").is_err());
+        assert!(parse_pipeline("@list-files(*.rs) | ").is_err());
+    }
+
+    #[test]
+    fn test_bind_arguments() {
+        let specs = vec![
+            ArgSpec::required("name"),
+            ArgSpec::optional("new-name"),
+            ArgSpec::optional("value").rest(),
+        ];
+        let bind = |input: &str| {
+            let invocation = parse_invocation(input).unwrap();
+            let values = invocation.args.iter().map(|a| text(a).to_string()).collect();
+            bind_arguments(&specs, &invocation, values, input)
+        };
+
+        assert_eq!(bind("@x(a)").unwrap(), vec!["a"]);
+        assert_eq!(bind("@x(a, value=v)").unwrap(), vec!["a", "", "v"]);
+        assert_eq!(bind("@x(new_name=b, a)").unwrap(), vec!["a", "b"]);
+        assert_eq!(bind("@x(a, b, c, d)").unwrap(), vec!["a", "b", "c, d"]);
+
+        let error = bind("@x()").unwrap_err();
+        assert_eq!(error.message, "missing argument 'name' for @x");
+        let error = bind("@x(a, colour=red)").unwrap_err();
+        assert_eq!(error.message, "unknown argument 'colour'");
+        assert_eq!(error.span, 13..16);
+        assert!(bind("@x(name=a, name=b)").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::apply;
+use crate::autocomplete::{autocomplete_empty, autocomplete_memory_id};
+use crate::chat::PromptType;
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, closure_handler, register_command, sync_handler};
+use crate::permissions::{self, Decision, Effect};
+
+pub fn register_apply_commands() {
+    register_command(Command {
+        name: "apply".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("answer-id")],
+        description: "Review and apply the file changes in an answer (the last one by default)".to_string(),
+        usage_example: "@apply([answer-id])".to_string(),
+        handler: closure_handler(|context, params| {
+            let answer = {
+                let memory = context.memory().lock().unwrap();
+                match params.first().filter(|id| !id.is_empty()) {
+                    Some(id) => memory.get(id).filter(|prompt| prompt.ptype == PromptType::ANSWER).cloned(),
+                    None => memory.values().filter(|prompt| prompt.ptype == PromptType::ANSWER).max_by_key(|prompt| prompt.date).cloned(),
+                }
+            };
+            let Some(answer) = answer else {
+                return Ok(Some(CommandOutput::Error(format!("no answer {}", params.first().map_or("", String::as_str)))));
+            };
+
+            let edits = apply::parse_edits(&answer.value);
+            if edits.is_empty() {
+                return Ok(Some(CommandOutput::Error(format!("answer {} has no file changes", answer.id))));
+            }
+            let mut plan = apply::plan(&answer.id, &edits);
+
+            // The preview asks before anything is written, but denied files are not offered
+            let settings = &context.config()?.permissions;
+            let mut allowed = Vec::with_capacity(plan.changes.len());
+            for change in plan.changes.drain(..) {
+                match permissions::decide(settings, &[Effect::WriteFs], "@apply", std::slice::from_ref(&change.path))? {
+                    Decision::Deny => plan.problems.push(format!("{}: denied by the permission rules", change.path)),
+                    _ => allowed.push(change),
+                }
+            }
+            plan.changes = allowed;
+
+            let mut lines: Vec<String> = plan
+                .changes
+                .iter()
+                .map(|change| format!("{} {}", if change.old.is_some() { "changed" } else { "new    " }, change.path))
+                .collect();
+            lines.extend(plan.problems.iter().map(|problem| format!("not applied: {}", problem)));
+            if plan.changes.is_empty() {
+                return Ok(Some(CommandOutput::Error(format!("nothing to apply from {}\n{}", answer.id, lines.join("\n")))));
+            }
+            apply::show_in_preview(plan);
+            Ok(Some(CommandOutput::Text(lines.join("\n"))))
+        }),
+        section: "file".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_memory_id),
+    });
+
+    register_command(Command {
+        name: "undo-apply".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("force")],
+        description: "Restore the files changed by the last @apply".to_string(),
+        usage_example: "@undo-apply() or @undo-apply(force)".to_string(),
+        handler: sync_handler(|params| {
+            let force = params.first().is_some_and(|param| param == "force");
+            Ok(Some(match apply::undo(&apply::journal_path(), force) {
+                Ok(message) => CommandOutput::Text(message),
+                Err(e) => CommandOutput::Error(e.to_string()),
+            }))
+        }),
+        section: "file".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_empty),
+    });
+}
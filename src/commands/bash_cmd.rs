@@ -1,25 +1,27 @@
 use crate::autocomplete::autocomplete_empty;
-use crate::commands_registry::{Command, CommandType, register_command};
-use regex::Regex;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, register_command, sync_handler};
+use crate::permissions::Effect;
 use std::process::Command as BashCommand;
-use syntect::util::{LinesWithEndings};
 
 pub fn register_bash_command() {
     register_command(Command {
         name: "bash".to_string(),
-        pattern: Regex::new(r"^>\s*(.+)").unwrap(),
+        sigil: Sigil::Shell,
+        args: vec![ArgSpec::required("command").rest()],
         description: "Bash command".to_string(),
         usage_example: ">ls -alt".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.len() < 1 {
                 println!("Usage: > Bash command (eg. ls, cp , rm)");
                 return Ok(None);
             }
             let cmd = &params[0];
             Ok(Some(execute_shell_command(cmd, true)))
-        },
+        }),
         section: "terminal".to_string(),
         command_type: CommandType::Terminal,
+        effects: vec![Effect::Exec],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_empty),
     });
 }
@@ -39,7 +41,7 @@ fn execute_shell_command(command_str: &str, use_bash: bool) -> String {
             if !output.stdout.is_empty() {
                 match String::from_utf8(output.stdout) {
                     Ok(stdout_str) => {
-                        return format!("{}", highlight_bash_output(&stdout_str));
+                        return stdout_str;
                     }
                     Err(e) => {
                         return format!("STDOUT is not valid UTF-8: {}", e);
@@ -48,12 +50,8 @@ fn execute_shell_command(command_str: &str, use_bash: bool) -> String {
             }
             if !output.stderr.is_empty() {
                 match String::from_utf8(output.stderr) {
-                    Ok(stderr_str) => {
-                        return format!("{}", highlight_bash_output(&stderr_str));
-                    }
-                    Err(e) => {
-                        return format!("STDERR is not valid UTF-8: {}", e);
-                    }
+                    Ok(stderr_str) => stderr_str,
+                    Err(e) => format!("STDERR is not valid UTF-8: {}", e),
                 }
             } else {
                 if output.status.success() {
@@ -69,49 +67,3 @@ fn execute_shell_command(command_str: &str, use_bash: bool) -> String {
         }
     }
 }
-
-fn highlight_bash_output(code: &str) -> String {
-    const CSI: &str = "\x1B[";
-    const RESET_ALL: &str = "\x1B[0m";
-
-    // Foreground Color (Standard White)
-    const FG_WHITE_STANDARD: &str = "15";
-    // OR: Foreground Color (8-bit Bright White - often index 15 or 231)
-    // const FG_WHITE_8BIT: &str = "38;5;15"; // Using 38;5;{N} for foreground
-
-    // Background Color (8-bit Grey - 234 is a dark grey)
-    // The full sequence part for 8-bit background is "48;5;{N}"
-    const BG_GREY_8BIT_CODE: &str = "240"; // Just the color code
-    const BG_GREY_SGR_PARAM: &str = "48;5"; // The SGR command for 8-bit background
-
-    // Erase line
-    const ERASE_LINE: &str = "2K";
-
-    // Construct the SGR parameters string for colors
-    // Example: "48;5;240;37" for grey BG and standard white FG
-    let color_params = format!(
-        "{};{};{}", // SGR for BG ; SGR for FG color code
-        BG_GREY_SGR_PARAM,
-        BG_GREY_8BIT_CODE,
-        FG_WHITE_STANDARD // Use standard white
-                          // If you wanted 8-bit white:
-                          // FG_WHITE_8BIT
-    );
-
-    let mut highlighted_code = String::new();
-
-    for line in LinesWithEndings::from(code) {
-        // LinesWithEndings enables use of newlines mode
-        highlighted_code.push_str(&format!(
-            "{}{}{}{}{}{}{}",
-            CSI,          // Start SGR sequence
-            color_params, // e.g., "48;5;234;37"
-            "m",          // End SGR sequence
-            CSI,          // Start Erase Line sequence
-            ERASE_LINE,   // Erase the line (fills with newly set background)
-            line,         // Your actual text
-            RESET_ALL
-        ));
-    }
-    highlighted_code
-}
@@ -1,76 +1,61 @@
-use regex::Regex;
-use crate::commands_registry::{Command, CommandType, register_command};
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, register_command, sync_handler};
 use crate::configuration::{self, Config, LlmSettings};
+use crate::permissions::Effect;
+use crate::session;
 use std::collections::HashMap;
 
 pub fn register_config_commands() {
     // Get current configuration
     register_command(Command {
         name: "config-get".to_string(),
-        pattern: Regex::new(r"!config-get\(\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![],
         description: "Display current configuration".to_string(),
         usage_example: "!config-get()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             match configuration::get_effective_config() {
                 Ok(config) => {
-                    let config_str = format!(
-                        "Current Configuration:\n\
-                         \nLLM Settings:\n\
-                         - Model: {}\n\
-                         - Temperature: {}\n\
-                         - Max Tokens: {}\n\
-                         - Top P: {}\n\
-                         - System Prompt: {}\n\
-                         \nUI Settings:\n\
-                         - Color Scheme: {}\n\
-                         - Show Line Numbers: {}\n\
-                         - Response Format: {}\n\
-                         - Auto Scroll: {}\n\
-                         \nMemory Settings:\n\
-                         - Auto Save Interval (min): {}\n\
-                         - Memory Limit (MB): {}\n\
-                         - Default Export Format: {}\n\
-                         - Auto Export on Exit: {}\n\
-                         \nWorkflow Settings:\n\
-                         - Max Iterations: {}\n\
-                         - Timeout (sec): {}\n\
-                         - Verify Steps: {}\n\
-                         - Parallel Execution: {}",
-                        config.llm.model,
-                        config.llm.temperature,
-                        config.llm.max_tokens,
-                        config.llm.top_p,
-                        config.llm.system_prompt.as_ref().unwrap_or(&"None".to_string()),
-                        config.ui.color_scheme,
-                        config.ui.show_line_numbers,
-                        config.ui.response_format,
-                        config.ui.auto_scroll,
-                        config.memory.auto_save_interval_minutes,
-                        config.memory.memory_limit_mb,
-                        config.memory.default_export_format,
-                        config.memory.auto_export_on_exit,
-                        config.workflow.max_iterations,
-                        config.workflow.timeout_seconds,
-                        config.workflow.verify_steps,
-                        config.workflow.parallel_execution
-                    );
-                    Ok(Some(config_str))
+                    let settings = [
+                        ("llm.model", config.llm.model.clone()),
+                        ("llm.temperature", config.llm.temperature.to_string()),
+                        ("llm.max_tokens", config.llm.max_tokens.to_string()),
+                        ("llm.top_p", config.llm.top_p.to_string()),
+                        ("llm.system_prompt", config.llm.system_prompt.clone().unwrap_or_else(|| "None".to_string())),
+                        ("ui.color_scheme", config.ui.color_scheme.clone()),
+                        ("ui.show_line_numbers", config.ui.show_line_numbers.to_string()),
+                        ("ui.response_format", config.ui.response_format.clone()),
+                        ("ui.auto_scroll", config.ui.auto_scroll.to_string()),
+                        ("memory.auto_save_interval_minutes", config.memory.auto_save_interval_minutes.to_string()),
+                        ("memory.memory_limit_mb", config.memory.memory_limit_mb.to_string()),
+                        ("memory.default_export_format", config.memory.default_export_format.clone()),
+                        ("memory.auto_export_on_exit", config.memory.auto_export_on_exit.to_string()),
+                        ("workflow.max_iterations", config.workflow.max_iterations.to_string()),
+                        ("workflow.timeout_seconds", config.workflow.timeout_seconds.to_string()),
+                        ("workflow.verify_steps", config.workflow.verify_steps.to_string()),
+                        ("workflow.parallel_execution", config.workflow.parallel_execution.to_string()),
+                    ];
+                    let rows = settings.into_iter().map(|(key, value)| vec![key.to_string(), value]).collect();
+                    Ok(Some(CommandOutput::table(&["Setting", "Value"], rows)))
                 }
-                Err(e) => Ok(Some(format!("Error loading configuration: {}", e)))
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Loading configuration failed: {}", e))))
             }
-        },
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Set LLM parameter
     register_command(Command {
         name: "config-set-llm".to_string(),
-        pattern: Regex::new(r"!config-set-llm\(\s*(\S+)\s*,\s*(.+)\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("key"), ArgSpec::required("value").rest()],
         description: "Set LLM parameter (temperature, max_tokens, top_p, system_prompt)".to_string(),
         usage_example: "!config-set-llm(temperature, 0.8)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.len() < 2 {
                 return Ok(Some("Usage: !config-set-llm(parameter, value)".to_string()));
             }
@@ -82,7 +67,7 @@ pub fn register_config_commands() {
                 Ok(mut config) => {
                     match param.as_str() {
                         "temperature" => {
-                            match value.parse::<f32>() {
+                            match value.parse::<f64>() {
                                 Ok(temp) if temp >= 0.0 && temp <= 2.0 => {
                                     config.llm.temperature = temp;
                                 }
@@ -100,7 +85,7 @@ pub fn register_config_commands() {
                             }
                         }
                         "top_p" => {
-                            match value.parse::<f32>() {
+                            match value.parse::<f64>() {
                                 Ok(top_p) if top_p > 0.0 && top_p <= 1.0 => {
                                     config.llm.top_p = top_p;
                                 }
@@ -125,19 +110,22 @@ pub fn register_config_commands() {
                 }
                 Err(e) => Ok(Some(format!("Error loading configuration: {}", e)))
             }
-        },
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Set session override
     register_command(Command {
         name: "config-session".to_string(),
-        pattern: Regex::new(r"!config-session\(\s*(\S+)\s*,\s*(.+)\s*\)").unwrap(),
-        description: "Set temporary session configuration override".to_string(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("key"), ArgSpec::required("value").rest()],
+        description: "Set configuration override for the current session (model, temperature, max_tokens, top_p, system_prompt)".to_string(),
         usage_example: "!config-session(temperature, 1.2)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.len() < 2 {
                 return Ok(Some("Usage: !config-session(parameter, value)".to_string()));
             }
@@ -147,12 +135,14 @@ pub fn register_config_commands() {
             
             // Get current session override or create new one
             let mut override_config = configuration::get_session_config_override()
-                .cloned()
                 .unwrap_or_else(|| Config::default());
             
             match param.as_str() {
+                "model" => {
+                    override_config.llm.model = value.to_string();
+                }
                 "temperature" => {
-                    match value.parse::<f32>() {
+                    match value.parse::<f64>() {
                         Ok(temp) if temp >= 0.0 && temp <= 2.0 => {
                             override_config.llm.temperature = temp;
                         }
@@ -170,7 +160,7 @@ pub fn register_config_commands() {
                     }
                 }
                 "top_p" => {
-                    match value.parse::<f32>() {
+                    match value.parse::<f64>() {
                         Ok(top_p) if top_p > 0.0 && top_p <= 1.0 => {
                             override_config.llm.top_p = top_p;
                         }
@@ -185,39 +175,52 @@ pub fn register_config_commands() {
                         override_config.llm.system_prompt = Some(value.to_string());
                     }
                 }
-                _ => return Ok(Some("Invalid parameter. Use: temperature, max_tokens, top_p, or system_prompt".to_string())),
+                _ => return Ok(Some("Invalid parameter. Use: model, temperature, max_tokens, top_p, or system_prompt".to_string())),
             }
             
             configuration::set_session_config_override(override_config);
-            Ok(Some(format!("Session override for '{}' set to '{}' (temporary)", param, value)))
-        },
+            match session::save_config_overrides() {
+                Ok(Some(session_name)) => Ok(Some(format!("Session override for '{}' set to '{}' (saved to session '{}')", param, value, session_name))),
+                Ok(None) => Ok(Some(format!("Session override for '{}' set to '{}' (temporary)", param, value))),
+                Err(e) => Ok(Some(format!("Session override for '{}' set to '{}', but saving it to the session failed: {}", param, value, e))),
+            }
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Clear session overrides
     register_command(Command {
         name: "config-session-clear".to_string(),
-        pattern: Regex::new(r"!config-session-clear\(\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![],
         description: "Clear all session configuration overrides".to_string(),
         usage_example: "!config-session-clear()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             configuration::clear_session_config_override();
-            Ok(Some("Session configuration overrides cleared".to_string()))
-        },
+            match session::save_config_overrides() {
+                Ok(_) => Ok(Some("Session configuration overrides cleared".to_string())),
+                Err(e) => Ok(Some(format!("Session configuration overrides cleared, but saving the session failed: {}", e))),
+            }
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Create and save model preset
     register_command(Command {
         name: "config-preset-save".to_string(),
-        pattern: Regex::new(r"!config-preset-save\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("name")],
         description: "Save current LLM settings as a preset".to_string(),
         usage_example: "!config-preset-save(creative)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 return Ok(Some("Usage: !config-preset-save(preset_name)".to_string()));
             }
@@ -236,19 +239,22 @@ pub fn register_config_commands() {
                 }
                 Err(e) => Ok(Some(format!("Error loading configuration: {}", e)))
             }
-        },
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Load model preset
     register_command(Command {
         name: "config-preset-load".to_string(),
-        pattern: Regex::new(r"!config-preset-load\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("name")],
         description: "Load a saved model preset".to_string(),
         usage_example: "!config-preset-load(creative)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 return Ok(Some("Usage: !config-preset-load(preset_name)".to_string()));
             }
@@ -270,19 +276,22 @@ pub fn register_config_commands() {
                 }
                 Err(e) => Ok(Some(format!("Error loading configuration: {}", e)))
             }
-        },
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // List presets
     register_command(Command {
         name: "config-preset-list".to_string(),
-        pattern: Regex::new(r"!config-preset-list\(\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![],
         description: "List all saved model presets".to_string(),
         usage_example: "!config-preset-list()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             match configuration::load_configuration() {
                 Ok(config) => {
                     if config.model_presets.is_empty() {
@@ -300,9 +309,11 @@ pub fn register_config_commands() {
                 }
                 Err(e) => Ok(Some(format!("Error loading configuration: {}", e)))
             }
-        },
+        }),
         section: "configuration".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 }
\ No newline at end of file
@@ -1,16 +1,16 @@
 use crate::autocomplete;
-use crate::commands_registry::{Command, CommandType, register_command};
-use regex::Regex;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, register_command, sync_handler};
 use rustyline::completion::Pair as Completion;
 use rustyline::error::ReadlineError;
 
 pub fn register_help_command() {
     register_command(Command {
         name: "help".to_string(),
-        pattern: Regex::new(r"@help\(\s*(\S*)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("topic")],
         description: "Display help information for available commands".to_string(),
         usage_example: "@help([topic])".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() || params[0].is_empty() {
                 crate::commands_registry::print_help();
             } else if params[0] == "autocomplete" {
@@ -31,9 +31,11 @@ pub fn register_help_command() {
                     "\nTip: You can type partial text and press TAB to see fuzzy matched suggestions"
                 );
             }
-            Ok(None)
-        },
+            Ok(None::<String>)
+        }),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: Some(
             |line, pos| -> Result<(usize, Vec<Completion>), ReadlineError> {
                 // Extract parameter information
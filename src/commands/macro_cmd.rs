@@ -0,0 +1,229 @@
+use crate::autocomplete::{autocomplete_file_path, autocomplete_memory_id, autocomplete_model_id};
+use crate::chat;
+use crate::command_parser;
+use crate::commands_registry::{
+    self, ArgSpec, AutocompleteHandler, Command, CommandContext, CommandFuture, CommandHandler, CommandType, Sigil,
+    get_command, register_command, valid_name,
+};
+use crate::configuration::{self, MacroDefinition};
+use crate::terminal;
+use std::sync::Arc;
+
+/// How deep macros may call other macros
+const MAX_MACRO_DEPTH: usize = 8;
+
+/// Marks where a text placeholder goes until the calls around it have run; noncharacters
+/// never appear in typed text or command output.
+const SLOT_START: char = '\u{FDD0}';
+const SLOT_END: char = '\u{FDD1}';
+
+tokio::task_local! {
+    /// The macros being expanded, outermost first. Each expansion runs in a scope with
+    /// its own name added, so the stack unwinds with it, also when it is cancelled.
+    static EXPANDING: Vec<String>;
+}
+
+/// A `[macros.<name>]` entry of the configuration, run as a command.
+struct MacroCommand {
+    name: String,
+    params: Vec<String>,
+    body: String,
+}
+
+impl CommandHandler for MacroCommand {
+    fn call<'a>(&'a self, _context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let mut expanding = EXPANDING.try_with(Clone::clone).unwrap_or_default();
+            if let Some(first) = expanding.iter().position(|name| *name == self.name) {
+                let cycle: Vec<String> = expanding[first..].iter().chain([&self.name]).map(|name| format!("@{}", name)).collect();
+                return Err(format!("Macro cycle: {}", cycle.join(" → ")).into());
+            }
+            if expanding.len() >= MAX_MACRO_DEPTH {
+                return Err(format!("Macro @{} nests more than {} levels deep", self.name, MAX_MACRO_DEPTH).into());
+            }
+            expanding.push(self.name.clone());
+            let output = EXPANDING.scope(expanding, self.expand(&parameters)).await?;
+            Ok(Some(output.into()))
+        })
+    }
+}
+
+impl MacroCommand {
+    /// Run the body with the arguments filled in: lines starting with `>` go to the shell,
+    /// calls in the remaining text are expanded in place. Arguments only ever fill the
+    /// slot of their placeholder: they are shell-quoted in shell lines, escaped inside call
+    /// arguments and put back as plain text after the calls in text have run.
+    async fn expand(&self, parameters: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut output = String::new();
+        let mut text = String::new();
+
+        for line in self.body.lines() {
+            if command_parser::shell_command(line).is_none() {
+                text.push_str(line);
+                text.push('\n');
+                continue;
+            }
+
+            if !text.is_empty() {
+                output.push_str(&self.expand_text(&text, parameters).await?);
+                text.clear();
+            }
+            let line = self.fill(line, parameters, shell_quote);
+            match chat::execute_command(&line).await {
+                Ok(Some(result)) => match result.command_output {
+                    Ok(Some(shell_output)) => output.push_str(&shell_output.render_llm()),
+                    Ok(None) => {}
+                    Err(e) => output.push_str(&format!("Error running '{}': {}", line.trim(), e)),
+                },
+                Ok(None) => {}
+                Err(e) => output.push_str(&format!("Error running '{}': {}", line.trim(), e)),
+            }
+            if !output.ends_with('\n') {
+                output.push('\n');
+            }
+        }
+
+        if !text.is_empty() {
+            output.push_str(&self.expand_text(&text, parameters).await?);
+        }
+        Ok(output.trim_end().to_string())
+    }
+
+    /// Expand the calls in `text`. Placeholders inside a call become escaped argument text;
+    /// the others are held as slots and filled in once the calls have run. A failing call
+    /// fails the macro.
+    async fn expand_text(&self, text: &str, parameters: &[String]) -> Result<String, Box<dyn std::error::Error>> {
+        let scan = command_parser::scan_prompt(text, |sigil, name| commands_registry::find_command(sigil, name).is_some());
+        let spans: Vec<_> = scan.pipelines.iter().flatten().map(|pipeline| pipeline.span.clone()).collect();
+
+        let mut template = String::with_capacity(text.len());
+        let mut pos = 0;
+        for span in &spans {
+            template.push_str(&self.fill(&text[pos..span.start], parameters, |index, _| format!("{}{}{}", SLOT_START, index, SLOT_END)));
+            template.push_str(&self.fill(&text[span.clone()], parameters, |_, value| escape_argument(value)));
+            pos = span.end;
+        }
+        template.push_str(&self.fill(&text[pos..], parameters, |index, _| format!("{}{}{}", SLOT_START, index, SLOT_END)));
+
+        let expansion = chat::check_embedded_commands(&template).await;
+        if let Some(error) = expansion.errors.into_iter().next() {
+            return Err(error.into());
+        }
+        let mut prompt = expansion.prompt;
+        for (index, value) in self.values(parameters).enumerate() {
+            prompt = prompt.replace(&format!("{}{}{}", SLOT_START, index, SLOT_END), value);
+        }
+        Ok(prompt)
+    }
+
+    /// `text` with every `{{param}}` replaced by `slot(index, value)`; the value of a
+    /// missing optional argument is "".
+    fn fill(&self, text: &str, parameters: &[String], slot: impl Fn(usize, &str) -> String) -> String {
+        let mut text = text.to_string();
+        for (index, (param, value)) in self.params.iter().zip(self.values(parameters)).enumerate() {
+            text = text.replace(&format!("{{{{{}}}}}", param), &slot(index, value));
+        }
+        text
+    }
+
+    fn values<'p>(&self, parameters: &'p [String]) -> impl Iterator<Item = &'p str> {
+        (0..self.params.len()).map(|index| parameters.get(index).map_or("", |value| value.as_str()))
+    }
+}
+
+/// A value as one shell word.
+fn shell_quote(_index: usize, value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// A value as literal text inside a call argument, quoted or not: every character the
+/// command language gives a meaning is escaped with a backslash.
+fn escape_argument(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ',' | '(' | ')' | '[' | ']' | '{' | '}' | '"' | '\'' | '@' | '!' | '|' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn autocomplete_handler(kind: &str) -> Result<AutocompleteHandler, String> {
+    match kind {
+        "file" => Ok(autocomplete_file_path),
+        "memory" => Ok(autocomplete_memory_id),
+        "model" => Ok(autocomplete_model_id),
+        other => Err(format!("unknown autocomplete '{}' (expected file, memory or model)", other)),
+    }
+}
+
+/// Register one macro as `@name(params)`. Fails for invalid definitions and for names
+/// already taken by another command.
+pub fn register_macro(name: &str, definition: &MacroDefinition) -> Result<(), String> {
+    if !valid_name(name) {
+        return Err(format!("'{}' is not a valid command name", name));
+    }
+    if get_command(name).is_some() {
+        return Err(format!("@{} is already a command", name));
+    }
+
+    let mut args = Vec::new();
+    let mut params = Vec::new();
+    for param in &definition.params {
+        let (param, optional) = match param.strip_suffix('?') {
+            Some(param) => (param, true),
+            None => (param.as_str(), false),
+        };
+        if !valid_name(param) {
+            return Err(format!("'{}' is not a valid parameter name", param));
+        }
+        if !optional && args.last().is_some_and(|spec: &ArgSpec| !spec.required) {
+            return Err(format!("required parameter '{}' follows an optional one", param));
+        }
+        args.push(if optional { ArgSpec::optional(param) } else { ArgSpec::required(param) });
+        params.push(param.to_string());
+    }
+
+    let autocomplete_handler = definition.autocomplete.as_deref().map(autocomplete_handler).transpose()?;
+    let description = if definition.description.is_empty() {
+        "User-defined macro".to_string()
+    } else {
+        definition.description.clone()
+    };
+
+    register_command(Command {
+        name: name.to_string(),
+        sigil: Sigil::At,
+        usage_example: format!("@{}({})", name, definition.params.join(", ")),
+        args,
+        description,
+        handler: Arc::new(MacroCommand {
+            name: name.to_string(),
+            params,
+            body: definition.body.clone(),
+        }),
+        section: definition.section.clone(),
+        command_type: if definition.local { CommandType::NotLLM } else { CommandType::LLM },
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler,
+    });
+    Ok(())
+}
+
+/// Register the macros of the `[macros]` configuration section.
+pub fn register_macro_commands() {
+    let config = match configuration::get_effective_config() {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+
+    let mut names: Vec<&String> = config.macros.keys().collect();
+    names.sort();
+    for name in names {
+        if let Err(e) = register_macro(name, &config.macros[name]) {
+            eprintln!("{}", terminal::format_error(&format!("Skipping macro @{}: {}", name, e)));
+        }
+    }
+}
@@ -1,185 +1,217 @@
-use crate::commands_registry::{Command, CommandType, register_command};
-use crate::mcp_client;
-use regex::Regex;
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{
+    ArgSpec, Command, CommandContext, CommandFuture, CommandHandler, CommandType, Sigil, async_handler,
+    get_all_commands, register_command, sync_handler, unregister_command,
+};
+use crate::mcp_client::{self, McpTool, ToolEvent};
+use crate::permissions::Effect;
 use serde_json::Value;
+use std::sync::Arc;
+
+/// An MCP tool exposed as its own `@mcp:<server>:<tool>(json-args)` command.
+struct McpToolCommand {
+    server_tool: String,
+}
+
+impl CommandHandler for McpToolCommand {
+    fn call<'a>(&'a self, context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            if !context.config()?.mcp.enabled {
+                return Ok(Some("MCP is disabled in the configuration".into()));
+            }
+
+            let args = parameters
+                .first()
+                .map(|args| args.trim())
+                .filter(|args| !args.is_empty())
+                .unwrap_or("{}")
+                .to_string();
+            match mcp_client::execute_mcp_command("mcp:call", &[self.server_tool.clone(), args]).await {
+                Ok(Some(result)) => Ok(Some(tool_output(result))),
+                Ok(None) => Ok(Some(format!("Failed to execute tool: {}", self.server_tool).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Executing tool {} failed: {}", self.server_tool, e)))),
+            }
+        })
+    }
+}
+
+/// A tool result as JSON when it is JSON, so it keeps its structure.
+fn tool_output(result: String) -> CommandOutput {
+    match serde_json::from_str::<Value>(&result) {
+        Ok(value) if value.is_object() || value.is_array() => CommandOutput::Json(value),
+        _ => CommandOutput::Text(result),
+    }
+}
+
+fn tool_command_name(tool: &McpTool) -> String {
+    format!("mcp:{}:{}", tool.server, tool.name)
+}
+
+/// Register a tool of a connected MCP server as a command.
+fn register_tool_command(tool: &McpTool) {
+    let server_tool = format!("{}:{}", tool.server, tool.name);
+    register_command(Command {
+        name: tool_command_name(tool),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("args").rest()],
+        description: tool.description.clone(),
+        usage_example: format!("@mcp:{}({{\"arg\":\"value\"}})", server_tool),
+        handler: Arc::new(McpToolCommand { server_tool }),
+        section: "mcp".to_string(),
+        command_type: CommandType::LLM,
+        effects: vec![Effect::Exec, Effect::Network],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+}
+
+/// Remove the tool commands of a server that was disconnected.
+fn unregister_server_tool_commands(server: &str) {
+    let prefix = format!("mcp:{}:", server);
+    for command in get_all_commands() {
+        if command.name.starts_with(&prefix) {
+            unregister_command(&command.name);
+        }
+    }
+}
 
 /// Register all MCP-related commands
 pub fn register_mcp_commands() {
+    // Tools of connected servers come and go as commands
+    mcp_client::set_tool_listener(|event| match event {
+        ToolEvent::Added(tool) => register_tool_command(tool),
+        ToolEvent::ServerRemoved(server) => unregister_server_tool_commands(server),
+    });
+
     // List MCP servers command
     register_command(Command {
-        name: "mcp-list-servers".to_string(),
-        pattern: Regex::new(r"@mcp:list-servers\(\s*\)").unwrap(),
+        name: "mcp:list-servers".to_string(),
+        sigil: Sigil::At,
+        args: vec![],
         description: "List all MCP servers and their connection status".to_string(),
         usage_example: "@mcp:list-servers()".to_string(),
-        handler: |_params| {
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:list-servers", &[]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some("No MCP servers configured".to_string())),
-                    Err(e) => Ok(Some(format!("Error listing MCP servers: {}", e))),
-                }
-            })
-        },
+        handler: async_handler(|_params| Box::pin(async move {
+            match mcp_client::execute_mcp_command("mcp:list-servers", &[]).await {
+                Ok(Some(result)) => Ok(Some(result.into())),
+                Ok(None) => Ok(Some("No MCP servers configured".into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Listing MCP servers failed: {}", e)))),
+            }
+        })),
         section: "mcp".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // List MCP tools command
     register_command(Command {
-        name: "mcp-list-tools".to_string(),
-        pattern: Regex::new(r"@mcp:list-tools\(\s*\)").unwrap(),
-        description: "List all available MCP tools across all servers".to_string(),
-        usage_example: "@mcp:list-tools()".to_string(),
-        handler: |_params| {
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:list-tools", &[]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some("No MCP tools available".to_string())),
-                    Err(e) => Ok(Some(format!("Error listing MCP tools: {}", e))),
-                }
-            })
-        },
-        section: "mcp".to_string(),
-        command_type: CommandType::NotLLM,
-        autocomplete_handler: None,
-    });
-
-    // List tools for specific server
-    register_command(Command {
-        name: "mcp-list-server-tools".to_string(),
-        pattern: Regex::new(r"@mcp:list-tools\(\s*(\S+)\s*\)").unwrap(),
-        description: "List MCP tools for a specific server".to_string(),
+        name: "mcp:list-tools".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("server")],
+        description: "List available MCP tools, across all servers or for one server".to_string(),
         usage_example: "@mcp:list-tools(server-name)".to_string(),
-        handler: |params| {
-            if params.is_empty() {
-                return Ok(Some("Usage: @mcp:list-tools(server-name)".to_string()));
+        handler: async_handler(|params| Box::pin(async move {
+            match mcp_client::execute_mcp_command("mcp:list-tools", &params).await {
+                Ok(Some(result)) => Ok(Some(result.into())),
+                Ok(None) => match params.first() {
+                    Some(server_name) => Ok(Some(format!("No tools found for server: {}", server_name).into())),
+                    None => Ok(Some("No MCP tools available".into())),
+                },
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Listing MCP tools failed: {}", e)))),
             }
-            
-            let server_name = &params[0];
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:list-tools", &[server_name.to_string()]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some(format!("No tools found for server: {}", server_name))),
-                    Err(e) => Ok(Some(format!("Error listing tools for server {}: {}", server_name, e))),
-                }
-            })
-        },
+        })),
         section: "mcp".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Connect to MCP server
     register_command(Command {
-        name: "mcp-connect".to_string(),
-        pattern: Regex::new(r"@mcp:connect\(\s*(\S+)\s*\)").unwrap(),
+        name: "mcp:connect".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("server")],
         description: "Connect to an MCP server".to_string(),
         usage_example: "@mcp:connect(server-name)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.is_empty() {
-                return Ok(Some("Usage: @mcp:connect(server-name)".to_string()));
+                return Ok(Some("Usage: @mcp:connect(server-name)".into()));
             }
             
             let server_name = &params[0];
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:connect", &[server_name.to_string()]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some(format!("Failed to connect to server: {}", server_name))),
-                    Err(e) => Ok(Some(format!("Error connecting to server {}: {}", server_name, e))),
-                }
-            })
-        },
+            match mcp_client::execute_mcp_command("mcp:connect", &[server_name.to_string()]).await {
+                Ok(Some(result)) => Ok(Some(result.into())),
+                Ok(None) => Ok(Some(format!("Failed to connect to server: {}", server_name).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Connecting to server {} failed: {}", server_name, e)))),
+            }
+        })),
         section: "mcp".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::Exec],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Disconnect from MCP server
     register_command(Command {
-        name: "mcp-disconnect".to_string(),
-        pattern: Regex::new(r"@mcp:disconnect\(\s*(\S+)\s*\)").unwrap(),
+        name: "mcp:disconnect".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("server")],
         description: "Disconnect from an MCP server".to_string(),
         usage_example: "@mcp:disconnect(server-name)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.is_empty() {
-                return Ok(Some("Usage: @mcp:disconnect(server-name)".to_string()));
+                return Ok(Some("Usage: @mcp:disconnect(server-name)".into()));
             }
             
             let server_name = &params[0];
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:disconnect", &[server_name.to_string()]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some(format!("Failed to disconnect from server: {}", server_name))),
-                    Err(e) => Ok(Some(format!("Error disconnecting from server {}: {}", server_name, e))),
-                }
-            })
-        },
+            match mcp_client::execute_mcp_command("mcp:disconnect", &[server_name.to_string()]).await {
+                Ok(Some(result)) => Ok(Some(result.into())),
+                Ok(None) => Ok(Some(format!("Failed to disconnect from server: {}", server_name).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Disconnecting from server {} failed: {}", server_name, e)))),
+            }
+        })),
         section: "mcp".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Call MCP tool
     register_command(Command {
-        name: "mcp-call-tool".to_string(),
-        pattern: Regex::new(r"@mcp:call\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
-        description: "Call an MCP tool with arguments".to_string(),
+        name: "mcp:call".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("tool"), ArgSpec::optional("args").rest()],
+        description: "Call an MCP tool, with optional JSON arguments".to_string(),
         usage_example: r#"@mcp:call(server:tool, {"arg":"value"})"#.to_string(),
-        handler: |params| {
-            if params.len() < 2 {
-                return Ok(Some(r#"Usage: @mcp:call(server:tool, {"arg":"value"})"#.to_string()));
-            }
-            
+        handler: async_handler(|params| Box::pin(async move {
             let server_tool = &params[0];
-            let args_str = &params[1];
-            
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:call", &[server_tool.to_string(), args_str.to_string()]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some(format!("Failed to execute tool: {}", server_tool))),
-                    Err(e) => Ok(Some(format!("Error executing tool {}: {}", server_tool, e))),
-                }
-            })
-        },
-        section: "mcp".to_string(),
-        command_type: CommandType::LLM,
-        autocomplete_handler: None,
-    });
+            let args_str = params.get(1).filter(|args| !args.is_empty()).cloned().unwrap_or_else(|| "{}".to_string());
 
-    // Simple MCP tool call without JSON args
-    register_command(Command {
-        name: "mcp-call-simple".to_string(),
-        pattern: Regex::new(r"@mcp:call\(\s*(\S+)\s*\)").unwrap(),
-        description: "Call an MCP tool without arguments".to_string(),
-        usage_example: "@mcp:call(server:tool)".to_string(),
-        handler: |params| {
-            if params.is_empty() {
-                return Ok(Some("Usage: @mcp:call(server:tool)".to_string()));
+            match mcp_client::execute_mcp_command("mcp:call", &[server_tool.to_string(), args_str]).await {
+                Ok(Some(result)) => Ok(Some(tool_output(result))),
+                Ok(None) => Ok(Some(format!("Failed to execute tool: {}", server_tool).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Executing tool {} failed: {}", server_tool, e)))),
             }
-            
-            let server_tool = &params[0];
-            
-            tokio::runtime::Handle::current().block_on(async {
-                match mcp_client::execute_mcp_command("mcp:call", &[server_tool.to_string(), "{}".to_string()]).await {
-                    Ok(Some(result)) => Ok(Some(result)),
-                    Ok(None) => Ok(Some(format!("Failed to execute tool: {}", server_tool))),
-                    Err(e) => Ok(Some(format!("Error executing tool {}: {}", server_tool, e))),
-                }
-            })
-        },
+        })),
         section: "mcp".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![Effect::Exec, Effect::Network],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // MCP status command
     register_command(Command {
-        name: "mcp-status".to_string(),
-        pattern: Regex::new(r"@mcp:status\(\s*\)").unwrap(),
+        name: "mcp:status".to_string(),
+        sigil: Sigil::At,
+        args: vec![],
         description: "Show MCP system status and configuration".to_string(),
         usage_example: "@mcp:status()".to_string(),
-        handler: |_params| {
+        handler: sync_handler(|_params| {
             let config = match crate::configuration::get_effective_config() {
                 Ok(config) => config,
                 Err(e) => return Ok(Some(format!("Error loading config: {}", e))),
@@ -209,9 +241,11 @@ pub fn register_mcp_commands() {
             );
             
             Ok(Some(status))
-        },
+        }),
         section: "mcp".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 }
\ No newline at end of file
@@ -1,16 +1,24 @@
-use regex::Regex;
 use std::fs;
 use std::path::Path;
 
 use crate::autocomplete::{autocomplete_file_path, autocomplete_memory_id, autocomplete_model_id};
 use crate::chat::{self, Prompt, PromptType};
-use crate::commands_registry::{Command, CommandType, register_command};
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, closure_handler, register_command, sync_handler};
+use crate::configuration::FileSettings;
 use crate::files::files as file_module; // Import autocomplete handlers
+use crate::permissions::{self, Effect};
+use crate::{session, symbols};
 
+pub mod apply_cmd;
 pub mod bash_cmd;
 pub mod config_cmd;
 pub mod help;
+pub mod macro_cmd;
 pub mod mcp_cmd;
+pub mod pipe_cmd;
+pub mod plugin_cmd;
+pub mod script_cmd;
 pub mod set_model;
 pub mod session_cmd;
 pub mod workflow_cmd;
@@ -20,150 +28,267 @@ pub fn register_all_commands() {
     // List files command
     register_command(Command {
         name: "list-files".to_string(),
-        pattern: Regex::new(r"@list-files\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("pattern").path()],
         description: "List files matching a pattern".to_string(),
         usage_example: "@list-files([wildcard])".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             if params.is_empty() {
                 println!("Usage: @list-files [wildcard]");
                 return Ok(None);
             }
             let pattern = &params[0];
-            let files = file_module::list_files(pattern)?;
-            Ok(Some(format!("\n{}\n", files.join("\n"))))
-        },
+            let files = file_module::list_files(pattern, &context.config()?.files)?;
+            Ok(Some(CommandOutput::FileList(files)))
+        }),
         section: "file".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_file_path),
     });
 
     // List folders command
     register_command(Command {
         name: "list-folders".to_string(),
-        pattern: Regex::new(r"@list-folders\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("pattern").path()],
         description: "List folders matching a pattern".to_string(),
         usage_example: "@list-folders([wildcard])".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             if params.is_empty() {
                 println!("Usage: @list-folders [wildcard]");
                 return Ok(None);
             }
             let pattern = &params[0];
-            let folders = file_module::list_folders(pattern)?;
-            Ok(Some(folders.join("\n")))
-        },
+            let folders = file_module::list_folders(pattern, &context.config()?.files)?;
+            Ok(Some(CommandOutput::FileList(folders)))
+        }),
         section: "folder".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_file_path),
     });
 
     // Read files command
     register_command(Command {
         name: "read-files".to_string(),
-        pattern: Regex::new(r"@read-files\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("pattern").path(), ArgSpec::optional("alias")],
         description: "Read multiple files using wildcard pattern into memory".to_string(),
         usage_example: "@read-files([wildcard])".to_string(),
-        handler: |params| {
-            if params.is_empty() {
-                println!("Usage: @read-files([wildcard])");
-                return Ok(None);
-            }
-            let pattern = &params[0];
-            let files_map = file_module::read_files(pattern)?;
-
-            // Concatenate all file contents into one string
-            let mut combined_content = String::new();
-            for (filename, content) in &files_map {
-                combined_content.push_str(&format!("File: {}\n", filename));
-                combined_content.push_str(content);
-                combined_content.push_str("\n\n");
-            }
-
-            Ok(Some(format!("{}", combined_content)))
-        },
+        handler: closure_handler(|context, params| {
+            let settings = &context.config()?.files;
+            let paths = match params.first().filter(|pattern| !pattern.is_empty()) {
+                Some(_) if context.input().is_some() => {
+                    return Err("@read-files takes a pattern or piped paths, not both".into());
+                }
+                Some(pattern) => file_module::list_files(pattern, settings)?,
+                // After a `|` the previous stage lists the files, one per line; they are
+                // checked against the path rules like a pattern argument
+                None => match context.input_lines() {
+                    Some(paths) => {
+                        let paths: Vec<String> = paths.iter().map(|path| path.trim().to_string()).collect();
+                        permissions::check(&[Effect::ReadFs], "@read-files", &paths)?;
+                        paths
+                    }
+                    None => {
+                        println!("Usage: @read-files([wildcard])");
+                        return Ok(None);
+                    }
+                },
+            };
+            Ok(Some(read_into_output(&paths, settings)))
+        }),
         section: "file".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: true,
         autocomplete_handler: Some(autocomplete_file_path),
     });
 
     register_command(Command {
         name: "read-folders".to_string(),
-        pattern: Regex::new(r"@read-folders\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("pattern").path()],
         description: "Read multiple folders using wildcard pattern into memory".to_string(),
         usage_example: "@read-folders([wildcard])".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             if params.is_empty() {
                 println!("Usage: @read-folders([wildcard])");
                 return Ok(None);
             }
-            let pattern = &params[0];
-            let files_map = file_module::read_folder(pattern)?;
-
-            // Concatenate all file contents into one string
-            let mut combined_content = String::new();
-            for (filename, content) in &files_map {
-                combined_content.push_str(&format!("File: {}\n", filename));
-                combined_content.push_str(content);
-                combined_content.push_str("\n\n");
-            }
-
-            Ok(Some(format!("{}", combined_content)))
-        },
+            let settings = &context.config()?.files;
+            let paths = file_module::list_folder_files(&params[0], settings)?;
+            Ok(Some(read_into_output(&paths, settings)))
+        }),
         section: "folder".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_file_path),
     });
 
     // Read file command
     register_command(Command {
         name: "read-file".to_string(),
-        pattern: Regex::new(r"@read-file\(\s*(\S+)\s*\)").unwrap(),
-        description: "Read a file into prompt".to_string(),
-        usage_example: "@read-file([filename])".to_string(),
-        handler: |params| {
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("path").path()],
+        description: "Read a file, or a range of its lines, into prompt".to_string(),
+        usage_example: "@read-file([filename]) or @read-file([filename]:[first]-[last])".to_string(),
+        handler: closure_handler(|context, params| {
             if params.is_empty() {
-                println!("Usage: @read-file([filename])");
+                println!("Usage: @read-file([filename]) or @read-file([filename]:[first]-[last])");
                 return Ok(None);
             }
-            let filename = &params[0];
-            let contents = file_module::read_file(filename)?;
+            let settings = &context.config()?.files;
+            let spec = &params[0];
+            match file_module::split_line_range(spec) {
+                (filename, Some((first, last))) if !Path::new(spec).exists() => {
+                    let file = file_module::read_lines(filename, first, last, settings)?;
+                    session::record_file_reference(filename);
+                    Ok(Some(CommandOutput::FileContents { files: vec![file], skipped: vec![] }))
+                }
+                _ => {
+                    if !Path::new(spec).is_file() {
+                        return Err(format!("{}: no such file", spec).into());
+                    }
+                    Ok(Some(read_into_output(std::slice::from_ref(spec), settings)))
+                }
+            }
+        }),
+        section: "file".to_string(),
+        command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_file_path),
+    });
 
-            Ok(Some(format!("File: {}\n{}", filename, contents)))
-        },
+    register_command(Command {
+        name: "read-symbol".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("path").path(), ArgSpec::required("symbol")],
+        description: "Read a function, type or impl block from a Rust, Python, TypeScript or Go file into prompt".to_string(),
+        usage_example: "@read-symbol([filename], [Type::method])".to_string(),
+        handler: closure_handler(|context, params| {
+            if params.len() < 2 {
+                println!("Usage: @read-symbol([filename], [Type::method])");
+                return Ok(None);
+            }
+            let (filename, query) = (&params[0], &params[1]);
+            let settings = &context.config()?.files;
+            let source = file_module::read_file(filename, settings)?;
+            let found = symbols::symbols(filename, &source)?;
+            let matches = symbols::find(&found, query);
+            if matches.is_empty() {
+                let top_level: Vec<&str> = found
+                    .iter()
+                    .filter(|symbol| symbol.depth == 0)
+                    .map(|symbol| symbol.path.as_str())
+                    .collect();
+                return Err(format!("{} has no symbol {}; it defines {}", filename, query, top_level.join(", ")).into());
+            }
+
+            let lines: Vec<&str> = source.lines().collect();
+            let mut files = Vec::with_capacity(matches.len());
+            for symbol in matches {
+                let content = lines[symbol.start_line - 1..symbol.end_line.min(lines.len())].join("\n");
+                files.push(file_module::excerpt(filename, symbol.start_line, content, settings)?);
+            }
+            session::record_file_reference(filename);
+            Ok(Some(CommandOutput::FileContents { files, skipped: vec![] }))
+        }),
+        section: "file".to_string(),
+        command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_file_path),
+    });
+
+    register_command(Command {
+        name: "tree".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("path").path(), ArgSpec::optional("depth")],
+        description: "Show the folder tree with file sizes and line counts".to_string(),
+        usage_example: "@tree([path], [depth])".to_string(),
+        handler: closure_handler(|context, params| {
+            let root = params.first().filter(|path| !path.is_empty()).map_or(".", String::as_str);
+            let depth = match params.get(1).filter(|depth| !depth.is_empty()) {
+                Some(depth) => depth.parse().map_err(|_| format!("'{}' is not a depth", depth))?,
+                None => 3,
+            };
+            Ok(Some(file_module::tree(root, depth, &context.config()?.files)?))
+        }),
+        section: "folder".to_string(),
+        command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_file_path),
+    });
+
+    register_command(Command {
+        name: "outline".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("path").path()],
+        description: "List the functions, types and impls of source files, to ask for the ones needed".to_string(),
+        usage_example: "@outline([path or wildcard])".to_string(),
+        handler: closure_handler(|context, params| {
+            let settings = &context.config()?.files;
+            let target = params.first().filter(|path| !path.is_empty()).map_or(".", String::as_str);
+            let paths = if Path::new(target).is_dir() {
+                let pattern = Path::new(target).join("**").join("*");
+                file_module::list_files(&pattern.to_string_lossy(), settings)?
+            } else {
+                file_module::list_files(target, settings)?
+            };
+
+            let lines = symbols::outline_files(&paths, settings);
+            if lines.is_empty() {
+                return Err(format!("{}: no Rust, Python, TypeScript, JavaScript or Go files", target).into());
+            }
+            Ok(Some(lines.join("\n")))
+        }),
         section: "file".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_file_path),
     });
 
     register_command(Command {
         name: "get-memory".to_string(),
-        pattern: Regex::new(r"@get-memory\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("id")],
         description: "Load content from memory into chat".to_string(),
         usage_example: "@get-memory([memory-id])".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             if params.is_empty() {
                 println!("Usage: @get-memory([memory-id])");
                 return Ok(None);
             }
             let memory_id = &params[0];
-            let memory = chat::get_memory().lock().unwrap();
+            let memory = context.memory().lock().unwrap();
 
             match memory.get(memory_id) {
-                Some(prompt) => Ok(Some(format!("{}:\n{}\n", memory_id, prompt.value))),
-                None => Ok(Some(format!("Error: prompt id {} not found.", memory_id))),
+                Some(prompt) => Ok(Some(CommandOutput::Text(format!("{}:\n{}\n", memory_id, prompt.value)))),
+                None => Ok(Some(CommandOutput::Error(format!("prompt id {} not found.", memory_id)))),
             }
-        },
+        }),
         section: "memory".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_memory_id),
     });
 
     register_command(Command {
         name: "set-alias".to_string(),
-        pattern: Regex::new(r"@set-alias\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("id"), ArgSpec::required("alias")],
         description: "Load content from memory into chat".to_string(),
         usage_example: "@set-alias([alias-id])".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 println!("Usage: @set-alias([alias-id])");
                 return Ok(None);
@@ -182,20 +307,23 @@ pub fn register_all_commands() {
                     Ok(Some(format!("Alias {} added.", prompt.id)))
                 }
             }
-        },
+        }),
         section: "memory".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_memory_id),
     });
 
     register_command(Command {
         name: "export".to_string(),
-        pattern: Regex::new(r"@export\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("id"), ArgSpec::required("path").path()],
         description: "Export memory content into file.".to_string(),
         usage_example: "@export(45dge64 or ? or _ or @ or ~, ./output.md)".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             let mut content = String::new();
-            let memory = chat::get_memory().lock().unwrap();
+            let memory = context.memory().lock().unwrap();
 
             if params.len() < 2 {
                 println!("Usage: @export([id or ? or _ or @ or ~],[file-name])");
@@ -231,55 +359,64 @@ pub fn register_all_commands() {
             }
             fs::write(file_name, content)?;
             Ok(Some(format!("File saved {}", file_name)))
-        },
+        }),
         section: "Utility".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_memory_id),
     });
 
     // Reset context command
     register_command(Command {
         name: "reset-memory".to_string(),
-        pattern: Regex::new(r"!reset-memory\(\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![],
         description: "Reset the memory".to_string(),
         usage_example: "!reset-memory()".to_string(),
-        handler: |_| {
-            let mut memory = chat::get_memory().lock().unwrap();
+        handler: closure_handler(|context, _| {
+            let mut memory = context.memory().lock().unwrap();
             memory.clear();
             Ok(Some("Memory reset done.".to_string()))
-        },
+        }),
         section: "memory".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::Destructive],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     register_command(Command {
         name: "remove-memory".to_string(),
-        pattern: Regex::new(r"!remove-memory\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("id")],
         description: "Remove memory item by id".to_string(),
         usage_example: "!remove-memory([memory-id])".to_string(),
-        handler: |params| {
+        handler: closure_handler(|context, params| {
             if params.len() < 1 {
                 println!("Usage: !remove-memory([memory-id])");
                 return Ok(None);
             }
             let memory_id = &params[0];
-            let mut memory = chat::get_memory().lock().unwrap();
+            let mut memory = context.memory().lock().unwrap();
             memory.remove(memory_id);
             Ok(Some(format!("Removed memory item {}", memory_id)))
-        },
+        }),
         section: "memory".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::Destructive],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_memory_id),
     });
 
     // set model command
     register_command(Command {
         name: "set-model".to_string(),
-        pattern: Regex::new(r"!set-model\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::Bang,
+        args: vec![ArgSpec::required("model")],
         description: "Set LLM model".to_string(),
         usage_example: "!set-model([model-id])".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.len() < 1 {
                 println!("Usage: !set-model([model-id])");
                 return Ok(None);
@@ -297,17 +434,36 @@ pub fn register_all_commands() {
                     ))
                 }
             }
-        },
+        }),
         section: "utility".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: Some(autocomplete_model_id),
     });
 
     // Register help command and set model command from existing modules
     help::register_help_command();
     bash_cmd::register_bash_command();
+    apply_cmd::register_apply_commands();
     config_cmd::register_config_commands();
     mcp_cmd::register_mcp_commands();
     session_cmd::register_session_commands();
     workflow_cmd::register_workflow_commands();
+    pipe_cmd::register_pipe_commands();
+    plugin_cmd::register_plugin_commands();
+    script_cmd::register_script_commands();
+
+    // User macros come last so they cannot shadow built-in, plugin or script commands
+    macro_cmd::register_macro_commands();
+}
+
+/// Read `paths` within the configured limits, noting each file that made it into the output
+/// as referenced by the session.
+fn read_into_output(paths: &[String], settings: &FileSettings) -> CommandOutput {
+    let (files, skipped) = file_module::read_files(paths, settings);
+    for file in &files {
+        session::record_file_reference(&file.path);
+    }
+    CommandOutput::FileContents { files, skipped }
 }
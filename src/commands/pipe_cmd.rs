@@ -0,0 +1,120 @@
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{
+    ArgSpec, Command, CommandContext, CommandHandlerOutputType, CommandType, PIPE_SECTION, Sigil, closure_handler,
+    register_command,
+};
+use regex::Regex;
+
+/// The output a filter works on; filters only make sense after a `|`.
+fn piped<'a>(context: &'a CommandContext, name: &str) -> Result<&'a CommandOutput, Box<dyn std::error::Error>> {
+    context.input().ok_or_else(|| {
+        format!("@{} filters piped input, e.g. @list-files(src/*.rs) | @{}", name, name).into()
+    })
+}
+
+/// Keep the items of the piped output for which `keep` holds.
+fn retain(input: &CommandOutput, keep: impl Fn(&str) -> bool) -> CommandHandlerOutputType {
+    let items = input.items();
+    let indices: Vec<usize> = (0..items.len()).filter(|&i| keep(&items[i])).collect();
+    Ok(Some(input.select(&indices)))
+}
+
+fn count(params: &[String]) -> Result<usize, Box<dyn std::error::Error>> {
+    match params.first() {
+        Some(count) if !count.is_empty() => Ok(count.parse().map_err(|_| format!("'{}' is not a number of lines", count))?),
+        _ => Ok(10),
+    }
+}
+
+fn register_filter(
+    name: &str,
+    args: Vec<ArgSpec>,
+    description: &str,
+    usage_example: &str,
+    filter: fn(&CommandContext, &[String]) -> CommandHandlerOutputType,
+) {
+    register_command(Command {
+        name: name.to_string(),
+        sigil: Sigil::At,
+        args,
+        description: description.to_string(),
+        usage_example: usage_example.to_string(),
+        handler: closure_handler(filter),
+        section: PIPE_SECTION.to_string(),
+        command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: true,
+        autocomplete_handler: None,
+    });
+}
+
+pub fn register_pipe_commands() {
+    register_filter(
+        "filter",
+        vec![ArgSpec::required("text")],
+        "Keep the piped lines that contain a text",
+        "@list-files(src/**/*.rs) | @filter(test)",
+        |context, params| retain(piped(context, "filter")?, |item| item.contains(params[0].as_str())),
+    );
+
+    register_filter(
+        "grep",
+        vec![ArgSpec::required("pattern")],
+        "Keep the piped lines that match a regular expression",
+        "@read-file(src/main.rs) | @grep(^use )",
+        |context, params| {
+            let pattern = Regex::new(&params[0]).map_err(|e| format!("Invalid pattern: {}", e))?;
+            retain(piped(context, "grep")?, |item| pattern.is_match(item))
+        },
+    );
+
+    register_filter(
+        "head",
+        vec![ArgSpec::optional("count")],
+        "Keep the first piped lines (10 by default)",
+        "@list-files(src/*.rs) | @head(5)",
+        |context, params| {
+            let count = count(params)?;
+            let input = piped(context, "head")?;
+            let indices: Vec<usize> = (0..input.items().len()).take(count).collect();
+            Ok(Some(input.select(&indices)))
+        },
+    );
+
+    register_filter(
+        "tail",
+        vec![ArgSpec::optional("count")],
+        "Keep the last piped lines (10 by default)",
+        "@read-file(build.log) | @tail(20)",
+        |context, params| {
+            let count = count(params)?;
+            let input = piped(context, "tail")?;
+            let len = input.items().len();
+            let indices: Vec<usize> = (len.saturating_sub(count)..len).collect();
+            Ok(Some(input.select(&indices)))
+        },
+    );
+
+    register_filter(
+        "sort",
+        vec![ArgSpec::optional("order")],
+        "Sort the piped lines, `desc` for reverse order",
+        "@list-files(src/*.rs) | @sort(desc)",
+        |context, params| {
+            let descending = match params.first().map(|order| order.to_lowercase()) {
+                None => false,
+                Some(order) if order.is_empty() || order == "asc" => false,
+                Some(order) if order == "desc" => true,
+                Some(order) => return Err(format!("Unknown order '{}', use asc or desc", order).into()),
+            };
+            let input = piped(context, "sort")?;
+            let items = input.items();
+            let mut indices: Vec<usize> = (0..items.len()).collect();
+            indices.sort_by(|&a, &b| items[a].cmp(&items[b]));
+            if descending {
+                indices.reverse();
+            }
+            Ok(Some(input.select(&indices)))
+        },
+    );
+}
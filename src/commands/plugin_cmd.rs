@@ -0,0 +1,137 @@
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{
+    Command, CommandContext, CommandFuture, CommandHandler, CommandType, Sigil, get_command, register_command,
+    sync_handler,
+};
+use crate::configuration;
+use crate::plugin_host::{self, CallRequest, LoadedPlugin};
+use crate::terminal;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A command provided by an external plugin executable.
+struct PluginCommand {
+    plugin: PathBuf,
+    command: String,
+    arg_names: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandHandler for PluginCommand {
+    fn call<'a>(&'a self, context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            let args: Map<String, Value> = self
+                .arg_names
+                .iter()
+                .zip(parameters)
+                .map(|(name, value)| (name.clone(), Value::String(value)))
+                .collect();
+            let request = CallRequest {
+                command: self.command.clone(),
+                args,
+                session: context.session.clone(),
+            };
+            plugin_host::call(&self.plugin, request, self.timeout)
+                .await
+                .map(|output| output.map(CommandOutput::Text))
+                .map_err(|e| format!("Plugin command @{} failed: {}", self.command, e).into())
+        })
+    }
+}
+
+/// Register the commands a plugin declared in its handshake. Returns why commands were
+/// skipped, one message each.
+pub fn register_plugin(plugin: &LoadedPlugin, timeout: Duration) -> Vec<String> {
+    let Ok(description) = &plugin.description else {
+        return Vec::new();
+    };
+
+    let mut skipped = Vec::new();
+    for spec in &description.commands {
+        if get_command(&spec.name).is_some() {
+            skipped.push(format!("@{} of plugin {} is already a command", spec.name, description.name));
+            continue;
+        }
+
+        let usage_example = spec.usage_example.clone().unwrap_or_else(|| {
+            let args: Vec<&str> = spec.args.iter().map(|arg| arg.name.as_str()).collect();
+            format!("@{}({})", spec.name, args.join(", "))
+        });
+        register_command(Command {
+            name: spec.name.clone(),
+            sigil: Sigil::At,
+            args: spec.args.iter().map(|arg| arg.arg_spec()).collect(),
+            description: format!("{} [{}]", spec.description, description.name),
+            usage_example,
+            handler: Arc::new(PluginCommand {
+                plugin: plugin.path.clone(),
+                command: spec.name.clone(),
+                arg_names: spec.args.iter().map(|arg| arg.name.clone()).collect(),
+                timeout,
+            }),
+            section: spec.section.clone(),
+            command_type: spec.command_type.command_type(),
+            effects: spec.effects.clone(),
+            reads_input: false,
+            autocomplete_handler: None,
+        });
+    }
+    skipped
+}
+
+fn format_plugins(plugins: &[LoadedPlugin]) -> String {
+    if plugins.is_empty() {
+        return "No plugins found".to_string();
+    }
+
+    let mut output = String::from("Plugins:\n");
+    for plugin in plugins {
+        match &plugin.description {
+            Ok(description) => {
+                let commands: Vec<String> = description.commands.iter().map(|c| format!("@{}", c.name)).collect();
+                output.push_str(&format!(
+                    "  {} {} ({})\n    commands: {}\n",
+                    description.name,
+                    description.version,
+                    plugin.path.display(),
+                    commands.join(", ")
+                ));
+            }
+            Err(e) => output.push_str(&format!("  {} - not loaded: {}\n", plugin.path.display(), e)),
+        }
+    }
+    output.trim_end().to_string()
+}
+
+/// Describe the plugins of the configured directories, register their commands and `@plugins`.
+pub fn register_plugin_commands() {
+    register_command(Command {
+        name: "plugins".to_string(),
+        sigil: Sigil::At,
+        args: vec![],
+        description: "List external plugins and the commands they provide".to_string(),
+        usage_example: "@plugins()".to_string(),
+        handler: sync_handler(|_params| Ok(Some(format_plugins(&plugin_host::loaded_plugins())))),
+        section: "plugins".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    let config = match configuration::get_effective_config() {
+        Ok(config) if config.plugins.enabled => config,
+        _ => return,
+    };
+    let timeout = Duration::from_secs(config.plugins.timeout_seconds as u64);
+    for plugin in plugin_host::load_plugins(&config) {
+        if let Err(e) = &plugin.description {
+            eprintln!("{}", terminal::format_error(&format!("Skipping plugin {}: {}", plugin.path.display(), e)));
+        }
+        for message in register_plugin(&plugin, timeout) {
+            eprintln!("{}", terminal::format_error(&format!("Skipping {}", message)));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{
+    ArgSpec, Command, CommandContext, CommandFuture, CommandHandler, CommandType, Sigil, get_command,
+    register_command, sync_handler, valid_name,
+};
+use crate::configuration;
+use crate::permissions::Effect;
+use crate::scripting::{self, LoadedScript, Script, ScriptCommandSpec};
+use crate::terminal;
+use rhai::Dynamic;
+use std::sync::Arc;
+
+/// A command registered by a script with `register_command`.
+struct ScriptCommand {
+    script: Arc<Script>,
+    function: String,
+    param_count: usize,
+}
+
+impl CommandHandler for ScriptCommand {
+    fn call<'a>(&'a self, _context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        Box::pin(async move {
+            // Optional parameters that were not given are passed as ""
+            let mut args: Vec<Dynamic> = parameters.into_iter().map(Dynamic::from).collect();
+            args.resize(self.param_count, Dynamic::from(String::new()));
+
+            let result = scripting::call_function_async(self.script.clone(), self.function.clone(), args).await?;
+            Ok((!result.is_unit()).then(|| CommandOutput::Text(result.to_string())))
+        })
+    }
+}
+
+/// Register one command of a loaded script.
+pub fn register_script_command(script: &Arc<Script>, spec: &ScriptCommandSpec) -> Result<(), String> {
+    if !valid_name(&spec.name) {
+        return Err(format!("'{}' is not a valid command name", spec.name));
+    }
+    if get_command(&spec.name).is_some() {
+        return Err(format!("@{} is already a command", spec.name));
+    }
+
+    let mut args = Vec::new();
+    for param in &spec.params {
+        let (param, optional) = match param.strip_suffix('?') {
+            Some(param) => (param, true),
+            None => (param.as_str(), false),
+        };
+        if !valid_name(param) {
+            return Err(format!("'{}' is not a valid parameter name", param));
+        }
+        if !optional && args.last().is_some_and(|spec: &ArgSpec| !spec.required) {
+            return Err(format!("required parameter '{}' follows an optional one", param));
+        }
+        args.push(if optional { ArgSpec::optional(param) } else { ArgSpec::required(param) });
+    }
+    register_command(Command {
+        name: spec.name.clone(),
+        sigil: Sigil::At,
+        args,
+        description: spec.description.clone(),
+        usage_example: format!("@{}({})", spec.name, spec.params.join(", ")),
+        handler: Arc::new(ScriptCommand {
+            script: script.clone(),
+            function: spec.function.clone(),
+            param_count: spec.params.len(),
+        }),
+        section: "scripts".to_string(),
+        command_type: CommandType::LLM,
+        // A script function may do anything the script functions allow
+        effects: vec![Effect::ReadFs, Effect::WriteFs, Effect::Exec, Effect::Network],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+    Ok(())
+}
+
+fn format_scripts(scripts: &[LoadedScript]) -> String {
+    if scripts.is_empty() {
+        return "No scripts found".to_string();
+    }
+
+    let mut output = String::from("Scripts:\n");
+    for loaded in scripts {
+        match &loaded.script {
+            Ok((_, registrations)) => {
+                let commands: Vec<String> = registrations.commands.iter().map(|c| format!("@{}", c.name)).collect();
+                let hooks: Vec<&str> = registrations.hooks.iter().map(|(hook, _)| hook.name()).collect();
+                output.push_str(&format!(
+                    "  {}\n    commands: {}\n    hooks: {}\n",
+                    loaded.path.display(),
+                    if commands.is_empty() { "-".to_string() } else { commands.join(", ") },
+                    if hooks.is_empty() { "-".to_string() } else { hooks.join(", ") }
+                ));
+            }
+            Err(e) => output.push_str(&format!("  {} - not loaded: {}\n", loaded.path.display(), e)),
+        }
+    }
+    output.trim_end().to_string()
+}
+
+/// Load the scripts of the configured directories, register their commands and `@scripts`.
+pub fn register_script_commands() {
+    register_command(Command {
+        name: "scripts".to_string(),
+        sigil: Sigil::At,
+        args: vec![],
+        description: "List loaded scripts with their commands and hooks".to_string(),
+        usage_example: "@scripts()".to_string(),
+        handler: sync_handler(|_params| Ok(Some(format_scripts(&scripting::loaded_scripts())))),
+        section: "scripts".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    let config = match configuration::get_effective_config() {
+        Ok(config) if config.scripting.enabled => config,
+        _ => return,
+    };
+    for loaded in scripting::load_scripts(&config) {
+        match &loaded.script {
+            Ok((script, registrations)) => {
+                for spec in &registrations.commands {
+                    if let Err(e) = register_script_command(script, spec) {
+                        eprintln!("{}", terminal::format_error(&format!("Skipping script command: {}", e)));
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", terminal::format_error(&format!("Skipping script {}: {}", loaded.path.display(), e)));
+            }
+        }
+    }
+}
@@ -1,15 +1,17 @@
-use regex::Regex;
-use crate::commands_registry::{Command, CommandType, register_command};
-use crate::session;
+use crate::autocomplete::autocomplete_file_path;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, closure_handler, register_command, sync_handler};
+use crate::permissions::Effect;
+use crate::{session, session_replay};
 
 pub fn register_session_commands() {
     // Create session command
     register_command(Command {
         name: "session-create".to_string(),
-        pattern: Regex::new(r"@session-create\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
         description: "Create a new conversation session".to_string(),
         usage_example: "@session-create(project-work)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 println!("Usage: @session-create(session-name)");
                 return Ok(None);
@@ -19,19 +21,22 @@ pub fn register_session_commands() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error creating session: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Switch session command
     register_command(Command {
         name: "session-switch".to_string(),
-        pattern: Regex::new(r"@session-switch\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
         description: "Switch to an existing conversation session".to_string(),
         usage_example: "@session-switch(project-work)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 println!("Usage: @session-switch(session-name)");
                 return Ok(None);
@@ -41,36 +46,48 @@ pub fn register_session_commands() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error switching session: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // List sessions command
     register_command(Command {
         name: "session-list".to_string(),
-        pattern: Regex::new(r"@session-list\(\s*\)").unwrap(),
-        description: "List all available conversation sessions".to_string(),
-        usage_example: "@session-list()".to_string(),
-        handler: |_| {
-            match session::list_sessions() {
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("filter")],
+        description: "List conversation sessions (pass 'all' to include archived ones)".to_string(),
+        usage_example: "@session-list([all])".to_string(),
+        handler: sync_handler(|params| {
+            let include_archived = params.first().is_some_and(|p| p == "all" || p == "archived");
+            let listing = if include_archived {
+                session::list_sessions_with_archived(true)
+            } else {
+                session::list_sessions()
+            };
+            match listing {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error listing sessions: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Delete session command
     register_command(Command {
         name: "session-delete".to_string(),
-        pattern: Regex::new(r"@session-delete\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
         description: "Delete a conversation session".to_string(),
         usage_example: "@session-delete(old-session)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
                 println!("Usage: @session-delete(session-name)");
                 return Ok(None);
@@ -80,19 +97,22 @@ pub fn register_session_commands() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error deleting session: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::Destructive],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Export session command
     register_command(Command {
         name: "session-export".to_string(),
-        pattern: Regex::new(r"@session-export\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name"), ArgSpec::required("path").path()],
         description: "Export a conversation session to a file".to_string(),
         usage_example: "@session-export(project-work, ./export.md)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.len() < 2 {
                 println!("Usage: @session-export(session-name, file-path)");
                 return Ok(None);
@@ -103,43 +123,372 @@ pub fn register_session_commands() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error exporting session: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Get current session info command
     register_command(Command {
         name: "session-current".to_string(),
-        pattern: Regex::new(r"@session-current\(\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![],
         description: "Show information about the current session".to_string(),
         usage_example: "@session-current()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             match session::get_current_session_info() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error getting current session info: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Save current session command
     register_command(Command {
         name: "session-save".to_string(),
-        pattern: Regex::new(r"@session-save\(\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![],
         description: "Manually save the current session".to_string(),
         usage_example: "@session-save()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             match session::save_current_session() {
                 Ok(message) => Ok(Some(message)),
                 Err(e) => Ok(Some(format!("Error saving session: {}", e))),
             }
-        },
+        }),
         section: "session".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Import sessions from another tool's export
+    register_command(Command {
+        name: "session-import".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("path").path(), ArgSpec::required("format")],
+        description: "Import conversations from a ChatGPT export or JSONL log as sessions".to_string(),
+        usage_example: "@session-import(./conversations.json, chatgpt)".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                println!("Usage: @session-import(file-path, chatgpt|jsonl)");
+                return Ok(None);
+            }
+            let import_path = &params[0];
+            let format = &params[1];
+            match session::import_sessions(import_path, format) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error importing sessions: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_file_path),
+    });
+
+    // Fork session command
+    register_command(Command {
+        name: "session-fork".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name"), ArgSpec::required("new-name"), ArgSpec::optional("prompt-id")],
+        description: "Copy a session under a new name, optionally only up to a given prompt".to_string(),
+        usage_example: "@session-fork(project-work, project-experiment, [prompt-id])".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                println!("Usage: @session-fork(session-name, new-name, [prompt-id])");
+                return Ok(None);
+            }
+            let source = &params[0];
+            let new_name = &params[1];
+            let up_to = params.get(2).map(|id| id.as_str());
+            match session::fork_session(source, new_name, up_to) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error forking session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Rename session command
+    register_command(Command {
+        name: "session-rename".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name"), ArgSpec::required("new-name")],
+        description: "Rename a conversation session".to_string(),
+        usage_example: "@session-rename(old-name, new-name)".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                println!("Usage: @session-rename(session-name, new-name)");
+                return Ok(None);
+            }
+            let old_name = &params[0];
+            let new_name = &params[1];
+            match session::rename_session(old_name, new_name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error renaming session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Merge sessions command
+    register_command(Command {
+        name: "session-merge".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("first"), ArgSpec::required("second"), ArgSpec::optional("merged-name")],
+        description: "Merge the memory of two sessions into the second one, or into a new session".to_string(),
+        usage_example: "@session-merge(research, project-work, [merged-name])".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                println!("Usage: @session-merge(first-session, second-session, [merged-name])");
+                return Ok(None);
+            }
+            let first = &params[0];
+            let second = &params[1];
+            let into = params.get(2).map(|name| name.as_str());
+            match session::merge_sessions(first, second, into) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error merging sessions: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs, Effect::Destructive],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Archive session command
+    register_command(Command {
+        name: "session-archive".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
+        description: "Archive a session so it is hidden from @session-list".to_string(),
+        usage_example: "@session-archive(old-project)".to_string(),
+        handler: sync_handler(|params| {
+            if params.is_empty() {
+                println!("Usage: @session-archive(session-name)");
+                return Ok(None);
+            }
+            let session_name = &params[0];
+            match session::archive_session(session_name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error archiving session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Unarchive session command
+    register_command(Command {
+        name: "session-unarchive".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
+        description: "Restore an archived session".to_string(),
+        usage_example: "@session-unarchive(old-project)".to_string(),
+        handler: sync_handler(|params| {
+            if params.is_empty() {
+                println!("Usage: @session-unarchive(session-name)");
+                return Ok(None);
+            }
+            let session_name = &params[0];
+            match session::unarchive_session(session_name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error restoring session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Encrypt session command
+    register_command(Command {
+        name: "session-encrypt".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
+        description: "Encrypt a session with a passphrase (asked for in a prompt)".to_string(),
+        usage_example: "@session-encrypt(project-work)".to_string(),
+        handler: sync_handler(|params| {
+            if params.is_empty() {
+                println!("Usage: @session-encrypt(session-name)");
+                return Ok(None);
+            }
+            let session_name = &params[0];
+            match session::request_session_encryption(session_name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error encrypting session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Decrypt session command
+    register_command(Command {
+        name: "session-decrypt".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name")],
+        description: "Store an unlocked encrypted session in plain text again".to_string(),
+        usage_example: "@session-decrypt(project-work)".to_string(),
+        handler: sync_handler(|params| {
+            if params.is_empty() {
+                println!("Usage: @session-decrypt(session-name)");
+                return Ok(None);
+            }
+            let session_name = &params[0];
+            match session::decrypt_session(session_name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error decrypting session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Pack session command
+    register_command(Command {
+        name: "session-pack".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name"), ArgSpec::required("bundle").path()],
+        description: "Pack a session and the files it read into a portable bundle".to_string(),
+        usage_example: "@session-pack(project-work, ./project-work.tar)".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                println!("Usage: @session-pack(session-name, bundle-path)");
+                return Ok(None);
+            }
+            match session::pack_session(&params[0], &params[1]) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error packing session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Unpack session command
+    register_command(Command {
+        name: "session-unpack".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("bundle").path(), ArgSpec::optional("new-name")],
+        description: "Restore a session bundle as a new session".to_string(),
+        usage_example: "@session-unpack(./project-work.tar, [new-name])".to_string(),
+        handler: sync_handler(|params| {
+            if params.is_empty() {
+                println!("Usage: @session-unpack(bundle-path, [new-name])");
+                return Ok(None);
+            }
+            let name = params.get(1).map(|name| name.as_str());
+            match session::unpack_session(&params[0], name) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error unpacking session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::ReadFs],
+        reads_input: false,
+        autocomplete_handler: Some(autocomplete_file_path),
+    });
+
+    // Session statistics command
+    register_command(Command {
+        name: "session-stats".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::optional("name"), ArgSpec::optional("json-path").path()],
+        description: "Show exchanges, tokens, cost, latency and activity of a session (optionally export as JSON)".to_string(),
+        usage_example: "@session-stats([session-name], [stats.json])".to_string(),
+        handler: closure_handler(|context, params| {
+            let name = params
+                .first()
+                .map(|name| name.as_str())
+                .filter(|name| !name.is_empty())
+                .or(context.session.as_deref());
+            let json_path = params.get(1).map(|path| path.as_str());
+            match session::session_stats(name, json_path) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error computing session stats: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::WriteFs],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    // Session replay commands
+    register_command(Command {
+        name: "session-replay".to_string(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("name"), ArgSpec::required("model")],
+        description: "Re-run a session's questions against another model into a derived session, with a side-by-side report".to_string(),
+        usage_example: "@session-replay(project-work, anthropic/claude-3.5-sonnet)".to_string(),
+        handler: sync_handler(|params| {
+            if params.len() < 2 {
+                return Ok(Some("Usage: @session-replay(session-name, model)".to_string()));
+            }
+            match session_replay::start_replay(&params[0], &params[1]) {
+                Ok(message) => Ok(Some(message)),
+                Err(e) => Ok(Some(format!("Error replaying session: {}", e))),
+            }
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![Effect::Network, Effect::WriteFs],
+        reads_input: false,
+        autocomplete_handler: None,
+    });
+
+    register_command(Command {
+        name: "session-replay-status".to_string(),
+        sigil: Sigil::At,
+        args: vec![],
+        description: "Show the progress of session replays".to_string(),
+        usage_example: "@session-replay-status()".to_string(),
+        handler: sync_handler(|_| match session_replay::replay_status() {
+            Ok(message) => Ok(Some(message)),
+            Err(e) => Ok(Some(format!("Error reading replay status: {}", e))),
+        }),
+        section: "session".to_string(),
+        command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 }
\ No newline at end of file
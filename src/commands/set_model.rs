@@ -26,6 +26,12 @@ pub async fn initialize_models() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The context length of `model_id`, if the model list has it.
+pub fn context_length(model_id: &str) -> Option<u64> {
+    let models = MODELS.lock().unwrap();
+    models.iter().find(|model| model.id == model_id).and_then(|model| model.context_length)
+}
+
 pub fn handle_set_model(command: &str) -> Result<(), Box<dyn std::error::Error>> {
     // Extract filter from parentheses format
     let filter_match = Regex::new(r"@set-model\(\s*(?:(.+))?\s*\)")
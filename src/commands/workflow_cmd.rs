@@ -1,17 +1,18 @@
-use crate::commands_registry::{register_command, Command, CommandType};
+use crate::command_output::CommandOutput;
+use crate::commands_registry::{ArgSpec, Command, CommandType, Sigil, async_handler, register_command, sync_handler};
 use crate::workflow::{get_workflow_engine, VerificationStrategy};
-use regex::Regex;
 
 pub fn register_workflow_commands() {
     // Start workflow command
     register_command(Command {
         name: "start-loop".to_string(),
-        pattern: Regex::new(r"@start-loop\(\s*(.+?)\s*,\s*(\d+)\s*,\s*(\w+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("goal"), ArgSpec::required("max-iterations"), ArgSpec::required("strategy")],
         description: "Start an autonomous workflow loop".to_string(),
         usage_example: "@start-loop(Set up Rust project, 10, file_exists)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.len() < 3 {
-                return Ok(Some("Usage: @start-loop(goal, max_iterations, verification_strategy)".to_string()));
+                return Ok(Some("Usage: @start-loop(goal, max_iterations, verification_strategy)".into()));
             }
 
             let goal = &params[0];
@@ -25,63 +26,65 @@ pub fn register_workflow_commands() {
             };
 
             let engine = get_workflow_engine();
-            let runtime = tokio::runtime::Handle::current();
-
-            match runtime.block_on(engine.start_workflow(goal, max_iterations, verification_strategy)) {
+            match engine.start_workflow(goal, max_iterations, verification_strategy).await {
                 Ok(plan_id) => {
                     Ok(Some(format!(
                         "Started workflow: {}\nGoal: {}\nMax iterations: {}\nPlan ID: {}",
                         plan_id, goal, max_iterations, plan_id
-                    )))
+                    ).into()))
                 }
-                Err(e) => Ok(Some(format!("Failed to start workflow: {}", e))),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to start workflow: {}", e)))),
             }
-        },
+        })),
         section: "workflow".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Continue workflow command
     register_command(Command {
         name: "continue-loop".to_string(),
-        pattern: Regex::new(r"@continue-loop\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Continue executing workflow steps".to_string(),
         usage_example: "@continue-loop(plan_id)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.is_empty() {
-                return Ok(Some("Usage: @continue-loop(plan_id)".to_string()));
+                return Ok(Some("Usage: @continue-loop(plan_id)".into()));
             }
 
             let plan_id = &params[0];
             let engine = get_workflow_engine();
-            let runtime = tokio::runtime::Handle::current();
-
-            match runtime.block_on(engine.continue_workflow(plan_id)) {
+            match engine.continue_workflow(plan_id).await {
                 Ok(should_continue) => {
                     if should_continue {
-                        Ok(Some(format!("Workflow {} continued. Execute @continue-loop({}) for next iteration.", plan_id, plan_id)))
+                        Ok(Some(format!("Workflow {} continued. Execute @continue-loop({}) for next iteration.", plan_id, plan_id).into()))
                     } else {
-                        Ok(Some(format!("Workflow {} completed successfully!", plan_id)))
+                        Ok(Some(format!("Workflow {} completed successfully!", plan_id).into()))
                     }
                 }
-                Err(e) => Ok(Some(format!("Failed to continue workflow: {}", e))),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to continue workflow: {}", e)))),
             }
-        },
+        })),
         section: "workflow".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Workflow status command
     register_command(Command {
         name: "workflow-status".to_string(),
-        pattern: Regex::new(r"@workflow-status\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Get status of a workflow".to_string(),
         usage_example: "@workflow-status(plan_id)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
-                return Ok(Some("Usage: @workflow-status(plan_id)".to_string()));
+                return Ok(Some("Usage: @workflow-status(plan_id)".into()));
             }
 
             let plan_id = &params[0];
@@ -115,137 +118,143 @@ pub fn register_workflow_commands() {
                             .collect::<Vec<_>>()
                             .join("\n")
                     );
-                    Ok(Some(status_text))
+                    Ok(Some(status_text.into()))
                 }
-                None => Ok(Some(format!("Workflow {} not found", plan_id))),
+                None => Ok(Some(CommandOutput::Error(format!("Workflow {} not found", plan_id)))),
             }
-        },
+        }),
         section: "workflow".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // List workflows command
     register_command(Command {
         name: "list-workflows".to_string(),
-        pattern: Regex::new(r"@list-workflows\(\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![],
         description: "List all active workflows".to_string(),
         usage_example: "@list-workflows()".to_string(),
-        handler: |_| {
+        handler: sync_handler(|_| {
             let engine = get_workflow_engine();
             let workflow_ids = engine.list_workflows();
 
             if workflow_ids.is_empty() {
-                Ok(Some("No active workflows".to_string()))
+                Ok(Some("No active workflows".into()))
             } else {
-                let mut result = "Active workflows:\n".to_string();
-                for (i, id) in workflow_ids.iter().enumerate() {
-                    if let Some(plan) = engine.get_workflow_status(id) {
-                        result.push_str(&format!(
-                            "{}. {} - {} ({:?})\n",
-                            i + 1,
-                            id,
-                            plan.goal,
-                            plan.status
-                        ));
-                    }
-                }
-                Ok(Some(result))
+                let rows = workflow_ids
+                    .iter()
+                    .filter_map(|id| engine.get_workflow_status(id))
+                    .map(|plan| vec![plan.id.clone(), plan.goal.clone(), format!("{:?}", plan.status)])
+                    .collect();
+                Ok(Some(CommandOutput::table(&["Plan", "Goal", "Status"], rows)))
             }
-        },
+        }),
         section: "workflow".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Pause workflow command
     register_command(Command {
         name: "pause-workflow".to_string(),
-        pattern: Regex::new(r"@pause-workflow\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Pause a running workflow".to_string(),
         usage_example: "@pause-workflow(plan_id)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
-                return Ok(Some("Usage: @pause-workflow(plan_id)".to_string()));
+                return Ok(Some("Usage: @pause-workflow(plan_id)".into()));
             }
 
             let plan_id = &params[0];
             let engine = get_workflow_engine();
 
             match engine.pause_workflow(plan_id) {
-                Ok(()) => Ok(Some(format!("Workflow {} paused", plan_id))),
-                Err(e) => Ok(Some(format!("Failed to pause workflow: {}", e))),
+                Ok(()) => Ok(Some(format!("Workflow {} paused", plan_id).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to pause workflow: {}", e)))),
             }
-        },
+        }),
         section: "workflow".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Resume workflow command
     register_command(Command {
         name: "resume-workflow".to_string(),
-        pattern: Regex::new(r"@resume-workflow\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Resume a paused workflow".to_string(),
         usage_example: "@resume-workflow(plan_id)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
-                return Ok(Some("Usage: @resume-workflow(plan_id)".to_string()));
+                return Ok(Some("Usage: @resume-workflow(plan_id)".into()));
             }
 
             let plan_id = &params[0];
             let engine = get_workflow_engine();
 
             match engine.resume_workflow(plan_id) {
-                Ok(()) => Ok(Some(format!("Workflow {} resumed", plan_id))),
-                Err(e) => Ok(Some(format!("Failed to resume workflow: {}", e))),
+                Ok(()) => Ok(Some(format!("Workflow {} resumed", plan_id).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to resume workflow: {}", e)))),
             }
-        },
+        }),
         section: "workflow".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Stop workflow command
     register_command(Command {
         name: "stop-workflow".to_string(),
-        pattern: Regex::new(r"@stop-workflow\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Stop a workflow".to_string(),
         usage_example: "@stop-workflow(plan_id)".to_string(),
-        handler: |params| {
+        handler: sync_handler(|params| {
             if params.is_empty() {
-                return Ok(Some("Usage: @stop-workflow(plan_id)".to_string()));
+                return Ok(Some("Usage: @stop-workflow(plan_id)".into()));
             }
 
             let plan_id = &params[0];
             let engine = get_workflow_engine();
 
             match engine.stop_workflow(plan_id) {
-                Ok(()) => Ok(Some(format!("Workflow {} stopped", plan_id))),
-                Err(e) => Ok(Some(format!("Failed to stop workflow: {}", e))),
+                Ok(()) => Ok(Some(format!("Workflow {} stopped", plan_id).into())),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to stop workflow: {}", e)))),
             }
-        },
+        }),
         section: "workflow".to_string(),
         command_type: CommandType::NotLLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Verify workflow command
     register_command(Command {
         name: "verify-workflow".to_string(),
-        pattern: Regex::new(r"@verify-workflow\(\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id")],
         description: "Verify workflow progress and success".to_string(),
         usage_example: "@verify-workflow(plan_id)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.is_empty() {
-                return Ok(Some("Usage: @verify-workflow(plan_id)".to_string()));
+                return Ok(Some("Usage: @verify-workflow(plan_id)".into()));
             }
 
             let plan_id = &params[0];
             let engine = get_workflow_engine();
-            let runtime = tokio::runtime::Handle::current();
-
-            match runtime.block_on(engine.verify_progress(plan_id)) {
+            match engine.verify_progress(plan_id).await {
                 Ok(result) => {
                     Ok(Some(format!(
                         "Verification result for {}:\nSuccess: {}\nScore: {:.1}%\nMessage: {}",
@@ -253,33 +262,34 @@ pub fn register_workflow_commands() {
                         result.success,
                         result.score * 100.0,
                         result.message
-                    )))
+                    ).into()))
                 }
-                Err(e) => Ok(Some(format!("Failed to verify workflow: {}", e))),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to verify workflow: {}", e)))),
             }
-        },
+        })),
         section: "workflow".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 
     // Execute specific step command
     register_command(Command {
         name: "execute-step".to_string(),
-        pattern: Regex::new(r"@execute-step\(\s*(\S+)\s*,\s*(\S+)\s*\)").unwrap(),
+        sigil: Sigil::At,
+        args: vec![ArgSpec::required("plan-id"), ArgSpec::required("step-id")],
         description: "Execute a specific workflow step".to_string(),
         usage_example: "@execute-step(plan_id, step_id)".to_string(),
-        handler: |params| {
+        handler: async_handler(|params| Box::pin(async move {
             if params.len() < 2 {
-                return Ok(Some("Usage: @execute-step(plan_id, step_id)".to_string()));
+                return Ok(Some("Usage: @execute-step(plan_id, step_id)".into()));
             }
 
             let plan_id = &params[0];
             let step_id = &params[1];
             let engine = get_workflow_engine();
-            let runtime = tokio::runtime::Handle::current();
-
-            match runtime.block_on(engine.execute_step(plan_id, step_id)) {
+            match engine.execute_step(plan_id, step_id).await {
                 Ok(result) => {
                     Ok(Some(format!(
                         "Step {} execution result:\nSuccess: {}\nOutput: {}\nError: {}",
@@ -287,13 +297,15 @@ pub fn register_workflow_commands() {
                         result.success,
                         result.output.unwrap_or_else(|| "None".to_string()),
                         result.error.unwrap_or_else(|| "None".to_string())
-                    )))
+                    ).into()))
                 }
-                Err(e) => Ok(Some(format!("Failed to execute step: {}", e))),
+                Err(e) => Ok(Some(CommandOutput::Error(format!("Failed to execute step: {}", e)))),
             }
-        },
+        })),
         section: "workflow".to_string(),
         command_type: CommandType::LLM,
+        effects: vec![],
+        reads_input: false,
         autocomplete_handler: None,
     });
 }
\ No newline at end of file
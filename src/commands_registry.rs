@@ -1,13 +1,141 @@
-use crate::terminal;
+use crate::chat::{self, Prompt};
+use crate::command_output::CommandOutput;
+use crate::command_parser::{self, ArgValue, Invocation, Pipeline};
+use crate::configuration::{self, Config};
+use crate::files::files;
+use crate::permissions::{self, Effect};
+use crate::{session, terminal};
 use lazy_static::lazy_static;
-use regex::Regex;
+use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::fmt;
-use std::sync::Mutex;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
-pub type CommandHandlerOutputType = Result<Option<String>, Box<dyn std::error::Error>>;
+pub use crate::command_parser::{ArgSpec, Sigil};
+
+pub type CommandHandlerOutputType = Result<Option<CommandOutput>, Box<dyn std::error::Error>>;
+
+/// What a plain handler function returns: a `CommandOutput` or anything that converts
+/// into one, like the `String` of a text result.
+pub type HandlerResult<T> = Result<Option<T>, Box<dyn std::error::Error>>;
+
+pub type SyncCommandHandler<T> = fn(&[String]) -> HandlerResult<T>;
+
+/// Future returned by a command handler.
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = CommandHandlerOutputType> + 'a>>;
+
+pub type AsyncCommandHandler = fn(Vec<String>) -> CommandFuture<'static>;
+
+/// What a command can use besides its arguments while it runs.
+pub struct CommandContext {
+    /// Name of the active session, if any
+    pub session: Option<String>,
+    config: OnceCell<Config>,
+    /// Output of the previous stage when the command runs in a pipeline
+    input: Option<CommandOutput>,
+}
+
+impl CommandContext {
+    pub fn current() -> Self {
+        let session = session::get_session_manager().lock().unwrap().get_current_session().cloned();
+        Self {
+            session,
+            config: OnceCell::new(),
+            input: None,
+        }
+    }
+
+    fn with_input(input: Option<CommandOutput>) -> Self {
+        Self {
+            input,
+            ..Self::current()
+        }
+    }
+
+    /// The output of the previous pipeline stage; only set for commands with `reads_input`.
+    pub fn input(&self) -> Option<&CommandOutput> {
+        self.input.as_ref()
+    }
+
+    /// The items of the piped input: paths of a file list, lines of text.
+    pub fn input_lines(&self) -> Option<Vec<String>> {
+        self.input().map(CommandOutput::items)
+    }
+
+    /// The effective configuration, loaded on first use.
+    pub fn config(&self) -> Result<&Config, Box<dyn std::error::Error>> {
+        if self.config.get().is_none() {
+            let _ = self.config.set(configuration::get_effective_config()?);
+        }
+        Ok(self.config.get().unwrap())
+    }
+
+    /// The prompts of the current conversation.
+    pub fn memory(&self) -> &'static Mutex<HashMap<String, Prompt>> {
+        chat::get_memory()
+    }
+}
+
+/// Runs a command. Implement it directly for handlers that carry state (an MCP tool, a
+/// user macro); plain functions are wrapped with `sync_handler` or `async_handler`.
+pub trait CommandHandler: Send + Sync {
+    fn call<'a>(&'a self, context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a>;
+}
+
+fn into_output<T: Into<CommandOutput>>(output: HandlerResult<T>) -> CommandHandlerOutputType {
+    output.map(|output| output.map(Into::into))
+}
+
+struct SyncFnHandler<T>(SyncCommandHandler<T>);
+
+impl<T: Into<CommandOutput> + 'static> CommandHandler for SyncFnHandler<T> {
+    fn call<'a>(&'a self, _context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        let output = into_output((self.0)(&parameters));
+        Box::pin(async move { output })
+    }
+}
+
+struct AsyncFnHandler(AsyncCommandHandler);
+
+impl CommandHandler for AsyncFnHandler {
+    fn call<'a>(&'a self, _context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        (self.0)(parameters)
+    }
+}
+
+struct ClosureHandler<F, T>(F, PhantomData<fn() -> T>);
+
+impl<F, T> CommandHandler for ClosureHandler<F, T>
+where
+    F: Fn(&CommandContext, &[String]) -> HandlerResult<T> + Send + Sync,
+    T: Into<CommandOutput>,
+{
+    fn call<'a>(&'a self, context: &'a CommandContext, parameters: Vec<String>) -> CommandFuture<'a> {
+        let output = into_output((self.0)(context, &parameters));
+        Box::pin(async move { output })
+    }
+}
+
+pub fn sync_handler<T: Into<CommandOutput> + 'static>(handler: SyncCommandHandler<T>) -> Arc<dyn CommandHandler> {
+    Arc::new(SyncFnHandler(handler))
+}
+
+pub fn async_handler(handler: AsyncCommandHandler) -> Arc<dyn CommandHandler> {
+    Arc::new(AsyncFnHandler(handler))
+}
+
+/// A synchronous handler that may capture state.
+pub fn closure_handler<F, T>(handler: F) -> Arc<dyn CommandHandler>
+where
+    F: Fn(&CommandContext, &[String]) -> HandlerResult<T> + Send + Sync + 'static,
+    T: Into<CommandOutput> + 'static,
+{
+    Arc::new(ClosureHandler(handler, PhantomData))
+}
 
-pub type CommandHandler = fn(&[String]) -> CommandHandlerOutputType;
 use rustyline::completion::Pair as Completion;
 use rustyline::error::ReadlineError;
 
@@ -22,33 +150,45 @@ pub enum CommandType {
 
 #[derive(Clone)]
 pub struct Command {
+    /// Name as invoked, without the sigil
     pub name: String,
-    pub pattern: Regex,
+    pub sigil: Sigil,
+    pub args: Vec<ArgSpec>,
     pub description: String,
     pub usage_example: String,
-    pub handler: CommandHandler,
+    pub handler: Arc<dyn CommandHandler>,
     pub section: String,
     pub command_type: CommandType,
+    /// What running the command can do beyond producing output; checked against the
+    /// `[permissions]` policy before it runs
+    pub effects: Vec<Effect>,
+    /// Whether the command takes the output of a previous stage; only such commands may
+    /// follow a `|`
+    pub reads_input: bool,
     pub autocomplete_handler: Option<AutocompleteHandler>, // Add autocomplete handler field
 }
 
 impl Command {
     pub fn new(
         name: String,
-        pattern: Regex,
+        sigil: Sigil,
+        args: Vec<ArgSpec>,
         description: String,
         usage_example: String,
-        handler: CommandHandler,
+        handler: Arc<dyn CommandHandler>,
         section: String,
     ) -> Self {
         Self {
             name,
-            pattern,
+            sigil,
+            args,
             description,
             usage_example,
             handler,
             section,
             command_type: CommandType::NotLLM,
+            effects: vec![],
+            reads_input: false,
             autocomplete_handler: None,
         }
     }
@@ -63,20 +203,18 @@ impl fmt::Debug for Command {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Command")
             .field("name", &self.name)
-            .field("pattern", &self.pattern.as_str())
+            .field("sigil", &self.sigil)
+            .field("args", &self.args)
             .field("description", &self.description)
             .field("usage_example", &self.usage_example)
             .field("section", &self.section)
+            .field("effects", &self.effects)
+            .field("reads_input", &self.reads_input)
             .field("autocomplete_handler", &self.autocomplete_handler.is_some())
             .finish()
     }
 }
 
-pub struct CommandResult {
-    pub command_name: String,
-    pub parameters: Vec<String>,
-}
-
 lazy_static! {
     static ref COMMAND_REGISTRY: Mutex<HashMap<String, Command>> = Mutex::new(HashMap::new());
 }
@@ -86,6 +224,18 @@ pub fn register_command(command: Command) {
     registry.insert(command.name.clone(), command);
 }
 
+/// Remove a command registered at runtime, returning it if it existed.
+pub fn unregister_command(name: &str) -> Option<Command> {
+    let mut registry = COMMAND_REGISTRY.lock().unwrap();
+    registry.remove(name)
+}
+
+/// Whether `name` may be used for a command or parameter defined in a macro or script:
+/// a letter followed by letters, digits, `-` and `_`.
+pub fn valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic()) && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
 pub fn get_command(name: &str) -> Option<Command> {
     let registry = COMMAND_REGISTRY.lock().unwrap();
     registry.get(name).cloned()
@@ -96,45 +246,125 @@ pub fn get_all_commands() -> Vec<Command> {
     registry.values().cloned().collect()
 }
 
-pub fn parse_command(input: &str) -> Option<CommandResult> {
+/// The command invoked as `name` with `sigil`. Shell lines go to the one `>` command.
+pub fn find_command(sigil: Sigil, name: &str) -> Option<Command> {
+    if sigil != Sigil::Shell {
+        return get_command(name).filter(|command| command.sigil == sigil);
+    }
     let registry = COMMAND_REGISTRY.lock().unwrap();
+    registry.values().find(|command| command.sigil == Sigil::Shell).cloned()
+}
 
-    for command in registry.values() {
-        if let Some(captures) = command.pattern.captures(input) {
-            let mut parameters = Vec::new();
-
-            // Skip the first capture (which is the entire match)
-            for i in 1..captures.len() {
-                if let Some(capture) = captures.get(i) {
-                    parameters.push(capture.as_str().to_string());
-                }
-            }
+/// No registered command has the invoked name and sigil.
+#[derive(Debug)]
+pub struct CommandNotFound;
+
+impl std::fmt::Display for CommandNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command not found")
+    }
+}
+
+impl std::error::Error for CommandNotFound {}
+
+/// Section of the filter commands (`@head`, `@grep`, ...). A pipeline ending in filters is
+/// sent to the LLM or not like the stage the filters read from.
+pub const PIPE_SECTION: &str = "pipe";
+
+type InvocationFuture<'a> = Pin<Box<dyn Future<Output = Result<CommandHandlerResult, Box<dyn std::error::Error>>> + 'a>>;
+
+/// Run a parsed invocation. Nested calls in its arguments run first, left to right, and
+/// their output becomes the argument value. `piped` is the output of the previous stage.
+/// The permission policy is checked before the command runs.
+fn execute_invocation<'a>(invocation: &'a Invocation, input: &'a str, piped: Option<CommandOutput>) -> InvocationFuture<'a> {
+    Box::pin(async move {
+        let command = find_command(invocation.sigil, &invocation.name).ok_or(CommandNotFound)?;
 
-            return Some(CommandResult {
-                command_name: command.name.clone(),
-                parameters,
+        let mut values = Vec::with_capacity(invocation.args.len());
+        for argument in &invocation.args {
+            values.push(match &argument.value {
+                ArgValue::Text(text) => text.clone(),
+                ArgValue::Call(nested) => execute_invocation(nested, input, None)
+                    .await?
+                    .command_output?
+                    .map(|output| output.render_llm())
+                    .unwrap_or_default(),
             });
         }
+        let parameters = command_parser::bind_arguments(&command.args, invocation, values, input)
+            .map_err(|e| e.render(input))?;
+
+        let target = match invocation.sigil {
+            Sigil::Shell => format!(">{}", parameters.join(" ")),
+            sigil => format!("{}{}", sigil.as_char(), invocation.name),
+        };
+        let paths: Vec<String> = command
+            .args
+            .iter()
+            .zip(&parameters)
+            .filter(|(spec, value)| spec.path && !value.is_empty())
+            // Rules match the file, not a line range given with it
+            .map(|(_, value)| files::split_line_range(value).0.to_string())
+            .collect();
+        if let Err(e) = permissions::check(&command.effects, &target, &paths) {
+            return Ok(CommandHandlerResult { command_output: Err(e), command });
+        }
+
+        let context = CommandContext::with_input(piped);
+        let command_output = command.handler.call(&context, parameters).await;
+        Ok(CommandHandlerResult { command_output, command })
+    })
+}
+
+/// Run the stages of a pipeline, each with the output of the one before as input. The
+/// result is that of the last stage, or of the first stage that failed. Every stage after
+/// a `|` is checked before the first one runs.
+async fn execute_pipeline(pipeline: &Pipeline, input: &str) -> Result<CommandHandlerResult, Box<dyn std::error::Error>> {
+    for stage in pipeline.stages.iter().skip(1) {
+        let command = find_command(stage.sigil, &stage.name)
+            .ok_or_else(|| format!("Unknown command in pipeline: {}{}", stage.sigil.as_char(), stage.name))?;
+        if !command.reads_input {
+            let error = format!("{}{} does not read piped input", stage.sigil.as_char(), stage.name);
+            return Ok(CommandHandlerResult { command_output: Err(error.into()), command });
+        }
     }
 
-    None
+    let mut piped = None;
+    let mut source_type: Option<CommandType> = None;
+    let mut result = None;
+
+    for stage in &pipeline.stages {
+        let mut stage_result = execute_invocation(stage, input, piped.take()).await?;
+        if stage_result.command.section == PIPE_SECTION {
+            if let Some(command_type) = &source_type {
+                stage_result.command.command_type = command_type.clone();
+            }
+        } else {
+            source_type = Some(stage_result.command.command_type.clone());
+        }
+
+        match &stage_result.command_output {
+            Ok(output) => piped = Some(output.clone().unwrap_or_else(|| CommandOutput::Text(String::new()))),
+            Err(_) => return Ok(stage_result),
+        }
+        result = Some(stage_result);
+    }
+    result.ok_or_else(|| "empty pipeline".into())
 }
 
-pub fn execute_command(
+pub async fn execute_command(
     input: &str,
 ) -> Result<Option<CommandHandlerResult>, Box<dyn std::error::Error>> {
-    if let Some(command_result) = parse_command(input) {
-        if let Some(command) = get_command(&command_result.command_name) {
-            let command_handler_output = (command.handler)(&command_result.parameters);
-
-            return Ok(Some(CommandHandlerResult {
-                command_output: command_handler_output,
-                command: command,
-            }));
+    let pipeline = command_parser::parse_pipeline(input).map_err(|e| e.render(input))?;
+    let invocation = &pipeline.stages[0];
+    match execute_pipeline(&pipeline, input).await {
+        Ok(result) => Ok(Some(result)),
+        // Unknown nested commands are reported like other argument errors
+        Err(e) if e.is::<CommandNotFound>() && find_command(invocation.sigil, &invocation.name).is_some() => {
+            Err(format!("Command not found in the arguments of {}{}", invocation.sigil.as_char(), invocation.name).into())
         }
+        Err(e) => Err(e),
     }
-
-    Err("Command not found".into())
 }
 
 // Modified print_help function to group commands by section
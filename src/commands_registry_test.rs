@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod tests {
+    use crate::command_output::CommandOutput;
+    use crate::commands::macro_cmd::register_macro;
+    use crate::commands::pipe_cmd::register_pipe_commands;
+    use crate::commands_registry::*;
+    use crate::configuration::MacroDefinition;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_sync_and_async_handlers() {
+        register_command(Command::new(
+            "test-sync-echo".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Echo the argument".to_string(),
+            "@test-sync-echo(text)".to_string(),
+            sync_handler(|params| Ok(Some(format!("sync {}", params[0])))),
+            "test".to_string(),
+        ));
+        register_command(Command::new(
+            "test-async-echo".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Echo the argument after yielding".to_string(),
+            "@test-async-echo(text)".to_string(),
+            async_handler(|params| {
+                Box::pin(async move {
+                    tokio::task::yield_now().await;
+                    Ok(Some(format!("async {}", params[0]).into()))
+                })
+            }),
+            "test".to_string(),
+        ));
+
+        let result = execute_command("@test-sync-echo(one)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("sync one".into()));
+
+        let result = execute_command("@test-async-echo(two)").await.unwrap().unwrap();
+        assert_eq!(result.command.name, "test-async-echo");
+        assert_eq!(result.command_output.unwrap(), Some("async two".into()));
+
+        assert!(execute_command("@test-missing(three)").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stateful_handlers_and_unregister() {
+        // A handler that captures state, which a bare function pointer cannot
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        register_command(Command::new(
+            "test-counter".to_string(),
+            Sigil::At,
+            vec![],
+            "Count calls".to_string(),
+            "@test-counter()".to_string(),
+            closure_handler(move |_, _| {
+                let count = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(Some(format!("call {}", count)))
+            }),
+            "test".to_string(),
+        ));
+
+        execute_command("@test-counter()").await.unwrap();
+        let result = execute_command("@test-counter()").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("call 2".into()));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        assert!(unregister_command("test-counter").is_some());
+        assert!(unregister_command("test-counter").is_none());
+        assert!(get_command("test-counter").is_none());
+        assert!(execute_command("@test-counter()").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nested_calls_and_named_arguments() {
+        register_command(Command::new(
+            "test-join".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("left"), ArgSpec::optional("separator"), ArgSpec::required("right")],
+            "Join two values".to_string(),
+            "@test-join(left, separator, right)".to_string(),
+            sync_handler(|params| Ok(Some(params.join("|")))),
+            "test".to_string(),
+        ));
+        register_command(Command::new(
+            "test-upper".to_string(),
+            Sigil::Bang,
+            vec![ArgSpec::required("text")],
+            "Upper-case a value".to_string(),
+            "!test-upper(text)".to_string(),
+            sync_handler(|params| Ok(Some(params[0].to_uppercase()))),
+            "test".to_string(),
+        ));
+
+        let result = execute_command(r#"@test-join(right="b, c", left=!test-upper(a))"#).await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("A||b, c".into()));
+
+        let result = execute_command("@test-join(x, -, @test-join(y, right=z))").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("x|-|y||z".into()));
+
+        // The sigil is part of the command: !test-upper is not @test-upper
+        let error = execute_command("@test-upper(a)").await.err().unwrap();
+        assert!(error.is::<CommandNotFound>());
+
+        let error = execute_command("@test-join(a, b, c, d)").await.err().unwrap();
+        assert!(error.to_string().starts_with("too many arguments for @test-join"));
+        let error = execute_command("@test-join(a, @test-missing(b))").await.err().unwrap();
+        assert!(error.to_string().contains("Command not found in the arguments of @test-join"));
+    }
+
+    #[tokio::test]
+    async fn test_macros() {
+        register_command(Command::new(
+            "test-shout".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Upper-case a value".to_string(),
+            "@test-shout(text)".to_string(),
+            sync_handler(|params| Ok(Some(params[0].to_uppercase()))),
+            "test".to_string(),
+        ));
+        let definition = MacroDefinition {
+            description: "Greet someone".to_string(),
+            params: vec!["name".to_string(), "greeting?".to_string()],
+            body: "Say {{greeting}} to @test-shout({{name}})!".to_string(),
+            section: "test".to_string(),
+            autocomplete: Some("memory".to_string()),
+            local: false,
+        };
+        register_macro("test-greet", &definition).unwrap();
+
+        let command = get_command("test-greet").unwrap();
+        assert_eq!(command.usage_example, "@test-greet(name, greeting?)");
+        assert_eq!(command.command_type, CommandType::LLM);
+        assert!(command.autocomplete_handler.is_some());
+
+        let result = execute_command("@test-greet(bob, hello)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("Say hello to BOB!".into()));
+        let result = execute_command("@test-greet(name=ann)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("Say  to ANN!".into()));
+
+        // Names of existing commands, bad parameters and cycles are refused
+        assert!(register_macro("test-greet", &definition).is_err());
+        let invalid = MacroDefinition {
+            params: vec!["a?".to_string(), "b".to_string()],
+            ..definition.clone()
+        };
+        assert!(register_macro("test-invalid", &invalid).is_err());
+        let cycle = MacroDefinition {
+            params: vec![],
+            body: "@test-cycle()".to_string(),
+            ..definition
+        };
+        register_macro("test-cycle", &cycle).unwrap();
+        let result = execute_command("@test-cycle()").await.unwrap().unwrap();
+        let error = result.command_output.unwrap_err().to_string();
+        assert_eq!(error, "Macro cycle: @test-cycle → @test-cycle");
+    }
+
+    #[tokio::test]
+    async fn test_macro_arguments_only_fill_their_slot() {
+        crate::commands::bash_cmd::register_bash_command();
+        register_command(Command::new(
+            "test-echo".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Echo a value in brackets".to_string(),
+            "@test-echo(text)".to_string(),
+            sync_handler(|params| Ok(Some(format!("[{}]", params[0])))),
+            "test".to_string(),
+        ));
+        let definition = MacroDefinition {
+            description: String::new(),
+            params: vec!["value".to_string()],
+            body: "Text {{value}} and @test-echo({{value}})\n>printf '%s' {{value}}".to_string(),
+            section: "test".to_string(),
+            autocomplete: None,
+            local: false,
+        };
+        register_macro("test-slots", &definition).unwrap();
+
+        // Neither a call in the text, a second argument nor a shell command get through
+        let shell_line = ">printf '%s' 'a), @test-echo(b; echo injected'";
+        crate::permissions::grant(
+            &crate::permissions::ConfirmationRequest { action: shell_line.to_string(), effects: vec![] },
+            true,
+        );
+        let result = execute_command(r#"@test-slots("a), @test-echo(b; echo injected")"#).await.unwrap().unwrap();
+        assert_eq!(
+            result.command_output.unwrap(),
+            Some("Text a), @test-echo(b; echo injected and [a), @test-echo(b; echo injected]\na), @test-echo(b; echo injected".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_shell_lines_need_the_command_after_the_sigil() {
+        crate::commands::bash_cmd::register_bash_command();
+        let definition = MacroDefinition {
+            description: String::new(),
+            params: vec![],
+            body: "> quoted, not run\n>printf ran".to_string(),
+            section: "test".to_string(),
+            autocomplete: None,
+            local: false,
+        };
+        register_macro("test-quote", &definition).unwrap();
+        crate::permissions::grant(
+            &crate::permissions::ConfirmationRequest { action: ">printf ran".to_string(), effects: vec![] },
+            true,
+        );
+
+        // Workflow steps and scripts run commands through the registry, which reads a
+        // line the way a question and a macro body do
+        let error = execute_command("> printf ran").await.err().unwrap().to_string();
+        assert!(error.contains("has to follow `>` directly"), "{}", error);
+        let result = execute_command("@test-quote()").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("> quoted, not run\nran".into()));
+    }
+
+    #[tokio::test]
+    async fn test_pipelines() {
+        register_pipe_commands();
+        register_command(Command::new(
+            "test-list".to_string(),
+            Sigil::At,
+            vec![],
+            "List some files".to_string(),
+            "@test-list()".to_string(),
+            sync_handler(|_params| Ok(Some("\nmain.rs\nparser_test.rs\nchat.rs\nregistry_test.rs\n".to_string()))),
+            "test".to_string(),
+        ));
+        register_command(Command {
+            command_type: CommandType::LLM,
+            reads_input: true,
+            ..Command::new(
+                "test-count".to_string(),
+                Sigil::At,
+                vec![],
+                "Count the piped lines".to_string(),
+                "@test-count()".to_string(),
+                closure_handler(|context, _params| Ok(Some(format!("{} files", context.input_lines().unwrap_or_default().len())))),
+                "test".to_string(),
+            )
+        });
+        register_command(Command::new(
+            "test-ignore-input".to_string(),
+            Sigil::At,
+            vec![],
+            "Ignore the piped input".to_string(),
+            "@test-ignore-input()".to_string(),
+            sync_handler(|_params| Ok(Some("ignored".to_string()))),
+            "test".to_string(),
+        ));
+        let listed = Arc::new(AtomicUsize::new(0));
+        let counter = listed.clone();
+        register_command(Command::new(
+            "test-counted-list".to_string(),
+            Sigil::At,
+            vec![],
+            "List a file and count the calls".to_string(),
+            "@test-counted-list()".to_string(),
+            closure_handler(move |_, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(Some("main.rs".to_string()))
+            }),
+            "test".to_string(),
+        ));
+
+        let result = execute_command("@test-list() | @filter(_test) | @sort(desc) | @head(1)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("registry_test.rs".into()));
+        // Filters are sent to the LLM or not like the stage they read from
+        assert_eq!(result.command.command_type, CommandType::NotLLM);
+
+        let result = execute_command("@test-list() | @grep(^[mc]) | @test-count").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("2 files".into()));
+        assert_eq!(result.command.command_type, CommandType::LLM);
+        let result = execute_command("@test-list() | @tail(3) | @sort").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("chat.rs\nparser_test.rs\nregistry_test.rs".into()));
+
+        // File lists stay file lists through the filters
+        register_command(Command::new(
+            "test-file-list".to_string(),
+            Sigil::At,
+            vec![],
+            "List files as a file list".to_string(),
+            "@test-file-list()".to_string(),
+            sync_handler(|_params| Ok(Some(CommandOutput::FileList(vec!["b.rs".to_string(), "a.rs".to_string()])))),
+            "test".to_string(),
+        ));
+        let result = execute_command("@test-file-list() | @sort").await.unwrap().unwrap();
+        assert_eq!(
+            result.command_output.unwrap(),
+            Some(CommandOutput::FileList(vec!["a.rs".to_string(), "b.rs".to_string()]))
+        );
+
+        // Stages that ignore their input fail before any stage runs
+        let result = execute_command("@test-counted-list() | @test-ignore-input").await.unwrap().unwrap();
+        assert!(result.command_output.unwrap_err().to_string().contains("does not read piped input"));
+        assert_eq!(listed.load(Ordering::SeqCst), 0);
+
+        // Filters without input and unknown stages fail
+        let result = execute_command("@head(2)").await.unwrap().unwrap();
+        assert!(result.command_output.is_err());
+        let result = execute_command("@test-list() | @head(many)").await.unwrap().unwrap();
+        assert!(result.command_output.is_err());
+        let error = execute_command("@test-list() | @test-nothing").await.err().unwrap();
+        assert!(error.to_string().contains("Unknown command in pipeline: @test-nothing"));
+    }
+}
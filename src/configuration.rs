@@ -1,19 +1,24 @@
 use directories::UserDirs;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Read;
 use std::collections::HashMap;
+use std::sync::Mutex;
 use toml_edit;
 
+use crate::permissions::{Decision, Effect};
+use crate::project;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LlmSettings {
     pub model: String,
     #[serde(default = "default_temperature")]
-    pub temperature: f32,
+    pub temperature: f64,
     #[serde(default = "default_max_tokens")]
     pub max_tokens: u32,
     #[serde(default = "default_top_p")]
-    pub top_p: f32,
+    pub top_p: f64,
     #[serde(default)]
     pub system_prompt: Option<String>,
 }
@@ -54,6 +59,100 @@ pub struct WorkflowSettings {
     pub parallel_execution: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplaySettings {
+    #[serde(default = "default_replay_max_concurrent_requests")]
+    pub max_concurrent_requests: u32,
+    #[serde(default = "default_replay_context_exchanges")]
+    pub context_exchanges: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginSettings {
+    #[serde(default = "default_plugins_enabled")]
+    pub enabled: bool,
+    /// Plugin directories searched besides `plugins/` in the data directory
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    #[serde(default = "default_plugin_timeout")]
+    pub timeout_seconds: u32,
+    /// Time limit of the `describe` handshake at startup
+    #[serde(default = "default_plugin_describe_timeout")]
+    pub describe_timeout_seconds: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScriptingSettings {
+    #[serde(default = "default_scripting_enabled")]
+    pub enabled: bool,
+    /// Script directories searched besides `scripts/` in the data directory
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    /// Operations one script call may run before it is stopped
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+    #[serde(default = "default_script_timeout")]
+    pub timeout_seconds: u32,
+}
+
+/// Which commands may run without asking. The first rule that matches an invocation
+/// decides; without one, commands with an effect listed in `ask` need confirmation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PermissionSettings {
+    #[serde(default = "default_ask_effects")]
+    pub ask: Vec<Effect>,
+    #[serde(default)]
+    pub rules: Vec<PermissionRule>,
+}
+
+/// A permission rule. Each pattern that is set has to match: `effect` one of the command's
+/// effects, `command` the invocation (`@export`, or `>` and the shell line) and `path` one of
+/// the paths it is given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PermissionRule {
+    #[serde(default)]
+    pub effect: Option<Effect>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    pub decision: Decision,
+}
+
+/// Limits for reading files into a prompt.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileSettings {
+    /// Files larger than this are skipped
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Bytes one command may read in total; the files after that are skipped
+    #[serde(default = "default_max_total_bytes")]
+    pub max_total_bytes: u64,
+    /// Leave out what `.gitignore`, `.ignore` and the global git excludes ignore
+    #[serde(default = "default_respect_ignore_files")]
+    pub respect_ignore_files: bool,
+}
+
+/// A user-defined command: `@name(params)` runs `body`, a template in the command language
+/// where `{{param}}` stands for an argument.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MacroDefinition {
+    #[serde(default)]
+    pub description: String,
+    /// Parameter names; a trailing `?` makes one optional
+    #[serde(default)]
+    pub params: Vec<String>,
+    pub body: String,
+    #[serde(default = "default_macro_section")]
+    pub section: String,
+    /// Completion for the arguments: "file", "memory" or "model"
+    #[serde(default)]
+    pub autocomplete: Option<String>,
+    /// Show the expansion locally instead of sending it to the LLM
+    #[serde(default)]
+    pub local: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct McpServerConfig {
     pub name: String,
@@ -89,15 +188,27 @@ pub struct Config {
     #[serde(default)]
     pub workflow: WorkflowSettings,
     #[serde(default)]
+    pub replay: ReplaySettings,
+    #[serde(default)]
     pub mcp: McpSettings,
     #[serde(default)]
+    pub plugins: PluginSettings,
+    #[serde(default)]
+    pub scripting: ScriptingSettings,
+    #[serde(default)]
+    pub permissions: PermissionSettings,
+    #[serde(default)]
+    pub files: FileSettings,
+    #[serde(default)]
     pub model_presets: HashMap<String, LlmSettings>,
+    #[serde(default)]
+    pub macros: HashMap<String, MacroDefinition>,
 }
 
 // Default value functions
-fn default_temperature() -> f32 { 0.7 }
+fn default_temperature() -> f64 { 0.7 }
 fn default_max_tokens() -> u32 { 4000 }
-fn default_top_p() -> f32 { 0.9 }
+fn default_top_p() -> f64 { 0.9 }
 fn default_color_scheme() -> String { "default".to_string() }
 fn default_show_line_numbers() -> bool { true }
 fn default_response_format() -> String { "markdown".to_string() }
@@ -110,6 +221,19 @@ fn default_max_iterations() -> u32 { 10 }
 fn default_timeout_seconds() -> u32 { 300 }
 fn default_verify_steps() -> bool { true }
 fn default_parallel_execution() -> bool { false }
+fn default_replay_max_concurrent_requests() -> u32 { 2 }
+fn default_replay_context_exchanges() -> u32 { 10 }
+fn default_plugins_enabled() -> bool { true }
+fn default_plugin_timeout() -> u32 { 30 }
+fn default_plugin_describe_timeout() -> u32 { 3 }
+fn default_scripting_enabled() -> bool { true }
+fn default_script_max_operations() -> u64 { 1_000_000 }
+fn default_script_timeout() -> u32 { 30 }
+fn default_ask_effects() -> Vec<Effect> { vec![Effect::WriteFs, Effect::Exec, Effect::Destructive] }
+fn default_max_file_bytes() -> u64 { 256 * 1024 }
+fn default_max_total_bytes() -> u64 { 1024 * 1024 }
+fn default_respect_ignore_files() -> bool { true }
+fn default_macro_section() -> String { "macros".to_string() }
 fn default_mcp_timeout() -> u32 { 60 }
 fn default_mcp_enabled() -> bool { true }
 fn default_mcp_auto_connect() -> bool { true }
@@ -160,6 +284,56 @@ impl Default for WorkflowSettings {
     }
 }
 
+impl Default for ReplaySettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: default_replay_max_concurrent_requests(),
+            context_exchanges: default_replay_context_exchanges(),
+        }
+    }
+}
+
+impl Default for PluginSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_plugins_enabled(),
+            dirs: Vec::new(),
+            timeout_seconds: default_plugin_timeout(),
+            describe_timeout_seconds: default_plugin_describe_timeout(),
+        }
+    }
+}
+
+impl Default for ScriptingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_scripting_enabled(),
+            dirs: Vec::new(),
+            max_operations: default_script_max_operations(),
+            timeout_seconds: default_script_timeout(),
+        }
+    }
+}
+
+impl Default for PermissionSettings {
+    fn default() -> Self {
+        Self {
+            ask: default_ask_effects(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for FileSettings {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: default_max_file_bytes(),
+            max_total_bytes: default_max_total_bytes(),
+            respect_ignore_files: default_respect_ignore_files(),
+        }
+    }
+}
+
 impl Default for McpSettings {
     fn default() -> Self {
         Self {
@@ -178,13 +352,54 @@ impl Default for Config {
             ui: UiSettings::default(),
             memory: MemorySettings::default(),
             workflow: WorkflowSettings::default(),
+            replay: ReplaySettings::default(),
             mcp: McpSettings::default(),
+            plugins: PluginSettings::default(),
+            scripting: ScriptingSettings::default(),
+            permissions: PermissionSettings::default(),
+            files: FileSettings::default(),
             model_presets: HashMap::new(),
+            macros: HashMap::new(),
         }
     }
 }
 
+/// Load `~/cai.conf`, with the overrides in the project's `.cai/cai.conf` applied on top
+/// when cai runs inside a project that has one.
 pub fn load_configuration() -> Result<Config, Box<dyn std::error::Error>> {
+    let config = load_global_configuration()?;
+
+    match project::current_scope().config_path() {
+        Some(path) if path.exists() => {
+            let overrides = fs::read_to_string(&path)?;
+            match toml::from_str::<toml::Table>(&overrides) {
+                Ok(overrides) => {
+                    let (overrides, ignored) = project_overrides(overrides);
+                    // Configuration is loaded again on every command; warn at startup only
+                    static WARNED: std::sync::Once = std::sync::Once::new();
+                    if !ignored.is_empty() {
+                        WARNED.call_once(|| eprintln!(
+                            "Warning: {} can only set {}; ignoring {}.",
+                            path.display(),
+                            PROJECT_SECTIONS.join(", "),
+                            ignored.keys().cloned().collect::<Vec<_>>().join(", ")
+                        ));
+                    }
+                    let mut merged = toml::Value::try_from(&config)?;
+                    merge_toml(&mut merged, toml::Value::Table(overrides));
+                    Ok(merged.try_into()?)
+                }
+                Err(e) => {
+                    eprintln!("Warning: Could not parse {}: {}. Ignoring project config.", path.display(), e);
+                    Ok(config)
+                }
+            }
+        }
+        _ => Ok(config),
+    }
+}
+
+fn load_global_configuration() -> Result<Config, Box<dyn std::error::Error>> {
     let user_dirs = UserDirs::new().expect("Could not find user directories");
     let config_path = user_dirs.home_dir().join("cai.conf");
 
@@ -210,7 +425,7 @@ pub fn load_configuration() -> Result<Config, Box<dyn std::error::Error>> {
                         config.llm.model = legacy.model;
                         
                         // Save the migrated config
-                        let _ = save_configuration(&config);
+                        let _ = save_global_configuration(&config);
                         config
                     },
                     Err(e) => {
@@ -231,7 +446,89 @@ pub fn load_configuration() -> Result<Config, Box<dyn std::error::Error>> {
     Ok(config)
 }
 
+/// The sections a project's `.cai/cai.conf` may set. Anything that runs code or decides
+/// what runs (plugins, scripts, macros, MCP servers, permission rules) only comes from
+/// `~/cai.conf`, so opening a repository cannot change it.
+pub const PROJECT_SECTIONS: &[&str] = &["llm", "ui", "memory", "workflow", "files", "model_presets"];
+
+/// Split a configuration table into the sections a project may set and the rest.
+pub fn project_overrides(overrides: toml::Table) -> (toml::Table, toml::Table) {
+    overrides
+        .into_iter()
+        .partition(|(key, _)| PROJECT_SECTIONS.contains(&key.as_str()))
+}
+
+/// Save a changed configuration. Inside a project that has a `.cai/cai.conf`, only the
+/// values that differ from `~/cai.conf` (or were already overridden) go to the project file,
+/// and only for the sections a project may set; changes to other sections go to `~/cai.conf`.
 pub fn save_configuration(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match project::current_scope().config_path() {
+        Some(path) if path.exists() => {
+            let (mut overrides, _) = project_overrides(toml::from_str::<toml::Table>(&fs::read_to_string(&path)?)?);
+            let mut global = toml::Table::try_from(load_global_configuration()?)?;
+            let (project, other) = project_overrides(toml::Table::try_from(config)?);
+
+            let mut global_changed = false;
+            for (key, value) in other {
+                if global.get(&key) != Some(&value) {
+                    global.insert(key, value);
+                    global_changed = true;
+                }
+            }
+            if global_changed {
+                save_global_configuration(&toml::Value::Table(global.clone()).try_into()?)?;
+            }
+
+            update_overrides(&mut overrides, &global, &project);
+            fs::write(&path, toml::to_string_pretty(&overrides)?)?;
+            Ok(())
+        }
+        _ => save_global_configuration(config),
+    }
+}
+
+/// Recursively overlay `overrides` onto `base`; tables are merged, other values replaced.
+pub fn merge_toml(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Record in `overrides` every value of `config` that differs from `base`. Keys that are
+/// already overridden are kept up to date even when they now match `base`.
+pub fn update_overrides(overrides: &mut toml::Table, base: &toml::Table, config: &toml::Table) {
+    for (key, value) in config {
+        match (value, base.get(key)) {
+            (toml::Value::Table(table), Some(toml::Value::Table(base_table))) => {
+                let mut nested = match overrides.remove(key) {
+                    Some(toml::Value::Table(nested)) => nested,
+                    _ => toml::Table::new(),
+                };
+                update_overrides(&mut nested, base_table, table);
+                if !nested.is_empty() {
+                    overrides.insert(key.clone(), toml::Value::Table(nested));
+                }
+            }
+            (value, base_value) => {
+                if base_value != Some(value) || overrides.contains_key(key) {
+                    overrides.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+fn save_global_configuration(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
     let user_dirs = UserDirs::new().expect("Could not find user directories");
     let config_path = user_dirs.home_dir().join("cai.conf");
     let toml = toml::to_string_pretty(config)?;
@@ -240,24 +537,23 @@ pub fn save_configuration(config: &Config) -> Result<(), Box<dyn std::error::Err
 }
 
 // Session-based configuration override support
-static mut SESSION_CONFIG_OVERRIDE: Option<Config> = None;
+lazy_static! {
+    static ref SESSION_CONFIG_OVERRIDE: Mutex<Option<Config>> = Mutex::new(None);
+}
 
 pub fn set_session_config_override(config: Config) {
-    unsafe {
-        SESSION_CONFIG_OVERRIDE = Some(config);
-    }
+    let mut session_override = SESSION_CONFIG_OVERRIDE.lock().unwrap();
+    *session_override = Some(config);
 }
 
-pub fn get_session_config_override() -> Option<&'static Config> {
-    unsafe {
-        SESSION_CONFIG_OVERRIDE.as_ref()
-    }
+pub fn get_session_config_override() -> Option<Config> {
+    let session_override = SESSION_CONFIG_OVERRIDE.lock().unwrap();
+    session_override.clone()
 }
 
 pub fn clear_session_config_override() {
-    unsafe {
-        SESSION_CONFIG_OVERRIDE = None;
-    }
+    let mut session_override = SESSION_CONFIG_OVERRIDE.lock().unwrap();
+    *session_override = None;
 }
 
 pub fn get_effective_config() -> Result<Config, Box<dyn std::error::Error>> {
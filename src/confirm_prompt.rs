@@ -0,0 +1,64 @@
+use crate::permissions::ConfirmationRequest;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfirmPromptState {
+    Allow,
+    AllowAlways,
+    Deny,
+    Open,
+}
+
+/// Asks whether a command that the permission policy holds back may run.
+pub struct ConfirmPrompt {
+    pub request: ConfirmationRequest,
+}
+
+impl ConfirmPrompt {
+    pub fn new(request: ConfirmationRequest) -> Self {
+        Self { request }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> ConfirmPromptState {
+        if key.kind != KeyEventKind::Press {
+            return ConfirmPromptState::Open;
+        }
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => ConfirmPromptState::Allow,
+            KeyCode::Char('a') => ConfirmPromptState::AllowAlways,
+            KeyCode::Char('n') | KeyCode::Esc => ConfirmPromptState::Deny,
+            _ => ConfirmPromptState::Open,
+        }
+    }
+
+    pub fn render_confirm_popup(&self, frame: &mut Frame) {
+        let effects: Vec<String> = self.request.effects.iter().map(|effect| effect.to_string()).collect();
+        let text = vec![
+            Line::from(self.request.action.clone()),
+            Line::from(format!("Effects: {}", effects.join(", "))),
+            Line::from(""),
+            Line::from("[y] allow for this question   [a] always allow this run   [n] deny"),
+        ];
+        let popup = Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title("Run this command?"));
+
+        let popup_area = self.popup_area(frame.area(), 70);
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(popup, popup_area);
+    }
+
+    fn popup_area(&self, area: Rect, percent_x: u16) -> Rect {
+        let vertical = Layout::vertical([Constraint::Length(6)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        area
+    }
+}
@@ -1,79 +1,281 @@
+//! Finding and reading files for prompts.
+//!
+//! Patterns are walked with `.gitignore`, `.ignore` and hidden files respected, results are
+//! sorted by path and files are read within the limits of the `[files]` config section.
 pub mod files {
-    use glob::glob;
+    use crate::command_output::{FileContent, SkippedFile};
+    use crate::configuration::FileSettings;
+    use glob::{MatchOptions, Pattern};
+    use ignore::WalkBuilder;
     use std::fs;
-    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
 
-    pub fn list_files(pattern: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    /// How much of a file is looked at to tell binary from text.
+    const BINARY_CHECK_BYTES: usize = 8000;
+
+    /// Files and folders ordered by path.
+    pub fn list_files(pattern: &str, settings: &FileSettings) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        walk(pattern, settings, false)
+    }
+
+    pub fn list_folders(pattern: &str, settings: &FileSettings) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        walk(pattern, settings, true)
+    }
+
+    /// The files below the folders matching `pattern`, at any depth.
+    pub fn list_folder_files(pattern: &str, settings: &FileSettings) -> Result<Vec<String>, Box<dyn std::error::Error>> {
         let mut files = Vec::new();
-        for entry in glob(pattern)? {
-            match entry {
-                Ok(path) => {
-                    if path.is_file() {
-                        files.push(path.display().to_string());
-                    }
-                }
-                Err(e) => println!("{:?}", e),
-            }
+        for folder in list_folders(pattern, settings)? {
+            let folder_pattern = Path::new(&folder).join("**").join("*");
+            files.extend(list_files(&folder_pattern.to_string_lossy(), settings)?);
         }
+        files.sort();
+        files.dedup();
         Ok(files)
     }
 
-    pub fn list_folders(pattern: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut folders = Vec::new();
-        for entry in glob(pattern)? {
-            match entry {
-                Ok(path) => {
-                    if path.is_dir() {
-                        folders.push(path.display().to_string());
-                    }
+    /// A whole text file, refused when it is over the size limit for one file.
+    pub fn read_file(filename: &str, settings: &FileSettings) -> Result<String, Box<dyn std::error::Error>> {
+        check_size(filename, settings)?;
+        let contents = fs::read_to_string(filename)?;
+        Ok(contents)
+    }
+
+    /// Fail for files over the size limit for one file, before anything of them is read.
+    fn check_size(path: &str, settings: &FileSettings) -> Result<(), Box<dyn std::error::Error>> {
+        let size = fs::metadata(path)?.len();
+        if size > settings.max_file_bytes {
+            return Err(format!("{}: {} bytes, over the limit of {}", path, size, settings.max_file_bytes).into());
+        }
+        Ok(())
+    }
+
+    /// Read `paths` in order. Binary files get a placeholder, invalid UTF-8 is replaced,
+    /// and files over the size limits or that cannot be read are skipped with the reason.
+    pub fn read_files(paths: &[String], settings: &FileSettings) -> (Vec<FileContent>, Vec<SkippedFile>) {
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        let mut total: u64 = 0;
+
+        for path in paths {
+            let skip = |reason: String| SkippedFile { path: path.clone(), reason };
+            let size = match fs::metadata(path) {
+                Ok(metadata) => metadata.len(),
+                Err(e) => {
+                    skipped.push(skip(e.to_string()));
+                    continue;
+                }
+            };
+            if size > settings.max_file_bytes {
+                skipped.push(skip(format!("{} bytes, over the limit of {}", size, settings.max_file_bytes)));
+                continue;
+            }
+            let bytes = match fs::read(path) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    skipped.push(skip(e.to_string()));
+                    continue;
                 }
-                Err(e) => println!("{:?}", e),
+            };
+            if is_binary(&bytes) {
+                files.push(FileContent::new(path.clone(), format!("[binary file, {} bytes]", bytes.len())));
+                continue;
+            }
+            if total + bytes.len() as u64 > settings.max_total_bytes {
+                skipped.push(skip(format!("the total limit of {} bytes was reached", settings.max_total_bytes)));
+                continue;
             }
+            total += bytes.len() as u64;
+            files.push(FileContent::new(path.clone(), String::from_utf8_lossy(&bytes).into_owned()));
         }
-        Ok(folders)
+        (files, skipped)
     }
 
-    pub fn read_file(filename: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let contents = fs::read_to_string(filename)?;
-        Ok(contents)
+    /// The files and folders below `root` down to `depth` levels, drawn as a tree with the size
+    /// and line count of each file. Ignored and hidden entries are left out.
+    pub fn tree(root: &str, depth: usize, settings: &FileSettings) -> Result<String, Box<dyn std::error::Error>> {
+        if !Path::new(root).is_dir() {
+            return Err(format!("{}: no such folder", root).into());
+        }
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .standard_filters(settings.respect_ignore_files)
+            .hidden(true)
+            .require_git(false)
+            .max_depth(Some(depth))
+            .sort_by_file_name(|a, b| a.cmp(b));
+
+        // Depth, name and what is shown after it
+        let mut entries: Vec<(usize, String, String)> = Vec::new();
+        let (mut folders, mut files) = (0, 0);
+        for entry in builder.build().flatten().filter(|entry| entry.depth() > 0) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().is_some_and(|file_type| file_type.is_dir()) {
+                folders += 1;
+                let more = if entry.depth() == depth { " …" } else { "" };
+                entries.push((entry.depth(), format!("{}/", name), more.to_string()));
+            } else {
+                files += 1;
+                entries.push((entry.depth(), name, format!("  ({})", describe_file(entry.path(), settings))));
+            }
+        }
+
+        // Whether each entry is the last in its folder, found walking back
+        let mut last = vec![false; entries.len()];
+        let mut later_sibling: Vec<bool> = Vec::new();
+        for (index, (entry_depth, _, _)) in entries.iter().enumerate().rev() {
+            later_sibling.resize(*entry_depth + 1, false);
+            last[index] = !later_sibling[*entry_depth];
+            later_sibling[*entry_depth] = true;
+        }
+
+        let mut lines = vec![format!("{}/", root.trim_end_matches('/'))];
+        let mut open_folders: Vec<bool> = Vec::new();
+        for (index, (entry_depth, name, info)) in entries.iter().enumerate() {
+            open_folders.truncate(entry_depth - 1);
+            let indent: String = open_folders.iter().map(|open| if *open { "│   " } else { "    " }).collect();
+            let branch = if last[index] { "└── " } else { "├── " };
+            lines.push(format!("{}{}{}{}", indent, branch, name, info));
+            open_folders.push(!last[index]);
+        }
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        lines.push(format!("{} folder{}, {} file{}", folders, plural(folders), files, plural(files)));
+        Ok(lines.join("\n"))
     }
 
-    pub fn read_files(pattern: &str) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-        let mut file_contents = HashMap::new();
-        for entry in glob(pattern)? {
-            match entry {
-                Ok(path) => {
-                    if path.is_file() {
-                        let filename = path.display().to_string();
-                        let contents = fs::read_to_string(&filename)?;
-                        file_contents.insert(filename, contents);
-                    }
-                }
-                Err(e) => println!("{:?}", e),
+    /// Size and line count of a file; binary files and files over the size limit are not counted.
+    fn describe_file(path: &Path, settings: &FileSettings) -> String {
+        let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        if size > settings.max_file_bytes {
+            return human_size(size);
+        }
+        match fs::read(path) {
+            Ok(bytes) if is_binary(&bytes) => format!("{}, binary", human_size(size)),
+            Ok(bytes) => {
+                let lines = bytes.iter().filter(|&&byte| byte == b'\n').count() + usize::from(!bytes.is_empty() && !bytes.ends_with(b"\n"));
+                format!("{}, {} line{}", human_size(size), lines, if lines == 1 { "" } else { "s" })
             }
+            Err(_) => human_size(size),
         }
-        Ok(file_contents)
     }
 
-    pub fn read_folder(pattern: &str) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
-        let mut folder_contents = HashMap::new();
-        for entry in glob(pattern)? {
-            match entry {
-                Ok(path) => {
-                    if path.is_dir() {
-                        for file_entry in fs::read_dir(path)? {
-                            let file_path = file_entry?.path();
-                            if file_path.is_file() {
-                                let filename = file_path.display().to_string();
-                                let contents = fs::read_to_string(&filename)?;
-                                folder_contents.insert(filename, contents);
-                            }
-                        }
-                    }
-                }
-                Err(e) => println!("{:?}", e),
+    pub fn human_size(bytes: u64) -> String {
+        match bytes {
+            0..1024 => format!("{} B", bytes),
+            1024..1_048_576 => format!("{:.1} KB", bytes as f64 / 1024.0),
+            _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+        }
+    }
+
+    /// Split `src/main.rs:10-20` into the path and the lines, counted from 1. `path:10` is
+    /// one line and `path:10-` runs to the end of the file.
+    pub fn split_line_range(spec: &str) -> (&str, Option<(usize, Option<usize>)>) {
+        let Some((path, range)) = spec.rsplit_once(':') else {
+            return (spec, None);
+        };
+        let number = |text: &str| text.trim().parse::<usize>().ok();
+        let lines = match range.split_once('-') {
+            Some((start, "")) => number(start).map(|start| (start, None)),
+            Some((start, end)) => number(start).zip(number(end)).map(|(start, end)| (start, Some(end))),
+            None => number(range).map(|line| (line, Some(line))),
+        };
+        match lines {
+            Some(lines) if !path.is_empty() => (path, Some(lines)),
+            _ => (spec, None),
+        }
+    }
+
+    /// Lines `start` to `end` of a text file, numbered when rendered. The whole file has to
+    /// be within the size limit for one file.
+    pub fn read_lines(path: &str, start: usize, end: Option<usize>, settings: &FileSettings) -> Result<FileContent, Box<dyn std::error::Error>> {
+        check_size(path, settings)?;
+        let bytes = fs::read(path)?;
+        if is_binary(&bytes) {
+            return Err(format!("{} is a binary file", path).into());
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        let count = text.lines().count();
+        let end = end.unwrap_or(count).min(count);
+        if start == 0 || start > end {
+            return Err(format!("{} has {} lines; lines are counted from 1", path, count).into());
+        }
+        let content: Vec<&str> = text.lines().skip(start - 1).take(end + 1 - start).collect();
+        excerpt(path, start, content.join("\n"), settings)
+    }
+
+    /// Part of a file starting at line `first_line`, within the size limit for one file.
+    pub fn excerpt(path: &str, first_line: usize, content: String, settings: &FileSettings) -> Result<FileContent, Box<dyn std::error::Error>> {
+        if content.len() as u64 > settings.max_file_bytes {
+            return Err(format!("{}: {} bytes, over the limit of {}", path, content.len(), settings.max_file_bytes).into());
+        }
+        Ok(FileContent {
+            path: path.to_string(),
+            content,
+            first_line: Some(first_line),
+        })
+    }
+
+    /// Text files may not contain NUL bytes; binary formats nearly always do early on.
+    pub fn is_binary(bytes: &[u8]) -> bool {
+        bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0)
+    }
+
+    /// The files (or folders) matching the glob `pattern`. Only the part of the tree the
+    /// pattern can match is walked. A path without wildcards is taken as it is, even when it
+    /// is ignored.
+    fn walk(pattern: &str, settings: &FileSettings, folders: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let (base, rest) = split_pattern(pattern);
+        if rest.is_empty() {
+            let matches = if folders { base.is_dir() } else { base.is_file() };
+            return Ok(if matches { vec![pattern.to_string()] } else { Vec::new() });
+        }
+
+        let matcher = Pattern::new(pattern)?;
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let implicit_root = base.as_os_str().is_empty();
+        let root = if implicit_root { PathBuf::from(".") } else { base };
+        if !root.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut builder = WalkBuilder::new(&root);
+        builder.standard_filters(settings.respect_ignore_files).hidden(true).require_git(false);
+        if !rest.contains("**") {
+            builder.max_depth(Some(Path::new(&rest).components().count()));
+        }
+
+        let mut found = Vec::new();
+        for entry in builder.build().flatten() {
+            if entry.file_type().is_some_and(|file_type| file_type.is_dir()) != folders {
+                continue;
+            }
+            let path = if implicit_root {
+                entry.path().strip_prefix(".").unwrap_or(entry.path())
+            } else {
+                entry.path()
+            };
+            if matcher.matches_path_with(path, options) {
+                found.push(path.display().to_string());
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+
+    /// Split a glob into the leading directories without wildcards and the rest.
+    fn split_pattern(pattern: &str) -> (PathBuf, String) {
+        let mut base = PathBuf::new();
+        let mut components = Path::new(pattern).components();
+        while let Some(component) = components.clone().next() {
+            if component.as_os_str().to_string_lossy().contains(['*', '?', '[']) {
+                break;
             }
+            base.push(component);
+            components.next();
         }
-        Ok(folder_contents)
+        (base, components.as_path().to_string_lossy().into_owned())
     }
-}
\ No newline at end of file
+}
@@ -13,6 +13,7 @@ use ratatui::{
 
 };
 
+use crate::configuration::{self, FileSettings};
 use crate::files::files::{list_files, read_file};
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileSelectorState {
@@ -70,7 +71,8 @@ impl FileSelector {
 
     pub fn render_files_popup(&mut self, frame: &mut Frame) {
 
-        self.list_source =  list_files(&"./**/*.md").expect("REASON");
+        let settings = configuration::get_effective_config().map(|config| config.files).unwrap_or_default();
+        self.list_source =  list_files("./**/*.md", &settings).expect("REASON");
 
         let items: Vec<ListItem> = self.list_source
             .iter()
@@ -109,7 +111,7 @@ impl FileSelector {
 
         frame.render_widget(Clear, popup_area);
         frame.render_widget(list, list_area);
-        self.render_file_snipest(frame, details_area);
+        self.render_file_snipest(frame, details_area, &settings);
     }
 
     fn popup_area(&self, area: Rect, percent_x: u16, percent_y: u16) -> Rect {
@@ -120,12 +122,12 @@ impl FileSelector {
         area
     }
 
-    fn render_file_snipest(&self, frame: &mut Frame, area: Rect) {
+    fn render_file_snipest(&self, frame: &mut Frame, area: Rect, settings: &FileSettings) {
 
         if self.current_index < self.list_source.len() {
             let value = &self.list_source[self.current_index];
 
-            match read_file(value) {
+            match read_file(value, settings) {
                 Ok(contents) => {
                     let details_widget = Paragraph::new(contents)
                         .block(Block::bordered().title("Content:"))
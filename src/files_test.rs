@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::FileSettings;
+    use crate::files::files::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_walk_respects_ignore_files_and_sorts() {
+        let base = TempDir::new("files");
+        fs::create_dir_all(base.join("src").join("nested")).unwrap();
+        fs::create_dir_all(base.join("target")).unwrap();
+        fs::write(base.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(base.join("src").join("b.rs"), "b").unwrap();
+        fs::write(base.join("src").join("a.rs"), "a").unwrap();
+        fs::write(base.join("src").join("nested").join("c.rs"), "c").unwrap();
+        fs::write(base.join("src").join("build.log"), "log").unwrap();
+        fs::write(base.join("target").join("out.rs"), "out").unwrap();
+        fs::write(base.join(".hidden.rs"), "hidden").unwrap();
+
+        let root = base.display().to_string();
+        let settings = FileSettings::default();
+        let found = list_files(&format!("{}/**/*.rs", root), &settings).unwrap();
+        assert_eq!(found, vec![
+            format!("{}/src/a.rs", root),
+            format!("{}/src/b.rs", root),
+            format!("{}/src/nested/c.rs", root),
+        ]);
+
+        // Without `**` only the given depth is matched
+        let found = list_files(&format!("{}/src/*", root), &settings).unwrap();
+        assert_eq!(found, vec![format!("{}/src/a.rs", root), format!("{}/src/b.rs", root)]);
+
+        // Ignored files are found when asked for by name or when ignore files are off
+        assert_eq!(list_files(&format!("{}/src/build.log", root), &settings).unwrap().len(), 1);
+        let everything = FileSettings {
+            respect_ignore_files: false,
+            ..FileSettings::default()
+        };
+        assert!(list_files(&format!("{}/**/*.rs", root), &everything).unwrap().contains(&format!("{}/target/out.rs", root)));
+
+        let folders = list_folders(&format!("{}/*", root), &settings).unwrap();
+        assert_eq!(folders, vec![format!("{}/src", root)]);
+        let files = list_folder_files(&format!("{}/src", root), &settings).unwrap();
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn test_read_files_limits_and_binary() {
+        let base = TempDir::new("files");
+        let path = |name: &str| base.join(name).display().to_string();
+        fs::write(base.join("a.txt"), "0123456789").unwrap();
+        fs::write(base.join("b.bin"), [0x89, b'P', b'N', b'G', 0, 0, 1]).unwrap();
+        fs::write(base.join("c.txt"), "x".repeat(30)).unwrap();
+        fs::write(base.join("d.txt"), "0123456789").unwrap();
+        fs::write(base.join("e.txt"), [b'o', b'k', 0xff]).unwrap();
+
+        let settings = FileSettings {
+            max_file_bytes: 20,
+            max_total_bytes: 22,
+            respect_ignore_files: true,
+        };
+        let paths: Vec<String> = ["a.txt", "b.bin", "c.txt", "d.txt", "e.txt", "missing.txt"].iter().map(|name| path(name)).collect();
+        let (files, skipped) = read_files(&paths, &settings);
+
+        let read: Vec<(&str, &str)> = files.iter().map(|file| (file.path.as_str(), file.content.as_str())).collect();
+        assert_eq!(read, vec![
+            (paths[0].as_str(), "0123456789"),
+            (paths[1].as_str(), "[binary file, 7 bytes]"),
+            (paths[3].as_str(), "0123456789"),
+        ]);
+        let skipped: Vec<&str> = skipped.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(skipped, vec![paths[2].as_str(), paths[4].as_str(), paths[5].as_str()]);
+
+        let (files, _) = read_files(&paths[4..5], &FileSettings::default());
+        assert_eq!(files[0].content, "ok\u{fffd}");
+    }
+
+    #[test]
+    fn test_line_ranges() {
+        assert_eq!(split_line_range("src/workflow.rs:332-400"), ("src/workflow.rs", Some((332, Some(400)))));
+        assert_eq!(split_line_range("src/main.rs:7"), ("src/main.rs", Some((7, Some(7)))));
+        assert_eq!(split_line_range("src/main.rs:7-"), ("src/main.rs", Some((7, None))));
+        assert_eq!(split_line_range("src/main.rs"), ("src/main.rs", None));
+        assert_eq!(split_line_range("C:notes"), ("C:notes", None));
+
+        let base = TempDir::new("files");
+        let path = base.join("lines.txt").display().to_string();
+        fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+
+        let settings = FileSettings::default();
+        let file = read_lines(&path, 2, Some(3), &settings).unwrap();
+        assert_eq!(file.content, "two\nthree");
+        assert_eq!(file.label(), format!("{}:2-3", path));
+        assert_eq!(file.text(), "2 | two\n3 | three");
+        assert_eq!(read_lines(&path, 3, None, &settings).unwrap().content, "three\nfour");
+        assert_eq!(read_lines(&path, 3, Some(99), &settings).unwrap().content, "three\nfour");
+        assert!(read_lines(&path, 5, None, &settings).is_err());
+        assert!(read_lines(&path, 0, Some(1), &settings).is_err());
+
+        // The size limit holds for the whole file, even when only a few lines are wanted
+        let small = FileSettings { max_file_bytes: 10, ..FileSettings::default() };
+        assert!(read_lines(&path, 1, Some(1), &small).unwrap_err().to_string().contains("over the limit of 10"));
+        assert!(read_file(&path, &small).is_err());
+        assert_eq!(read_file(&path, &settings).unwrap(), "one\ntwo\nthree\nfour\n");
+    }
+
+    #[test]
+    fn test_tree() {
+        let base = TempDir::new("files");
+        fs::create_dir_all(base.join("src").join("commands")).unwrap();
+        fs::create_dir_all(base.join("target")).unwrap();
+        fs::write(base.join(".gitignore"), "target/\n").unwrap();
+        fs::write(base.join("Cargo.toml"), "[package]\nname = \"demo\"\n").unwrap();
+        fs::write(base.join("src").join("main.rs"), "fn main() {}").unwrap();
+        fs::write(base.join("src").join("logo.png"), [0x89, b'P', 0]).unwrap();
+        fs::write(base.join("src").join("commands").join("mod.rs"), "").unwrap();
+        fs::write(base.join("target").join("out"), "out").unwrap();
+
+        let root = base.display().to_string();
+        let settings = FileSettings::default();
+        assert_eq!(tree(&root, 3, &settings).unwrap(), format!(
+            "{}/\n├── Cargo.toml  (24 B, 2 lines)\n└── src/\n    ├── commands/\n    │   └── mod.rs  (0 B, 0 lines)\n    ├── logo.png  (3 B, binary)\n    └── main.rs  (12 B, 1 line)\n2 folders, 4 files",
+            root
+        ));
+        assert_eq!(tree(&root, 1, &settings).unwrap(), format!("{}/\n├── Cargo.toml  (24 B, 2 lines)\n└── src/ …\n1 folder, 1 file", root));
+        assert!(tree(&format!("{}/missing", root), 1, &settings).is_err());
+        assert_eq!(human_size(2048), "2.0 KB");
+    }
+}
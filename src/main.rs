@@ -1,13 +1,37 @@
 use tokio;
+mod apply;
+#[cfg(test)]
+mod apply_test;
+mod apply_preview;
 mod chat;
+#[cfg(test)]
+mod chat_test;
 mod command_handler;
+mod command_output;
+#[cfg(test)]
+mod command_output_test;
+mod command_parser;
+#[cfg(test)]
+mod command_parser_test;
 mod commands;
 mod commands_registry;
+#[cfg(test)]
+mod commands_registry_test;
 mod configuration;
 mod files;
+#[cfg(test)]
+mod files_test;
 mod input_handler;
 mod openrouter;
+mod project;
+#[cfg(test)]
+mod project_test;
 mod session;
+mod session_bundle;
+mod session_crypto;
+mod session_import;
+mod session_replay;
+mod session_stats;
 #[cfg(test)]
 mod session_test;
 mod workflow;
@@ -17,10 +41,26 @@ mod terminal;
 mod autocomplete;
 mod chat_ui;
 mod commands_selector;
+mod confirm_prompt;
 mod files_selector;
+mod passphrase_prompt;
+mod permissions;
+#[cfg(test)]
+mod permissions_test;
+mod prompt_preview;
+mod plugin_host;
+#[cfg(test)]
+mod plugin_host_test;
+mod scripting;
+#[cfg(test)]
+mod scripting_test;
+mod stats_panel;
+mod symbols;
+#[cfg(test)]
+mod symbols_test;
+#[cfg(test)]
+mod test_util;
 mod mcp_client;
-mod workflow;
-mod workflow_test;
 
 use chat_ui::main_ui;
 //use editor::run_editor;
@@ -40,8 +80,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("MCP commands may not work correctly");
     }
 
+    // Replay prompts journaled before an unclean exit
+    match session::recover_session_journal() {
+        Ok(Some(message)) => println!("{}", message),
+        Ok(None) => {}
+        Err(e) => println!("Warning: Failed to recover session journal: {}", e),
+    }
+
+    let config = configuration::load_configuration().unwrap_or_default();
+    permissions::set_policy(config.permissions);
+    let memory_settings = config.memory;
+    session::start_auto_save(memory_settings.auto_save_interval_minutes);
+
     main_ui();
 
+    match session::shutdown_session(&memory_settings) {
+        Ok(Some(message)) => println!("{}", message),
+        Ok(None) => {}
+        Err(e) => println!("Warning: Failed to save session on exit: {}", e),
+    }
+
     //chat::chat_loop().await?;
     Ok(())
 }
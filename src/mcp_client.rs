@@ -10,6 +10,27 @@ use chrono::{DateTime, Utc};
 
 lazy_static! {
     static ref MCP_MANAGER: Mutex<Option<McpManager>> = Mutex::new(None);
+    static ref TOOL_LISTENER: Mutex<Option<fn(ToolEvent)>> = Mutex::new(None);
+}
+
+/// A change to the tools servers offer, reported to whoever exposes them as commands.
+pub enum ToolEvent<'a> {
+    Added(&'a McpTool),
+    /// All tools of the named server went away
+    ServerRemoved(&'a str),
+}
+
+/// Set the function told about tools coming and going. The command layer uses it to
+/// keep one command per tool, so this module does not depend on it.
+pub fn set_tool_listener(listener: fn(ToolEvent)) {
+    *TOOL_LISTENER.lock().unwrap() = Some(listener);
+}
+
+fn notify(event: ToolEvent) {
+    let listener = *TOOL_LISTENER.lock().unwrap();
+    if let Some(listener) = listener {
+        listener(event);
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -92,9 +113,9 @@ impl McpManager {
 
     /// Connect to all configured MCP servers
     pub async fn connect_all_servers(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        for (name, config) in &self.settings.servers {
+        for (name, config) in self.settings.servers.clone() {
             if config.enabled {
-                match self.connect_server(name, config).await {
+                match self.connect_server(&name, &config).await {
                     Ok(_) => println!("Connected to MCP server: {}", name),
                     Err(e) => eprintln!("Failed to connect to MCP server {}: {}", name, e),
                 }
@@ -132,12 +153,13 @@ impl McpManager {
         // Discover available tools, resources, and prompts
         self.discover_client_capabilities(&mut client).await?;
         
-        // Update global tools registry
+        // Update global tools registry; each tool also becomes an @mcp:<server>:<tool> command
         for tool in &client.tools {
             self.global_tools.insert(
                 format!("{}:{}", client.name, tool.name),
                 tool.clone()
             );
+            notify(ToolEvent::Added(tool));
         }
         
         client.connected = true;
@@ -305,6 +327,7 @@ impl McpManager {
             for tool in &client.tools {
                 self.global_tools.remove(&format!("{}:{}", client.name, tool.name));
             }
+            notify(ToolEvent::ServerRemoved(&client.name));
             
             // Kill the process if it exists
             if let Some(process_arc) = client.process.take() {
@@ -405,5 +428,6 @@ async fn connect_server(server_name: &str) -> Result<String, Box<dyn std::error:
 }
 
 async fn disconnect_server(server_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    notify(ToolEvent::ServerRemoved(server_name));
     Ok(format!("Disconnected from MCP server: {}", server_name))
 }
\ No newline at end of file
@@ -1,12 +1,17 @@
-use crate::configuration;
+use crate::chat::PromptStats;
+use crate::configuration::{self, LlmSettings};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::time::Instant;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Model {
     pub id: String,
     pub name: String,
+    /// Tokens the model accepts per request, prompt and answer together
+    #[serde(default)]
+    pub context_length: Option<u64>,
 }
 
 pub async fn list_openrouter_models() -> Result<Vec<Model>, Box<dyn std::error::Error>> {
@@ -27,36 +32,67 @@ pub async fn list_openrouter_models() -> Result<Vec<Model>, Box<dyn std::error::
     Ok(models)
 }
 
+/// An LLM answer with the usage OpenRouter reported for it.
+#[derive(Debug, Clone)]
+pub struct LlmResponse {
+    pub content: String,
+    pub stats: PromptStats,
+}
+
 #[allow(dead_code)]
 pub async fn call_openrouter_api(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(call_openrouter_api_with_usage(prompt).await?.content)
+}
+
+pub async fn call_openrouter_api_with_usage(prompt: &str) -> Result<LlmResponse, Box<dyn std::error::Error>> {
     let config = configuration::get_effective_config()?;
+    call_openrouter_chat(&config.llm, &[], prompt).await
+}
+
+/// Send `prompt` after the earlier `(question, answer)` exchanges in `history`, using
+/// the given LLM settings instead of the effective configuration.
+pub async fn call_openrouter_chat(
+    llm: &LlmSettings,
+    history: &[(String, String)],
+    prompt: &str,
+) -> Result<LlmResponse, Box<dyn std::error::Error>> {
     let api_key = std::env::var("OPENROUTER_API_KEY")
         .expect("OPENROUTER_API_KEY environment variable not set");
 
     let client = Client::new();
     let endpoint = "https://openrouter.ai/api/v1/chat/completions";
 
-    let mut messages = vec![json!({
-        "role": "user",
-        "content": prompt
-    })];
+    let mut messages = Vec::new();
 
     // Add system prompt if configured
-    if let Some(system_prompt) = &config.llm.system_prompt {
-        messages.insert(0, json!({
+    if let Some(system_prompt) = &llm.system_prompt {
+        messages.push(json!({
             "role": "system",
             "content": system_prompt
         }));
     }
 
+    for (question, answer) in history {
+        messages.push(json!({ "role": "user", "content": question }));
+        messages.push(json!({ "role": "assistant", "content": answer }));
+    }
+
+    messages.push(json!({
+        "role": "user",
+        "content": prompt
+    }));
+
     let body = json!({
-        "model": &config.llm.model,
+        "model": &llm.model,
         "messages": messages,
-        "temperature": config.llm.temperature,
-        "max_tokens": config.llm.max_tokens,
-        "top_p": config.llm.top_p
+        "temperature": llm.temperature,
+        "max_tokens": llm.max_tokens,
+        "top_p": llm.top_p,
+        "usage": { "include": true }
     });
 
+    let started = Instant::now();
+
     let resp = client
         .post(endpoint)
         .header("Authorization", format!("Bearer {}", api_key))
@@ -67,11 +103,29 @@ pub async fn call_openrouter_api(prompt: &str) -> Result<String, Box<dyn std::er
         .await?;
 
     let json: serde_json::Value = resp.json().await?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    if let Some(message) = json["error"]["message"].as_str() {
+        return Err(format!("OpenRouter error: {}", message).into());
+    }
 
     // Extract the response message
     let response_content = json["choices"][0]["message"]["content"]
         .as_str()
         .unwrap_or("No response");
 
-    Ok(response_content.to_string())
+    let usage = &json["usage"];
+    let stats = PromptStats {
+        model: Some(json["model"].as_str().unwrap_or(&llm.model).to_string()),
+        prompt_tokens: usage["prompt_tokens"].as_u64().map(|n| n as u32),
+        completion_tokens: usage["completion_tokens"].as_u64().map(|n| n as u32),
+        cost: usage["cost"].as_f64(),
+        latency_ms: Some(latency_ms),
+        expansion_bytes: None,
+    };
+
+    Ok(LlmResponse {
+        content: response_content.to_string(),
+        stats,
+    })
 }
@@ -0,0 +1,95 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::widgets::{Block, Borders, Clear};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+};
+use tui_textarea::TextArea;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassphrasePromptState {
+    Entered,
+    Editing,
+    Exit,
+}
+
+/// Masked single-line input for session passphrases. With `confirm`, the passphrase
+/// has to be typed twice before it is returned.
+pub struct PassphrasePrompt<'a> {
+    input: TextArea<'a>,
+    title: String,
+    confirm: bool,
+    first_entry: Option<String>,
+}
+
+impl PassphrasePrompt<'_> {
+    pub fn new(title: &str, confirm: bool) -> Self {
+        let mut prompt = Self {
+            input: TextArea::default(),
+            title: title.to_string(),
+            confirm,
+            first_entry: None,
+        };
+        prompt.reset_input(title);
+        prompt
+    }
+
+    fn reset_input(&mut self, title: &str) {
+        self.input = TextArea::default();
+        self.input.set_mask_char('*');
+        self.input.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{} ('Enter' to confirm, 'Esc' to cancel)", title)),
+        );
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> (Option<String>, PassphrasePromptState) {
+        if key.kind != KeyEventKind::Press {
+            return (None, PassphrasePromptState::Editing);
+        }
+        match key.code {
+            KeyCode::Esc => (None, PassphrasePromptState::Exit),
+            KeyCode::Enter => {
+                let passphrase = self.input.lines().join("");
+                if passphrase.is_empty() {
+                    return (None, PassphrasePromptState::Editing);
+                }
+                if !self.confirm {
+                    return (Some(passphrase), PassphrasePromptState::Entered);
+                }
+                match self.first_entry.take() {
+                    None => {
+                        self.first_entry = Some(passphrase);
+                        self.reset_input("Confirm passphrase");
+                        (None, PassphrasePromptState::Editing)
+                    }
+                    Some(first) if first == passphrase => (Some(passphrase), PassphrasePromptState::Entered),
+                    Some(_) => {
+                        let title = format!("Passphrases did not match. {}", self.title);
+                        self.reset_input(&title);
+                        (None, PassphrasePromptState::Editing)
+                    }
+                }
+            }
+            _ => {
+                self.input.input(key);
+                (None, PassphrasePromptState::Editing)
+            }
+        }
+    }
+
+    pub fn render_passphrase_popup(&self, frame: &mut Frame) {
+        let popup_area = self.popup_area(frame.area(), 60);
+        frame.render_widget(Clear, popup_area);
+        frame.render_widget(&self.input, popup_area);
+    }
+
+    fn popup_area(&self, area: Rect, percent_x: u16) -> Rect {
+        let vertical = Layout::vertical([Constraint::Length(3)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        area
+    }
+}
@@ -0,0 +1,237 @@
+//! Permission policy for commands with side effects.
+//!
+//! Every command declares its effects. Before an invocation runs, the `[permissions]` section
+//! of the config decides whether it is allowed, denied or needs confirmation. An invocation
+//! that needs confirmation fails and leaves a `ConfirmationRequest` behind; the chat UI asks
+//! the user and runs the question again once it is granted.
+use crate::configuration::{PermissionRule, PermissionSettings};
+use directories::UserDirs;
+use glob::{MatchOptions, Pattern};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Component, PathBuf};
+use std::sync::Mutex;
+
+/// Something a command does besides producing output.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    ReadFs,
+    WriteFs,
+    Exec,
+    Network,
+    Destructive,
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Effect::ReadFs => "read-fs",
+            Effect::WriteFs => "write-fs",
+            Effect::Exec => "exec",
+            Effect::Network => "network",
+            Effect::Destructive => "destructive",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// An invocation waiting for the user to allow it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmationRequest {
+    /// The invocation with the paths it is given, e.g. `@export /tmp/notes.md`
+    pub action: String,
+    pub effects: Vec<Effect>,
+}
+
+lazy_static! {
+    static ref CONFIRMATION_REQUEST: Mutex<Option<ConfirmationRequest>> = Mutex::new(None);
+    // Actions the user allowed for the current question only, and for the rest of this run
+    static ref GRANTED_ONCE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    static ref GRANTED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    // The `[permissions]` section of `~/cai.conf`, set once at startup
+    static ref POLICY: Mutex<Option<PermissionSettings>> = Mutex::new(None);
+}
+
+/// Use `settings` for every following `check`. Until this is called, e.g. in tests, the
+/// default policy applies.
+pub fn set_policy(settings: PermissionSettings) {
+    *POLICY.lock().unwrap() = Some(settings);
+}
+
+/// The decision for a command with `effects`, invoked as `target` (`@name`, or `>` and the
+/// shell line) with the path arguments `paths`.
+pub fn decide(
+    settings: &PermissionSettings,
+    effects: &[Effect],
+    target: &str,
+    paths: &[String],
+) -> Result<Decision, Box<dyn std::error::Error>> {
+    for rule in &settings.rules {
+        if rule_matches(rule, effects, target, paths)? {
+            return Ok(rule.decision);
+        }
+    }
+    if effects.iter().any(|effect| settings.ask.contains(effect)) {
+        Ok(Decision::Ask)
+    } else {
+        Ok(Decision::Allow)
+    }
+}
+
+fn rule_matches(
+    rule: &PermissionRule,
+    effects: &[Effect],
+    target: &str,
+    paths: &[String],
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(effect) = rule.effect
+        && !effects.contains(&effect)
+    {
+        return Ok(false);
+    }
+    if let Some(command) = &rule.command {
+        let pattern = Pattern::new(command).map_err(|e| format!("Invalid command pattern '{}': {}", command, e))?;
+        if !pattern.matches(target) {
+            return Ok(false);
+        }
+        // A `*` would match `; rm -rf ~` as well, so shell lines that chain, redirect or
+        // substitute commands are never allowed by a command pattern
+        if rule.decision == Decision::Allow && target.strip_prefix('>').is_some_and(has_shell_operators) {
+            return Ok(false);
+        }
+    }
+    if let Some(path) = &rule.path {
+        let absolute_pattern = absolute(path).to_string_lossy().into_owned();
+        let pattern = Pattern::new(&absolute_pattern).map_err(|e| format!("Invalid path pattern '{}': {}", path, e))?;
+        // `*` stays within one directory, `**` crosses them
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        if !paths.iter().any(|p| pattern.matches_path_with(&absolute(p), options)) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether a shell line does more than run one program with arguments: `;`, `&`, `|`,
+/// redirections, subshells or line breaks outside of quotes, and `$` or backticks outside of
+/// single quotes.
+fn has_shell_operators(line: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in line.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (_, '$' | '`') => return true,
+            (Some('"'), '"') => quote = None,
+            (Some('"'), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';' | '&' | '|' | '<' | '>' | '(' | ')' | '\n') => return true,
+            _ => {}
+        }
+    }
+    // An unterminated quote leaves the line to the shell's interpretation
+    quote.is_some()
+}
+
+/// `path` with a leading `~` expanded, made absolute against the working directory and
+/// with `.` and `..` resolved. The file does not have to exist.
+fn absolute(path: &str) -> PathBuf {
+    let expanded = match path.strip_prefix("~/") {
+        Some(rest) => match UserDirs::new() {
+            Some(dirs) => dirs.home_dir().join(rest),
+            None => PathBuf::from(path),
+        },
+        None => PathBuf::from(path),
+    };
+    let joined = if expanded.is_absolute() {
+        expanded
+    } else {
+        std::env::current_dir().unwrap_or_default().join(expanded)
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// How an invocation is named in messages and remembered grants.
+pub fn describe_action(target: &str, paths: &[String]) -> String {
+    if paths.is_empty() {
+        target.to_string()
+    } else {
+        format!("{} {}", target, paths.join(" "))
+    }
+}
+
+/// Check an invocation against the policy set with `set_policy`. A denied invocation is an
+/// error. One that needs confirmation is an error as well, unless the user already allowed
+/// it, and leaves a `ConfirmationRequest` for the UI.
+pub fn check(effects: &[Effect], target: &str, paths: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = POLICY.lock().unwrap().clone().unwrap_or_default();
+    let action = describe_action(target, paths);
+
+    match decide(&settings, effects, target, paths)? {
+        Decision::Allow => Ok(()),
+        Decision::Deny => Err(format!("{} is denied by the permission policy", action).into()),
+        Decision::Ask => {
+            if GRANTED.lock().unwrap().contains(&action) || GRANTED_ONCE.lock().unwrap().contains(&action) {
+                return Ok(());
+            }
+            // Keep the first request; the others come up when the question runs again
+            let mut request = CONFIRMATION_REQUEST.lock().unwrap();
+            if request.is_none() {
+                *request = Some(ConfirmationRequest {
+                    action: action.clone(),
+                    effects: effects.to_vec(),
+                });
+            }
+            Err(format!("{} needs confirmation", action).into())
+        }
+    }
+}
+
+/// The pending confirmation request, if a command left one behind.
+pub fn take_confirmation_request() -> Option<ConfirmationRequest> {
+    CONFIRMATION_REQUEST.lock().unwrap().take()
+}
+
+/// Allow the action of `request` until the current question is finished, or with
+/// `remember` for the rest of this run.
+pub fn grant(request: &ConfirmationRequest, remember: bool) {
+    if remember {
+        GRANTED.lock().unwrap().insert(request.action.clone());
+    } else {
+        GRANTED_ONCE.lock().unwrap().insert(request.action.clone());
+    }
+}
+
+/// End the grants given for the current question. A question asks about one action at a
+/// time and runs again after each answer, so its grants have to last until it is finished.
+pub fn forget_question_grants() {
+    GRANTED_ONCE.lock().unwrap().clear();
+}
@@ -0,0 +1,261 @@
+//! External command plugins: executables in a plugin directory that talk JSON over stdio.
+//!
+//! At startup every plugin is run as `<plugin> describe` and prints a `PluginDescription`
+//! naming its commands. A command call runs `<plugin> call`, writes a `CallRequest` to its
+//! stdin and reads a `CallResponse` from its stdout. Each call is a separate process with a
+//! time limit, so a plugin that crashes or hangs only fails that one call.
+use crate::commands_registry::{ArgSpec, CommandType};
+use crate::configuration::Config;
+use crate::permissions::Effect;
+use crate::project::Scope;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginDescription {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    pub commands: Vec<PluginCommandSpec>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginCommandSpec {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub usage_example: Option<String>,
+    #[serde(default = "default_plugin_section")]
+    pub section: String,
+    #[serde(default)]
+    pub command_type: PluginCommandType,
+    #[serde(default)]
+    pub args: Vec<PluginArgSpec>,
+    /// What the command does besides returning output, e.g. `["network"]`
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginCommandType {
+    #[default]
+    Llm,
+    NotLlm,
+    Terminal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginArgSpec {
+    pub name: String,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    #[serde(default)]
+    pub rest: bool,
+    #[serde(default)]
+    pub path: bool,
+}
+
+fn default_plugin_section() -> String { "plugins".to_string() }
+fn default_required() -> bool { true }
+
+impl PluginCommandType {
+    pub fn command_type(self) -> CommandType {
+        match self {
+            PluginCommandType::Llm => CommandType::LLM,
+            PluginCommandType::NotLlm => CommandType::NotLLM,
+            PluginCommandType::Terminal => CommandType::Terminal,
+        }
+    }
+}
+
+impl PluginArgSpec {
+    pub fn arg_spec(&self) -> ArgSpec {
+        let spec = if self.required {
+            ArgSpec::required(&self.name)
+        } else {
+            ArgSpec::optional(&self.name)
+        };
+        let spec = if self.rest { spec.rest() } else { spec };
+        if self.path { spec.path() } else { spec }
+    }
+}
+
+/// Sent on stdin for `<plugin> call`.
+#[derive(Serialize, Debug)]
+pub struct CallRequest {
+    pub command: String,
+    /// Given arguments by name
+    pub args: Map<String, Value>,
+    pub session: Option<String>,
+}
+
+/// Expected on stdout from `<plugin> call`: the output, or an error message.
+#[derive(Deserialize, Debug)]
+pub struct CallResponse {
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// A plugin found at startup and what its handshake returned.
+#[derive(Debug, Clone)]
+pub struct LoadedPlugin {
+    pub path: PathBuf,
+    pub description: Result<PluginDescription, String>,
+}
+
+lazy_static! {
+    static ref PLUGINS: Mutex<Vec<LoadedPlugin>> = Mutex::new(Vec::new());
+}
+
+/// `plugins/` in the global data directory, followed by the `[plugins] dirs` of the config.
+/// Only `~/cai.conf` can set these; a project's config cannot add plugin directories.
+pub fn plugin_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = vec![Scope::Global.data_dir().join("plugins")];
+    dirs.extend(config.plugins.dirs.iter().map(PathBuf::from));
+    dirs
+}
+
+/// Executable files directly inside `dirs`, in name order per directory.
+pub fn discover(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut plugins = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_executable(path))
+            .collect();
+        found.sort();
+        plugins.extend(found);
+    }
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Run the `describe` handshake of one plugin.
+pub fn load_plugin(path: &Path, timeout: Duration) -> LoadedPlugin {
+    let description = run_plugin(path, "describe", None, timeout).and_then(|stdout| {
+        serde_json::from_slice::<PluginDescription>(&stdout).map_err(|e| format!("invalid describe output: {}", e))
+    });
+    LoadedPlugin {
+        path: path.to_path_buf(),
+        description,
+    }
+}
+
+/// Describe every plugin of the configured directories and remember the result for `@plugins`.
+/// The handshakes run side by side, so startup waits for the slowest plugin only.
+pub fn load_plugins(config: &Config) -> Vec<LoadedPlugin> {
+    let timeout = Duration::from_secs(config.plugins.describe_timeout_seconds as u64);
+    let paths = discover(&plugin_dirs(config));
+    let loaded: Vec<LoadedPlugin> = thread::scope(|scope| {
+        let handshakes: Vec<_> = paths.iter().map(|path| scope.spawn(move || load_plugin(path, timeout))).collect();
+        handshakes
+            .into_iter()
+            .zip(&paths)
+            .map(|(handshake, path)| {
+                handshake.join().unwrap_or_else(|_| LoadedPlugin {
+                    path: path.clone(),
+                    description: Err("describe handshake panicked".to_string()),
+                })
+            })
+            .collect()
+    });
+    *PLUGINS.lock().unwrap() = loaded.clone();
+    loaded
+}
+
+pub fn loaded_plugins() -> Vec<LoadedPlugin> {
+    PLUGINS.lock().unwrap().clone()
+}
+
+/// Run one command of a plugin.
+pub async fn call(path: &Path, request: CallRequest, timeout: Duration) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let input = serde_json::to_vec(&request)?;
+    let plugin = path.to_path_buf();
+    let stdout = tokio::task::spawn_blocking(move || run_plugin(&plugin, "call", Some(input), timeout)).await??;
+
+    let response: CallResponse = serde_json::from_slice(&stdout)
+        .map_err(|e| format!("{} returned an invalid response: {}", path.display(), e))?;
+    match response.error {
+        Some(error) => Err(error.into()),
+        None => Ok(response.output),
+    }
+}
+
+/// Run `<plugin> <action>` with `input` on stdin and return its stdout. Fails when the
+/// plugin cannot start, outlives `timeout` (it is killed) or exits unsuccessfully.
+fn run_plugin(path: &Path, action: &str, input: Option<Vec<u8>>, timeout: Duration) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(path)
+        .arg(action)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not start {}: {}", path.display(), e))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        thread::spawn(move || stdin.write_all(&input));
+    }
+    // Read both pipes while waiting, so a chatty plugin cannot block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stdout.read_to_end(&mut buffer);
+        buffer
+    });
+    let stderr = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = stderr.read_to_end(&mut buffer);
+        buffer
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{} {} timed out after {}s", path.display(), action, timeout.as_secs()));
+            }
+            Err(e) => return Err(format!("could not wait for {}: {}", path.display(), e)),
+        }
+    };
+
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{} {} failed ({}): {}", path.display(), action, output.status, stderr.trim()));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use crate::commands::plugin_cmd::register_plugin;
+    use crate::commands_registry::{CommandType, execute_command, get_command};
+    use crate::plugin_host::{discover, load_plugin, load_plugins};
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::time::Duration;
+
+    const PLUGIN: &str = r#"#!/bin/sh
+case "$1" in
+describe)
+    echo '{"name": "echo", "version": "1.0", "commands": [
+        {"name": "test-plugin-echo", "description": "Echo", "command_type": "not_llm",
+         "args": [{"name": "text"}, {"name": "suffix", "required": false}]},
+        {"name": "test-plugin-fail"}, {"name": "test-plugin-crash"}, {"name": "test-plugin-hang"}]}'
+    ;;
+call)
+    read request
+    case "$request" in
+    *test-plugin-fail*) echo '{"error": "nothing to do"}' ;;
+    *test-plugin-crash*) echo "boom" >&2; exit 3 ;;
+    *test-plugin-hang*) sleep 5 ;;
+    *)
+        text=$(echo "$request" | sed 's/.*"text":"\([^"]*\)".*/\1/')
+        echo "{\"output\": \"echo $text\"}"
+        ;;
+    esac
+    ;;
+esac
+"#;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_plugin_protocol() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("plugins");
+        let path = dir.join("echo-plugin");
+        fs::write(&path, PLUGIN).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(dir.join("notes.txt"), "not a plugin").unwrap();
+
+        assert_eq!(discover(&[dir.to_path_buf()]), vec![path.clone()]);
+
+        let plugin = load_plugin(&path, Duration::from_secs(1));
+        let description = plugin.description.as_ref().unwrap();
+        assert_eq!(description.name, "echo");
+        assert_eq!(description.commands.len(), 4);
+        assert!(register_plugin(&plugin, Duration::from_secs(1)).is_empty());
+
+        let command = get_command("test-plugin-echo").unwrap();
+        assert_eq!(command.command_type, CommandType::NotLLM);
+        assert_eq!(command.usage_example, "@test-plugin-echo(text, suffix)");
+
+        let result = execute_command("@test-plugin-echo(hello)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("echo hello".into()));
+
+        // Errors, crashes and hangs fail only the call
+        let result = execute_command("@test-plugin-fail()").await.unwrap().unwrap();
+        assert!(result.command_output.unwrap_err().to_string().ends_with("nothing to do"));
+        let result = execute_command("@test-plugin-crash()").await.unwrap().unwrap();
+        assert!(result.command_output.unwrap_err().to_string().contains("boom"));
+        let result = execute_command("@test-plugin-hang()").await.unwrap().unwrap();
+        assert!(result.command_output.unwrap_err().to_string().contains("timed out"));
+
+        // Registering again reports the clashes instead of replacing the commands
+        assert_eq!(register_plugin(&plugin, Duration::from_secs(1)).len(), 4);
+
+        fs::write(&path, "#!/bin/sh\necho not json\n").unwrap();
+        assert!(load_plugin(&path, Duration::from_secs(1)).description.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plugins_are_described_side_by_side() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::Instant;
+
+        let dir = TempDir::new("plugins");
+        for name in ["slow-a", "slow-b", "slow-c"] {
+            let path = dir.join(name);
+            let script = format!("#!/bin/sh\nsleep 1\necho '{{\"name\": \"{}\", \"commands\": []}}'\n", name);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let mut config = crate::configuration::Config::default();
+        config.plugins.dirs = vec![dir.display().to_string()];
+
+        let start = Instant::now();
+        let loaded = load_plugins(&config);
+        assert!(start.elapsed() < Duration::from_millis(2500));
+        let names: Vec<String> = loaded
+            .iter()
+            .filter(|plugin| plugin.path.starts_with(&dir))
+            .map(|plugin| plugin.description.as_ref().unwrap().name.clone())
+            .collect();
+        assert_eq!(names, vec!["slow-a", "slow-b", "slow-c"]);
+    }
+}
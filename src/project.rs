@@ -0,0 +1,73 @@
+use directories::{ProjectDirs, UserDirs};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Where sessions, prompt history and config overrides are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scope {
+    Project(PathBuf), // project root; data lives in `<root>/.cai/`
+    Global,
+}
+
+static SCOPE: OnceLock<Scope> = OnceLock::new();
+
+/// The scope for this run, detected once from the directory cai was started in.
+pub fn current_scope() -> &'static Scope {
+    SCOPE.get_or_init(|| match std::env::current_dir() {
+        Ok(dir) => match detect_project_root(&dir) {
+            Some(root) => Scope::Project(root),
+            None => Scope::Global,
+        },
+        Err(_) => Scope::Global,
+    })
+}
+
+/// Walk up from `start` to the nearest directory containing a `.cai/` directory or a
+/// `.git` entry (a directory, or a file for worktrees and submodules). The home directory
+/// is never a project root, so a `~/.cai` or a dotfiles repository does not capture
+/// every directory below it.
+pub fn detect_project_root(start: &Path) -> Option<PathBuf> {
+    let home = UserDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    start
+        .ancestors()
+        .filter(|dir| home.as_deref() != Some(*dir))
+        .find(|dir| dir.join(".cai").is_dir() || dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+impl Scope {
+    /// The directory holding data for this scope: `<root>/.cai` for a project, the
+    /// platform data directory otherwise.
+    pub fn data_dir(&self) -> PathBuf {
+        match self {
+            Scope::Project(root) => root.join(".cai"),
+            Scope::Global => match ProjectDirs::from("", "", "cai") {
+                Some(proj_dirs) => proj_dirs.data_dir().to_path_buf(),
+                None => PathBuf::from(".cai"),
+            },
+        }
+    }
+
+    pub fn sessions_dir(&self) -> PathBuf {
+        self.data_dir().join("sessions")
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        self.data_dir().join("history.txt")
+    }
+
+    /// Project config overrides, layered over the global `~/cai.conf`.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        match self {
+            Scope::Project(root) => Some(root.join(".cai").join("cai.conf")),
+            Scope::Global => None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Scope::Project(root) => format!("project {}", root.display()),
+            Scope::Global => "global".to_string(),
+        }
+    }
+}
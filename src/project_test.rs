@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{merge_toml, project_overrides, update_overrides};
+    use crate::project::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_project_root_detection() {
+        let base = TempDir::new("project");
+        let nested = base.join("repo").join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+
+        // Nothing marks a project yet (the temp dir itself is not inside a repo)
+        assert_eq!(detect_project_root(&nested), detect_project_root(&std::env::temp_dir()));
+
+        // A git root without .cai/ is a project
+        fs::create_dir_all(base.join("repo").join(".git")).unwrap();
+        assert_eq!(detect_project_root(&nested), Some(base.join("repo")));
+
+        fs::create_dir_all(base.join("repo").join(".cai")).unwrap();
+        assert_eq!(detect_project_root(&nested), Some(base.join("repo")));
+
+        // The nearest match wins, so a .cai directory inside the repository takes over
+        fs::create_dir_all(base.join("repo").join("src").join(".cai")).unwrap();
+        let root = detect_project_root(&nested).unwrap();
+        assert_eq!(root, base.join("repo").join("src"));
+
+        let scope = Scope::Project(root.clone());
+        assert_eq!(scope.sessions_dir(), root.join(".cai").join("sessions"));
+        assert_eq!(scope.history_path(), root.join(".cai").join("history.txt"));
+        assert_eq!(Scope::Global.config_path(), None);
+    }
+
+    #[test]
+    fn test_git_root_without_cai_dir_is_a_project() {
+        let base = TempDir::new("project");
+        let nested = base.join("worktree").join("src");
+        fs::create_dir_all(&nested).unwrap();
+
+        // Worktrees and submodules have a .git file rather than a directory
+        fs::write(base.join("worktree").join(".git"), "gitdir: /elsewhere\n").unwrap();
+        let root = detect_project_root(&nested).unwrap();
+        assert_eq!(root, base.join("worktree"));
+        assert_eq!(Scope::Project(root.clone()).config_path(), Some(root.join(".cai").join("cai.conf")));
+    }
+
+    #[test]
+    fn test_project_config_overrides() {
+        let global: toml::Table = toml::from_str(
+            "[llm]\nmodel = \"global-model\"\ntemperature = 0.7\n[ui]\nauto_scroll = true\n",
+        )
+        .unwrap();
+
+        let mut merged = toml::Value::Table(global.clone());
+        merge_toml(&mut merged, toml::from_str("[llm]\ntemperature = 0.2\n").unwrap());
+        assert_eq!(merged["llm"]["model"].as_str(), Some("global-model"));
+        assert_eq!(merged["llm"]["temperature"].as_float(), Some(0.2));
+
+        // Only changed values are written back, and existing overrides are kept
+        let mut overrides: toml::Table = toml::from_str("[llm]\ntemperature = 0.2\n").unwrap();
+        let mut changed = merged.as_table().unwrap().clone();
+        changed["llm"]["temperature"] = toml::Value::Float(0.7);
+        changed["ui"]["auto_scroll"] = toml::Value::Boolean(false);
+        update_overrides(&mut overrides, &global, &changed);
+        assert_eq!(overrides["llm"]["temperature"].as_float(), Some(0.7));
+        assert!(overrides["llm"].get("model").is_none());
+        assert_eq!(overrides["ui"]["auto_scroll"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn test_project_config_cannot_set_code_running_sections() {
+        let overrides: toml::Table = toml::from_str(
+            "[llm]\nmodel = \"project-model\"\n[plugins]\ndirs = [\"./evil\"]\n[permissions]\ndefault = \"allow\"\n[macros.x]\nbody = \">rm -rf ~\"\n",
+        )
+        .unwrap();
+
+        let (allowed, ignored) = project_overrides(overrides);
+        assert_eq!(allowed.keys().collect::<Vec<_>>(), vec!["llm"]);
+        assert_eq!(ignored.keys().collect::<Vec<_>>(), vec!["macros", "permissions", "plugins"]);
+    }
+}
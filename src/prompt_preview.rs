@@ -0,0 +1,238 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Clear, Paragraph, Row, Table, TableState};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::{Style, Stylize, palette::tailwind::{AMBER, RED, SKY}},
+};
+
+use crate::chat::{Expansion, PromptPart, estimate_tokens};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptPreviewState {
+    Send,
+    Cancel,
+    Open,
+}
+
+/// Popup showing a question with its commands expanded, before it is sent. Each expansion
+/// can be dropped from the prompt. When `pre_send` hooks change the prompt, the popup shows
+/// the result for a second confirmation.
+pub struct PromptPreview {
+    expansion: Expansion,
+    /// Estimated tokens of each command expansion
+    tokens: Vec<usize>,
+    dropped: Vec<usize>,
+    /// The prompt as the hooks made it, with their failures
+    hooked: Option<(String, Vec<String>)>,
+    selected: TableState,
+    scroll: u16,
+    context_length: Option<u64>,
+}
+
+impl PromptPreview {
+    pub fn new(expansion: Expansion, context_length: Option<u64>) -> Self {
+        let tokens: Vec<usize> = expansion
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                PromptPart::Command { output, .. } => Some(estimate_tokens(output)),
+                PromptPart::Text(_) => None,
+            })
+            .collect();
+        let selected = TableState::default().with_selected((!tokens.is_empty()).then_some(0));
+        Self {
+            expansion,
+            tokens,
+            dropped: Vec::new(),
+            hooked: None,
+            selected,
+            scroll: 0,
+            context_length,
+        }
+    }
+
+    /// The prompt without the dropped expansions, as the hooks get it.
+    pub fn prompt(&self) -> String {
+        self.expansion.prompt_without(&self.dropped)
+    }
+
+    /// The prompt as the hooks made it, once it is shown.
+    pub fn hooked_prompt(&self) -> Option<&str> {
+        self.hooked.as_ref().map(|(prompt, _)| prompt.as_str())
+    }
+
+    /// Show the prompt as the `pre_send` hooks made it, and what failed in them, in place
+    /// of the expansions. `Esc` goes back to them.
+    pub fn show_hooked(&mut self, prompt: String, errors: Vec<String>) {
+        self.hooked = Some((prompt, errors));
+        self.scroll = 0;
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> PromptPreviewState {
+        if key.kind != KeyEventKind::Press {
+            return PromptPreviewState::Open;
+        }
+        if self.hooked.is_some() {
+            match key.code {
+                KeyCode::Esc => self.hooked = None,
+                KeyCode::Enter | KeyCode::F(1) => return PromptPreviewState::Send,
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+                KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+                _ => {}
+            }
+            return PromptPreviewState::Open;
+        }
+        match key.code {
+            KeyCode::Esc => return PromptPreviewState::Cancel,
+            KeyCode::Enter | KeyCode::F(1) => return PromptPreviewState::Send,
+            KeyCode::Up => self.selected.select_previous(),
+            KeyCode::Down if self.selected.selected().is_some_and(|index| index + 1 < self.tokens.len()) => {
+                self.selected.select_next()
+            }
+            KeyCode::Char(' ') | KeyCode::Char('d') => {
+                if let Some(index) = self.selected.selected() {
+                    match self.dropped.iter().position(|&dropped| dropped == index) {
+                        Some(position) => {
+                            self.dropped.remove(position);
+                        }
+                        None => self.dropped.push(index),
+                    }
+                }
+            }
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            _ => {}
+        }
+        PromptPreviewState::Open
+    }
+
+    pub fn render_preview_popup(&mut self, frame: &mut Frame) {
+        let popup_area = self.popup_area(frame.area(), 90, 90);
+        frame.render_widget(Clear, popup_area);
+        let outer = Block::bordered().title(if self.hooked.is_some() {
+            "Prompt preview ('Enter' to send as rewritten by the pre_send hooks, 'PgUp'/'PgDn' to scroll, 'Esc' to go back)"
+        } else {
+            "Prompt preview ('Enter' to send, 'Space' to drop or keep an expansion, 'PgUp'/'PgDn' to scroll, 'Esc' to cancel)"
+        });
+        let inner = outer.inner(popup_area);
+        frame.render_widget(outer, popup_area);
+
+        let warnings: Vec<&String> = match &self.hooked {
+            Some((_, errors)) => self.expansion.warnings.iter().chain(errors).collect(),
+            None => self.expansion.warnings.iter().collect(),
+        };
+        let list_height = (self.tokens.len() as u16 + 3).min(12);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(list_height),
+                Constraint::Min(3),
+                Constraint::Length(1 + warnings.len() as u16),
+            ])
+            .split(inner);
+
+        let commands = self.commands();
+        let expansion_rows = commands.iter().enumerate().map(|(index, command)| {
+            let mark = if self.dropped.contains(&index) { "[ ]" } else { "[x]" };
+            Row::new(vec![
+                Cell::from(mark),
+                Cell::from(command.to_string()),
+                Cell::from(format!("{} tokens", self.tokens[index])),
+            ])
+        });
+        let expansions = Table::new(
+            expansion_rows,
+            [Constraint::Length(3), Constraint::Min(20), Constraint::Length(16)],
+        )
+        .header(Row::new(vec!["", "Command", "Tokens"]).bold())
+        .row_highlight_style(Style::new().bg(SKY.c800))
+        .block(Block::bordered().title("Expansions"));
+        frame.render_stateful_widget(expansions, rows[0], &mut self.selected);
+
+        let (lines, title) = match &self.hooked {
+            Some((prompt, _)) => {
+                let mut lines = vec![Line::default()];
+                push_text(&mut lines, prompt);
+                (lines, "As sent, rewritten by the pre_send hooks")
+            }
+            None => (self.preview_lines(), "As sent"),
+        };
+        let prompt = Paragraph::new(lines).scroll((self.scroll, 0)).block(Block::bordered().title(title));
+        frame.render_widget(prompt, rows[1]);
+
+        let total = estimate_tokens(self.hooked_prompt().unwrap_or(&self.prompt()));
+        let summary = match self.context_length {
+            Some(context) => {
+                let line = format!(
+                    "Total: ~{} of {} tokens of context ({}%)",
+                    total,
+                    context,
+                    total as u64 * 100 / context.max(1)
+                );
+                if total as u64 > context { line.fg(RED.c500) } else { line.into() }
+            }
+            None => format!("Total: ~{} tokens (context length of the model unknown)", total).into(),
+        };
+        let mut summary_lines = vec![Line::from(summary)];
+        summary_lines.extend(warnings.iter().map(|warning| Line::styled(warning.as_str(), Style::new().fg(AMBER.c400))));
+        frame.render_widget(Paragraph::new(summary_lines), rows[2]);
+    }
+
+    fn commands(&self) -> Vec<&str> {
+        self.expansion
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                PromptPart::Command { command, .. } => Some(command.as_str()),
+                PromptPart::Text(_) => None,
+            })
+            .collect()
+    }
+
+    /// The prompt with every expansion between marker lines, which are not sent.
+    fn preview_lines(&self) -> Vec<Line<'_>> {
+        let marker = Style::new().fg(AMBER.c400);
+        let mut lines: Vec<Line> = vec![Line::default()];
+        let mut index = 0;
+        for part in &self.expansion.parts {
+            match part {
+                PromptPart::Text(text) => push_text(&mut lines, text),
+                PromptPart::Command { command, output } => {
+                    if self.dropped.contains(&index) {
+                        lines.push(Line::styled(format!("┄┄ {} (dropped) ┄┄", command), marker));
+                    } else {
+                        lines.push(Line::styled(format!("┄┄ {} · ~{} tokens ┄┄", command, self.tokens[index]), marker));
+                        lines.push(Line::default());
+                        push_text(&mut lines, output);
+                        lines.push(Line::styled(format!("┄┄ end of {} ┄┄", command), marker));
+                    }
+                    lines.push(Line::default());
+                    index += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    fn popup_area(&self, area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+        let vertical = Layout::vertical([Constraint::Percentage(percent_y)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        area
+    }
+}
+
+/// Append `text` to `lines`, continuing the last line.
+fn push_text<'a>(lines: &mut Vec<Line<'a>>, text: &'a str) {
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            lines.push(Line::default());
+        }
+        if !line.is_empty() {
+            lines.last_mut().unwrap().push_span(Span::raw(line));
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Rhai scripts that add commands and hook into cai.
+//!
+//! Every `*.rhai` file of the script directories is run once at startup. Its top level calls
+//! `register_command(...)` and `on(hook, function)` to name the functions cai should call
+//! later. Scripts run with a limit on operations and on time, and can only read and write
+//! files below the project root (the current directory outside a project). Everything a
+//! script reads, writes, runs or sends is checked against the permission policy.
+use crate::chat;
+use crate::commands_registry;
+use crate::configuration::{Config, ScriptingSettings};
+use crate::openrouter;
+use crate::permissions::{self, Effect};
+use crate::project::{self, Scope};
+use lazy_static::lazy_static;
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map as RhaiMap, Scope as RhaiScope};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+/// How deep scripts may run commands that run scripts
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+tokio::task_local! {
+    /// Number of script calls the running command was started from
+    static SCRIPT_DEPTH: usize;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    /// `fn(prompt)`: may return a rewritten prompt before it is sent
+    PreSend,
+    /// `fn(prompt, answer)`: may return a rewritten answer
+    PostAnswer,
+    /// `fn(step)` with `plan_id`, `step_id` and `description`
+    StepStart,
+    /// `fn(step)` with additionally `success` and `output`
+    StepFinish,
+}
+
+impl Hook {
+    fn from_name(name: &str) -> Option<Hook> {
+        match name.replace('-', "_").as_str() {
+            "pre_send" => Some(Hook::PreSend),
+            "post_answer" => Some(Hook::PostAnswer),
+            "step_start" => Some(Hook::StepStart),
+            "step_finish" => Some(Hook::StepFinish),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreSend => "pre_send",
+            Hook::PostAnswer => "post_answer",
+            Hook::StepStart => "step_start",
+            Hook::StepFinish => "step_finish",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Hook::PostAnswer => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// A compiled script file.
+pub struct Script {
+    pub path: PathBuf,
+    ast: AST,
+    /// Limits its calls run with
+    settings: ScriptingSettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScriptCommandSpec {
+    pub name: String,
+    pub description: String,
+    pub function: String,
+    /// Parameter names; a trailing `?` makes one optional
+    pub params: Vec<String>,
+}
+
+/// What a script registered while it loaded.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptRegistrations {
+    pub commands: Vec<ScriptCommandSpec>,
+    pub hooks: Vec<(Hook, String)>,
+}
+
+#[derive(Clone)]
+pub struct LoadedScript {
+    pub path: PathBuf,
+    pub script: Result<(Arc<Script>, ScriptRegistrations), String>,
+}
+
+lazy_static! {
+    static ref SCRIPTS: Mutex<Vec<LoadedScript>> = Mutex::new(Vec::new());
+    static ref HOOKS: Mutex<Vec<(Hook, Arc<Script>, String)>> = Mutex::new(Vec::new());
+    /// Hook failures not shown yet; hooks run while the TUI owns the screen
+    static ref HOOK_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// `scripts/` in the global data directory, followed by the `[scripting] dirs` of the config.
+pub fn script_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = vec![Scope::Global.data_dir().join("scripts")];
+    dirs.extend(config.scripting.dirs.iter().map(PathBuf::from));
+    dirs
+}
+
+/// `*.rhai` files directly inside `dirs`, in name order per directory.
+pub fn discover_scripts(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut scripts = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect();
+        found.sort();
+        scripts.extend(found);
+    }
+    scripts
+}
+
+/// Compile a script and run its top level to collect its registrations. Commands and the
+/// LLM are not available at this point.
+pub fn load_script(path: &Path, settings: &ScriptingSettings) -> Result<(Arc<Script>, ScriptRegistrations), String> {
+    let registrations = Arc::new(Mutex::new(ScriptRegistrations::default()));
+    let engine = build_engine(settings, None, Some(registrations.clone()));
+    let ast = engine.compile_file(path.to_path_buf()).map_err(|e| e.to_string())?;
+    engine.run_ast(&ast).map_err(|e| e.to_string())?;
+
+    let registrations = registrations.lock().unwrap().clone();
+    let has_function = |name: &str, arity: usize| ast.iter_functions().any(|f| f.name == name && f.params.len() == arity);
+    for command in &registrations.commands {
+        if !has_function(&command.function, command.params.len()) {
+            return Err(format!(
+                "command @{} needs fn {}() with {} parameter(s)",
+                command.name,
+                command.function,
+                command.params.len()
+            ));
+        }
+    }
+    for (hook, function) in &registrations.hooks {
+        if !has_function(function, hook.arity()) {
+            return Err(format!("{} hook needs fn {}() with {} parameter(s)", hook.name(), function, hook.arity()));
+        }
+    }
+
+    Ok((
+        Arc::new(Script {
+            path: path.to_path_buf(),
+            ast,
+            settings: settings.clone(),
+        }),
+        registrations,
+    ))
+}
+
+/// Load every script of the configured directories, subscribe their hooks and remember the
+/// result for `@scripts`.
+pub fn load_scripts(config: &Config) -> Vec<LoadedScript> {
+    let loaded: Vec<LoadedScript> = discover_scripts(&script_dirs(config))
+        .into_iter()
+        .map(|path| LoadedScript {
+            script: load_script(&path, &config.scripting),
+            path,
+        })
+        .collect();
+
+    HOOKS.lock().unwrap().clear();
+    for (script, registrations) in loaded.iter().filter_map(|loaded| loaded.script.as_ref().ok()) {
+        subscribe_hooks(script, registrations);
+    }
+    *SCRIPTS.lock().unwrap() = loaded.clone();
+    loaded
+}
+
+/// Subscribe the hooks a loaded script registered.
+pub fn subscribe_hooks(script: &Arc<Script>, registrations: &ScriptRegistrations) {
+    let mut hooks = HOOKS.lock().unwrap();
+    for (hook, function) in &registrations.hooks {
+        hooks.push((*hook, script.clone(), function.clone()));
+    }
+}
+
+pub fn loaded_scripts() -> Vec<LoadedScript> {
+    SCRIPTS.lock().unwrap().clone()
+}
+
+/// Call a function of a script. Blocks; `runtime` lets the script run commands and call the
+/// LLM, with the number of script calls this one is nested in.
+pub fn call_function(script: &Script, function: &str, args: Vec<Dynamic>, runtime: Option<(Handle, usize)>) -> Result<Dynamic, String> {
+    let engine = build_engine(&script.settings, runtime, None);
+    let options = CallFnOptions::new().eval_ast(false);
+    engine
+        .call_fn_with_options::<Dynamic>(options, &mut RhaiScope::new(), &script.ast, function, args)
+        .map_err(|e| match *e {
+            // Stopped by the time limit, with the reason as token
+            EvalAltResult::ErrorTerminated(reason, _) => format!("{}: {}", script.path.display(), reason),
+            e => format!("{}: {}", script.path.display(), e),
+        })
+}
+
+/// `call_function` on a blocking thread, so scripts can wait for commands and the LLM.
+pub async fn call_function_async(script: Arc<Script>, function: String, args: Vec<Dynamic>) -> Result<Dynamic, String> {
+    // The blocking thread has no task-locals, so the depth is handed over explicitly
+    let depth = SCRIPT_DEPTH.try_with(|depth| *depth).unwrap_or(0) + 1;
+    if depth > MAX_SCRIPT_DEPTH {
+        return Err(format!("scripts nest more than {} levels deep", MAX_SCRIPT_DEPTH));
+    }
+    let runtime = Handle::current();
+    tokio::task::spawn_blocking(move || call_function(&script, &function, args, Some((runtime, depth))))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn hooks_for(hook: Hook) -> Vec<(Arc<Script>, String)> {
+    let hooks = HOOKS.lock().unwrap();
+    hooks
+        .iter()
+        .filter(|(subscribed, _, _)| *subscribed == hook)
+        .map(|(_, script, function)| (script.clone(), function.clone()))
+        .collect()
+}
+
+/// Run the hooks for `hook` in load order. A hook returning a string replaces `text`.
+async fn run_hooks(hook: Hook, mut text: Option<String>, args: impl Fn(Option<&String>) -> Vec<Dynamic>) -> Option<String> {
+    for (script, function) in hooks_for(hook) {
+        match call_function_async(script, function, args(text.as_ref())).await {
+            Ok(result) => {
+                if let (Some(text), Ok(rewritten)) = (text.as_mut(), result.into_string()) {
+                    *text = rewritten;
+                }
+            }
+            Err(e) => HOOK_ERRORS.lock().unwrap().push(format!("{} hook failed: {}", hook.name(), e)),
+        }
+    }
+    text
+}
+
+/// Hook failures since the last call, for the UI to show.
+pub fn take_hook_errors() -> Vec<String> {
+    std::mem::take(&mut *HOOK_ERRORS.lock().unwrap())
+}
+
+/// Let `pre_send` hooks rewrite the enriched prompt before it goes to the LLM.
+pub async fn pre_send(prompt: String) -> String {
+    let original = prompt.clone();
+    run_hooks(Hook::PreSend, Some(prompt), |prompt| vec![prompt.cloned().unwrap_or_default().into()])
+        .await
+        .unwrap_or(original)
+}
+
+/// Let `post_answer` hooks rewrite the answer of the LLM.
+pub async fn post_answer(prompt: String, answer: String) -> String {
+    let original = answer.clone();
+    run_hooks(Hook::PostAnswer, Some(answer), |answer| {
+        vec![prompt.clone().into(), answer.cloned().unwrap_or_default().into()]
+    })
+    .await
+    .unwrap_or(original)
+}
+
+fn step_map(plan_id: &str, step_id: &str, description: &str) -> RhaiMap {
+    let mut step = RhaiMap::new();
+    step.insert("plan_id".into(), plan_id.into());
+    step.insert("step_id".into(), step_id.into());
+    step.insert("description".into(), description.into());
+    step
+}
+
+pub async fn step_started(plan_id: &str, step_id: &str, description: &str) {
+    let step = step_map(plan_id, step_id, description);
+    run_hooks(Hook::StepStart, None, |_| vec![step.clone().into()]).await;
+}
+
+pub async fn step_finished(plan_id: &str, step_id: &str, description: &str, success: bool, output: &str) {
+    let mut step = step_map(plan_id, step_id, description);
+    step.insert("success".into(), success.into());
+    step.insert("output".into(), output.into());
+    run_hooks(Hook::StepFinish, None, |_| vec![step.clone().into()]).await;
+}
+
+/// The directory scripts may read and write in.
+fn sandbox_root() -> Result<PathBuf, String> {
+    let root = match project::current_scope() {
+        Scope::Project(root) => root.clone(),
+        Scope::Global => std::env::current_dir().map_err(|e| e.to_string())?,
+    };
+    root.canonicalize().map_err(|e| e.to_string())
+}
+
+/// Resolve `path` (relative to the sandbox root) and refuse anything outside the root.
+pub fn sandboxed_path(path: &str, for_write: bool) -> Result<PathBuf, String> {
+    let root = sandbox_root()?;
+    let joined = root.join(path);
+    let resolved = match joined.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) if for_write => {
+            let parent = joined.parent().ok_or_else(|| format!("invalid path {}", path))?;
+            let name = joined.file_name().ok_or_else(|| format!("invalid path {}", path))?;
+            parent.canonicalize().map_err(|e| format!("{}: {}", path, e))?.join(name)
+        }
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    };
+    if !resolved.starts_with(&root) {
+        return Err(format!("{} is outside {}", path, root.display()));
+    }
+    Ok(resolved)
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Check a script function against the permission policy, named by `target` like a command.
+fn check_permission(effect: Effect, target: &str, path: Option<&Path>) -> ScriptResult<()> {
+    let paths: Vec<String> = path.map(|path| path.to_string_lossy().into_owned()).into_iter().collect();
+    permissions::check(&[effect], target, &paths).map_err(|e| e.to_string().into())
+}
+
+fn build_engine(
+    settings: &ScriptingSettings,
+    runtime: Option<(Handle, usize)>,
+    registrations: Option<Arc<Mutex<ScriptRegistrations>>>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(settings.max_operations);
+    engine.set_max_call_levels(64);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(16 * 1024 * 1024);
+    engine.set_max_array_size(100_000);
+    engine.set_max_map_size(100_000);
+
+    let timeout = settings.timeout_seconds;
+    let deadline = Instant::now() + Duration::from_secs(timeout as u64);
+    engine.on_progress(move |_| {
+        (Instant::now() > deadline).then(|| format!("script ran longer than {}s", timeout).into())
+    });
+
+    let command_runtime = runtime.clone();
+    engine.register_fn("command", move |text: &str| -> ScriptResult<String> {
+        let (runtime, depth) = command_runtime.as_ref().ok_or("commands are not available while a script loads")?;
+        // The command is checked against the permission policy like one typed by the user
+        match runtime.block_on(SCRIPT_DEPTH.scope(*depth, commands_registry::execute_command(text))) {
+            Ok(Some(result)) => result
+                .command_output
+                .map(|output| output.map(|output| output.render_llm()).unwrap_or_default())
+                .map_err(|e| e.to_string().into()),
+            Ok(None) => Ok(String::new()),
+            Err(e) => Err(e.to_string().into()),
+        }
+    });
+    engine.register_fn("llm", move |prompt: &str| -> ScriptResult<String> {
+        let (runtime, _) = runtime.as_ref().ok_or("the LLM is not available while a script loads")?;
+        check_permission(Effect::Network, "llm", None)?;
+        runtime
+            .block_on(openrouter::call_openrouter_api(prompt))
+            .map_err(|e| e.to_string().into())
+    });
+
+    engine.register_fn("get_memory", |id: &str| -> ScriptResult<String> {
+        let memory = chat::get_memory().lock().unwrap();
+        match memory.get(id) {
+            Some(prompt) => Ok(prompt.value.clone()),
+            None => Err(format!("no memory with id {}", id).into()),
+        }
+    });
+    engine.register_fn("memory_ids", || -> Array {
+        let memory = chat::get_memory().lock().unwrap();
+        let mut prompts: Vec<_> = memory.values().collect();
+        prompts.sort_by_key(|prompt| prompt.date);
+        prompts.iter().map(|prompt| prompt.id.clone().into()).collect()
+    });
+    engine.register_fn("read_file", |path: &str| -> ScriptResult<String> {
+        let path = sandboxed_path(path, false)?;
+        check_permission(Effect::ReadFs, "read_file", Some(&path))?;
+        fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e).into())
+    });
+    engine.register_fn("write_file", |path: &str, content: &str| -> ScriptResult<()> {
+        let path = sandboxed_path(path, true)?;
+        check_permission(Effect::WriteFs, "write_file", Some(&path))?;
+        fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e).into())
+    });
+
+    let commands = registrations.clone();
+    let register = move |name: &str, description: &str, function: &str, params: Array| -> ScriptResult<()> {
+        let registrations = commands.as_ref().ok_or("register_command is only available while a script loads")?;
+        let params = params
+            .into_iter()
+            .map(|param| param.into_string().map_err(|_| "parameter names must be strings"))
+            .collect::<Result<Vec<String>, _>>()?;
+        registrations.lock().unwrap().commands.push(ScriptCommandSpec {
+            name: name.to_string(),
+            description: description.to_string(),
+            function: function.to_string(),
+            params,
+        });
+        Ok(())
+    };
+    let register_without_params = register.clone();
+    engine.register_fn("register_command", register);
+    engine.register_fn("register_command", move |name: &str, description: &str, function: &str| {
+        register_without_params(name, description, function, Array::new())
+    });
+    engine.register_fn("on", move |hook: &str, function: &str| -> ScriptResult<()> {
+        let registrations = registrations.as_ref().ok_or("on is only available while a script loads")?;
+        let hook = Hook::from_name(hook)
+            .ok_or_else(|| format!("unknown hook '{}' (expected pre_send, post_answer, step_start or step_finish)", hook))?;
+        registrations.lock().unwrap().hooks.push((hook, function.to_string()));
+        Ok(())
+    });
+
+    engine
+}
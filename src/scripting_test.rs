@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use crate::commands::script_cmd::register_script_command;
+    use crate::commands_registry::*;
+    use crate::configuration::ScriptingSettings;
+    use crate::permissions::{self, ConfirmationRequest};
+    use crate::scripting::{self, Hook, call_function, load_script, subscribe_hooks};
+    use crate::test_util::TempDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const SCRIPT: &str = r#"
+register_command("test-script-words", "Count words", "count_words", ["text", "unit?"]);
+register_command("test-script-call", "Run a command", "call_command");
+on("pre_send", "add_footer");
+on("post-answer", "shout");
+
+fn count_words(text, unit) {
+    let count = text.split(" ").len();
+    if unit == "" { `${count}` } else { `${count} ${unit}` }
+}
+fn call_command() { command("@test-script-helper(a b)") + "!" }
+fn add_footer(prompt) { prompt + "\n-- reviewed" }
+fn shout(prompt, answer) { answer.to_upper() }
+fn spin() { loop { } }
+fn read(path) { read_file(path) }
+fn write(path) { write_file(path, "x") }
+"#;
+
+    /// Write a script into a directory of its own, which is removed when the guard is dropped.
+    fn write_script(name: &str, source: &str) -> (TempDir, PathBuf) {
+        let dir = TempDir::new("scripts");
+        let path = dir.join(name);
+        fs::write(&path, source).unwrap();
+        (dir, path)
+    }
+
+    #[tokio::test]
+    async fn test_script_commands_and_hooks() {
+        register_command(Command::new(
+            "test-script-helper".to_string(),
+            Sigil::At,
+            vec![ArgSpec::required("text")],
+            "Upper-case a value".to_string(),
+            "@test-script-helper(text)".to_string(),
+            sync_handler(|params| Ok(Some(params[0].to_uppercase()))),
+            "test".to_string(),
+        ));
+
+        let (_dir, path) = write_script("words.rhai", SCRIPT);
+        assert_eq!(scripting::discover_scripts(&[path.parent().unwrap().to_path_buf()]), vec![path.clone()]);
+        let (script, registrations) = load_script(&path, &ScriptingSettings::default()).unwrap();
+        assert_eq!(registrations.commands.len(), 2);
+        assert_eq!(registrations.hooks, vec![
+            (Hook::PreSend, "add_footer".to_string()),
+            (Hook::PostAnswer, "shout".to_string()),
+        ]);
+        for spec in &registrations.commands {
+            register_script_command(&script, spec).unwrap();
+        }
+
+        // Script commands may write files and run commands, so they need confirmation
+        let result = execute_command("@test-script-words(one two three)").await.unwrap().unwrap();
+        assert!(result.command_output.unwrap_err().to_string().contains("needs confirmation"));
+        for name in ["@test-script-words", "@test-script-call"] {
+            permissions::grant(&ConfirmationRequest { action: name.to_string(), effects: vec![] }, true);
+        }
+        assert!(register_script_command(&script, &registrations.commands[0]).is_err());
+
+        let result = execute_command("@test-script-words(one two three)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("3".into()));
+        let result = execute_command("@test-script-words(one two, unit=words)").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("2 words".into()));
+        let result = execute_command("@test-script-call()").await.unwrap().unwrap();
+        assert_eq!(result.command_output.unwrap(), Some("A B!".into()));
+
+        subscribe_hooks(&script, &registrations);
+        assert_eq!(scripting::pre_send("Question".to_string()).await, "Question\n-- reviewed");
+        assert_eq!(scripting::post_answer("q".to_string(), "answer".to_string()).await, "ANSWER");
+
+        // A failing hook is skipped and kept for the UI to show, not printed over it
+        let (_failing_dir, failing) = write_script("failing.rhai", "on(\"post_answer\", \"fail\");\nfn fail(prompt, answer) { throw \"boom\" }");
+        let (failing_script, failing_registrations) = load_script(&failing, &ScriptingSettings::default()).unwrap();
+        subscribe_hooks(&failing_script, &failing_registrations);
+        assert_eq!(scripting::post_answer("q".to_string(), "answer".to_string()).await, "ANSWER");
+        let errors = scripting::take_hook_errors();
+        assert!(errors.len() == 1 && errors[0].contains("post_answer hook failed") && errors[0].contains("boom"), "{:?}", errors);
+        assert!(scripting::take_hook_errors().is_empty());
+    }
+
+    #[test]
+    fn test_script_command_names_are_checked() {
+        let (_dir, path) = write_script("names.rhai", SCRIPT);
+        let (script, registrations) = load_script(&path, &ScriptingSettings::default()).unwrap();
+        let mut spec = registrations.commands[0].clone();
+
+        spec.name = "test script;rm".to_string();
+        assert!(register_script_command(&script, &spec).unwrap_err().contains("not a valid command name"));
+        spec.name = "test-script-bad-param".to_string();
+        spec.params = vec!["te)xt".to_string()];
+        assert!(register_script_command(&script, &spec).unwrap_err().contains("not a valid parameter name"));
+        spec.params = vec!["unit?".to_string(), "text".to_string()];
+        assert!(register_script_command(&script, &spec).unwrap_err().contains("follows an optional one"));
+        assert!(get_command("test-script-bad-param").is_none());
+    }
+
+    #[test]
+    fn test_script_limits_and_sandbox() {
+        let (_dir, path) = write_script("limits.rhai", SCRIPT);
+        let settings = ScriptingSettings {
+            max_operations: 10_000,
+            ..ScriptingSettings::default()
+        };
+        let (script, _) = load_script(&path, &settings).unwrap();
+        let error = call_function(&script, "spin", vec![], None).unwrap_err();
+        assert!(error.contains("Too many operations"), "{}", error);
+
+        let settings = ScriptingSettings {
+            max_operations: 0,
+            timeout_seconds: 1,
+            ..ScriptingSettings::default()
+        };
+        let (script, _) = load_script(&path, &settings).unwrap();
+        let error = call_function(&script, "spin", vec![], None).unwrap_err();
+        assert!(error.contains("longer than 1s"), "{}", error);
+
+        // Files are limited to the project (here the crate) directory
+        let manifest = call_function(&script, "read", vec!["Cargo.toml".into()], None).unwrap();
+        assert!(manifest.into_string().unwrap().contains("[package]"));
+        assert!(call_function(&script, "read", vec!["/etc/hostname".into()], None).is_err());
+        assert!(call_function(&script, "write", vec!["../escaped.txt".into()], None).is_err());
+        // Writes inside the sandbox are still subject to the permission policy
+        let error = call_function(&script, "write", vec!["script-output.txt".into()], None).unwrap_err();
+        assert!(error.contains("write_file") && error.contains("needs confirmation"), "{}", error);
+        assert!(!std::path::Path::new("script-output.txt").exists());
+        // Commands and the LLM need a runtime, which scripts only get when called from one
+        let error = call_function(&script, "call_command", vec![], None).unwrap_err();
+        assert!(error.contains("not available"), "{}", error);
+
+        let (_broken_dir, broken) = write_script("broken.rhai", "on(\"pre_send\", \"missing\");");
+        assert!(load_script(&broken, &settings).err().unwrap().contains("fn missing()"));
+    }
+}
@@ -1,25 +1,54 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
-use std::fs;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
-use directories::ProjectDirs;
 
 use crate::chat::{Prompt, get_memory};
+use crate::configuration::{self, Config, LlmSettings, MemorySettings};
+use crate::project;
+use crate::session_bundle::{self, PackReport};
+use crate::session_crypto::{self, SessionKey};
+use crate::session_import::{self, ImportFormat};
+use crate::session_stats::{self, SessionStats};
 
 lazy_static! {
     static ref SESSION_MANAGER: Mutex<SessionManager> = Mutex::new(SessionManager::new());
+    static ref PASSPHRASE_REQUEST: Mutex<Option<PassphraseRequest>> = Mutex::new(None);
+    // Files read into prompts since the current session was loaded
+    static ref REFERENCED_FILES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
 }
 
+type Migration = fn(&mut Value) -> Result<(), Box<dyn std::error::Error>>;
+
+/// Created and last-accessed times of a session
+type SessionTimes = (DateTime<Utc>, DateTime<Utc>);
+
+/// Session name, snapshot directory, restored file count and skipped files of an unpacked bundle
+type Unpacked = (String, PathBuf, usize, Vec<String>);
+
+/// Upgrades applied in order to session files on load. `MIGRATIONS[n]` upgrades a
+/// file from schema version n to n + 1; append a step whenever `Session` or `Prompt` changes.
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2, migrate_v2_to_v3];
+
+pub const SESSION_SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
+    #[serde(default)]
+    pub schema_version: u32,
     pub name: String,
     pub created: DateTime<Utc>,
     pub last_accessed: DateTime<Utc>,
     pub memory: HashMap<String, Prompt>,
     pub config_overrides: Option<SessionConfig>,
+    /// Files pulled into prompts with `@read-file`/`@read-files`, as they were referenced.
+    #[serde(default)]
+    pub referenced_files: BTreeSet<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +56,93 @@ pub struct SessionConfig {
     pub model: Option<String>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f64>,
+    #[serde(default)]
+    pub top_p: Option<f64>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+impl SessionConfig {
+    /// Capture the LLM settings of a session override config. Only values that differ
+    /// from the defaults are kept, matching how `get_effective_config` merges them.
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = LlmSettings::default();
+        SessionConfig {
+            model: (config.llm.model != defaults.model).then(|| config.llm.model.clone()),
+            max_tokens: (config.llm.max_tokens != defaults.max_tokens).then_some(config.llm.max_tokens),
+            temperature: (config.llm.temperature != defaults.temperature).then_some(config.llm.temperature),
+            top_p: (config.llm.top_p != defaults.top_p).then_some(config.llm.top_p),
+            system_prompt: config.llm.system_prompt.clone(),
+        }
+    }
+
+    pub fn to_config(&self) -> Config {
+        let mut config = Config::default();
+        if let Some(model) = &self.model {
+            config.llm.model = model.clone();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.llm.max_tokens = max_tokens;
+        }
+        if let Some(temperature) = self.temperature {
+            config.llm.temperature = temperature;
+        }
+        if let Some(top_p) = self.top_p {
+            config.llm.top_p = top_p;
+        }
+        config.llm.system_prompt = self.system_prompt.clone();
+        config
+    }
+
+    /// Apply the overridden values on top of `llm`.
+    pub fn apply_to(&self, llm: &mut LlmSettings) {
+        if let Some(model) = &self.model {
+            llm.model = model.clone();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            llm.max_tokens = max_tokens;
+        }
+        if let Some(temperature) = self.temperature {
+            llm.temperature = temperature;
+        }
+        if let Some(top_p) = self.top_p {
+            llm.top_p = top_p;
+        }
+        if self.system_prompt.is_some() {
+            llm.system_prompt = self.system_prompt.clone();
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.model.is_none()
+            && self.max_tokens.is_none()
+            && self.temperature.is_none()
+            && self.top_p.is_none()
+            && self.system_prompt.is_none()
+    }
+}
+
+/// A prompt appended to the crash-recovery journal, tagged with the session that was
+/// active when it was created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    session: Option<String>,
+    prompt: Prompt,
+}
+
+/// What a passphrase entered in the UI is used for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassphraseAction {
+    Unlock,  // unlock an encrypted session and switch to it
+    Encrypt, // encrypt a session (or change its passphrase)
+}
+
+/// A command that needs a passphrase leaves a request behind; the UI asks for the
+/// passphrase so it never appears in the prompt text or the input history.
+#[derive(Debug, Clone)]
+pub struct PassphraseRequest {
+    pub session: String,
+    pub action: PassphraseAction,
 }
 
 #[derive(Debug)]
@@ -34,17 +150,14 @@ pub struct SessionManager {
     pub current_session: Option<String>,
     pub sessions_dir: PathBuf,
     pub auto_save: bool,
+    /// Keys of the encrypted sessions unlocked in this process, by session name.
+    pub session_keys: HashMap<String, SessionKey>,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
-        let sessions_dir = if let Some(proj_dirs) = ProjectDirs::from("", "", "cai") {
-            let mut dir = proj_dirs.data_dir().to_path_buf();
-            dir.push("sessions");
-            dir
-        } else {
-            PathBuf::from(".cai/sessions")
-        };
+        // Project-local when started inside a project, global otherwise
+        let sessions_dir = project::current_scope().sessions_dir();
 
         // Ensure sessions directory exists
         if !sessions_dir.exists() {
@@ -57,20 +170,21 @@ impl SessionManager {
             current_session: None,
             sessions_dir,
             auto_save: true,
+            session_keys: HashMap::new(),
         }
     }
 
     pub fn create_session(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if name.is_empty() || name.contains('/') || name.contains('\\') {
-            return Err("Invalid session name".into());
-        }
+        validate_session_name(name)?;
 
         let session = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
             name: name.to_string(),
             created: Utc::now(),
             last_accessed: Utc::now(),
             memory: HashMap::new(),
             config_overrides: None,
+            referenced_files: BTreeSet::new(),
         };
 
         self.save_session(&session)?;
@@ -97,6 +211,13 @@ impl SessionManager {
                 memory.insert(key.clone(), value.clone());
             }
         }
+        *REFERENCED_FILES.lock().unwrap() = session.referenced_files.clone();
+
+        // Apply the session's stored configuration overrides
+        match &session.config_overrides {
+            Some(overrides) => configuration::set_session_config_override(overrides.to_config()),
+            None => configuration::clear_session_config_override(),
+        }
 
         // Save the updated last_accessed time
         self.save_session(&session)?;
@@ -116,39 +237,297 @@ impl SessionManager {
                 session.memory.insert(key.clone(), value.clone());
             }
         }
+        session.referenced_files = REFERENCED_FILES.lock().unwrap().clone();
         
         session.last_accessed = Utc::now();
         self.save_session(&session)?;
+
+        // Everything in the journal is now on disk
+        if self.current_session.as_deref() == Some(session_name) {
+            self.clear_journal()?;
+        }
+        Ok(())
+    }
+
+    /// Create an empty session derived from `source`, as `@session-replay` does. A session
+    /// derived from an encrypted one is encrypted with the same passphrase.
+    pub fn create_derived_session(&mut self, source: &str, name: &str, overrides: Option<SessionConfig>) -> Result<(), Box<dyn std::error::Error>> {
+        validate_session_name(name)?;
+        if self.get_session_path(name).exists() {
+            return Err(format!("Session '{}' already exists", name).into());
+        }
+
+        if let Some(key) = self.session_keys.get(source).cloned() {
+            self.session_keys.insert(name.to_string(), key);
+        }
+
+        let session = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: name.to_string(),
+            created: Utc::now(),
+            last_accessed: Utc::now(),
+            memory: HashMap::new(),
+            config_overrides: overrides.filter(|o| !o.is_empty()),
+            referenced_files: BTreeSet::new(),
+        };
+        self.save_session(&session)
+    }
+
+    /// Add prompts to a stored session. When it is the current session they are added to
+    /// the in-memory context too, so the next save keeps them.
+    pub fn append_prompts(&self, name: &str, prompts: &[Prompt]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut session = self.load_session(name)?;
+        for prompt in prompts {
+            session.memory.insert(prompt.id.clone(), prompt.clone());
+        }
+        self.save_session(&session)?;
+
+        if self.current_session.as_deref() == Some(name) {
+            let mut memory = get_memory().lock().unwrap();
+            for prompt in prompts {
+                memory.insert(prompt.id.clone(), prompt.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save_config_overrides_to_session(&self, session_name: &str, overrides: Option<SessionConfig>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut session = self.load_session(session_name)?;
+        session.config_overrides = overrides.filter(|o| !o.is_empty());
+        self.save_session(&session)?;
         Ok(())
     }
 
     pub fn list_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut sessions = Vec::new();
-        
-        if !self.sessions_dir.exists() {
-            return Ok(sessions);
+        list_session_files(&self.sessions_dir)
+    }
+
+    pub fn list_archived_sessions(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        list_session_files(&self.archive_dir())
+    }
+
+    /// Copy a session under a new name. With `up_to`, only prompts up to and including
+    /// that prompt (by date) are copied. Returns the number of copied prompts.
+    pub fn fork_session(&mut self, source: &str, new_name: &str, up_to: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        validate_session_name(new_name)?;
+        if self.get_session_path(new_name).exists() {
+            return Err(format!("Session '{}' already exists", new_name).into());
         }
 
-        for entry in fs::read_dir(&self.sessions_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() && path.extension().map_or(false, |ext| ext == "json") {
-                if let Some(stem) = path.file_stem() {
-                    if let Some(name) = stem.to_str() {
-                        sessions.push(name.to_string());
-                    }
-                }
+        if self.current_session.as_deref() == Some(source) {
+            self.save_current_memory_to_session(source)?;
+        }
+        let mut session = self.load_session(source)?;
+
+        if let Some(prompt_id) = up_to {
+            let cutoff = session
+                .memory
+                .get(prompt_id)
+                .map(|prompt| prompt.date)
+                .ok_or_else(|| format!("Prompt '{}' not found in session '{}'", prompt_id, source))?;
+            session.memory.retain(|_, prompt| prompt.date <= cutoff);
+        }
+
+        // A fork of an encrypted session stays encrypted with the same passphrase
+        if let Some(key) = self.session_keys.get(source).cloned() {
+            self.session_keys.insert(new_name.to_string(), key);
+        }
+
+        session.name = new_name.to_string();
+        session.created = Utc::now();
+        session.last_accessed = Utc::now();
+        self.save_session(&session)?;
+        Ok(session.memory.len())
+    }
+
+    pub fn rename_session(&mut self, old_name: &str, new_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        validate_session_name(new_name)?;
+        let old_path = self.get_session_path(old_name);
+        let new_path = self.get_session_path(new_name);
+        if !old_path.exists() {
+            return Err(format!("Session '{}' not found", old_name).into());
+        }
+        if new_path.exists() {
+            return Err(format!("Session '{}' already exists", new_name).into());
+        }
+
+        let is_current = self.current_session.as_deref() == Some(old_name);
+        if is_current {
+            self.save_current_memory_to_session(old_name)?;
+        }
+
+        let mut session = self.load_session(old_name)?;
+        session.name = new_name.to_string();
+        if let Some(key) = self.session_keys.remove(old_name) {
+            self.session_keys.insert(new_name.to_string(), key);
+        }
+        fs::rename(&old_path, &new_path)?;
+        self.save_session(&session)?;
+
+        if is_current {
+            self.current_session = Some(new_name.to_string());
+        }
+        Ok(())
+    }
+
+    /// Combine the memory of two sessions, deduplicated by prompt id. The result is
+    /// written to `into`, or to `second` when no target is given. The result is
+    /// encrypted if either input was. Returns the number of prompts in the merged session.
+    pub fn merge_sessions(&mut self, first: &str, second: &str, into: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        if let Some(current) = &self.current_session
+            && (current == first || current == second)
+        {
+            self.save_current_memory_to_session(current)?;
+        }
+
+        let first_session = self.load_session(first)?;
+        let mut merged = self.load_session(second)?;
+
+        if let Some(target) = into {
+            validate_session_name(target)?;
+            if target != first && target != second && self.get_session_path(target).exists() {
+                return Err(format!("Session '{}' already exists", target).into());
             }
+            merged.name = target.to_string();
         }
 
-        sessions.sort();
-        Ok(sessions)
+        for (id, prompt) in first_session.memory {
+            merged.memory.entry(id).or_insert(prompt);
+        }
+        merged.referenced_files.extend(first_session.referenced_files);
+        if !self.session_keys.contains_key(&merged.name) {
+            let key = self.session_keys.get(second).or_else(|| self.session_keys.get(first)).cloned();
+            if let Some(key) = key {
+                self.session_keys.insert(merged.name.clone(), key);
+            }
+        }
+        merged.created = merged.created.min(first_session.created);
+        merged.last_accessed = Utc::now();
+        self.save_session(&merged)?;
+
+        // Reload memory if the active session was the merge target
+        if self.current_session.as_deref() == Some(merged.name.as_str()) {
+            let mut memory = get_memory().lock().unwrap();
+            memory.clear();
+            for (key, value) in &merged.memory {
+                memory.insert(key.clone(), value.clone());
+            }
+            *REFERENCED_FILES.lock().unwrap() = merged.referenced_files.clone();
+        }
+
+        Ok(merged.memory.len())
+    }
+
+    /// Move a session into the archive subdirectory, which `@session-list` hides by default.
+    pub fn archive_session(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.current_session.as_deref() == Some(name) {
+            return Err(format!("Cannot archive the active session '{}'", name).into());
+        }
+        let session_path = self.get_session_path(name);
+        if !session_path.exists() {
+            return Err(format!("Session '{}' not found", name).into());
+        }
+
+        let archive_dir = self.archive_dir();
+        fs::create_dir_all(&archive_dir)?;
+        let archive_path = archive_dir.join(format!("{}.json", name));
+        if archive_path.exists() {
+            return Err(format!("An archived session named '{}' already exists", name).into());
+        }
+        fs::rename(session_path, archive_path)?;
+        Ok(())
+    }
+
+    pub fn unarchive_session(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let archive_path = self.archive_dir().join(format!("{}.json", name));
+        if !archive_path.exists() {
+            return Err(format!("Archived session '{}' not found", name).into());
+        }
+        let session_path = self.get_session_path(name);
+        if session_path.exists() {
+            return Err(format!("Session '{}' already exists", name).into());
+        }
+        fs::rename(archive_path, session_path)?;
+        Ok(())
+    }
+
+    pub fn get_archived_session_info(&self, name: &str) -> Result<Session, Box<dyn std::error::Error>> {
+        let (session, _) = read_session_file(
+            &self.archive_dir().join(format!("{}.json", name)),
+            self.session_keys.get(name),
+        )?;
+        Ok(session)
+    }
+
+    /// Created and last-accessed times of an encrypted session, read without decrypting it.
+    /// Returns None for sessions that are not encrypted.
+    pub fn get_encrypted_session_header(&self, name: &str, archived: bool) -> Result<Option<SessionTimes>, Box<dyn std::error::Error>> {
+        let path = if archived {
+            self.archive_dir().join(format!("{}.json", name))
+        } else {
+            self.get_session_path(name)
+        };
+        let value: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if !session_crypto::is_encrypted(&value) {
+            return Ok(None);
+        }
+        let created = serde_json::from_value(value["created"].clone())?;
+        let last_accessed = serde_json::from_value(value["last_accessed"].clone())?;
+        Ok(Some((created, last_accessed)))
+    }
+
+    pub fn is_session_encrypted(&self, name: &str) -> bool {
+        matches!(self.get_encrypted_session_header(name, false), Ok(Some(_)))
+    }
+
+    /// Check the passphrase of an encrypted session and keep its key for this process.
+    pub fn unlock_session(&mut self, name: &str, passphrase: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let session_path = self.get_session_path(name);
+        if !session_path.exists() {
+            return Err(format!("Session '{}' not found", name).into());
+        }
+        let envelope: Value = serde_json::from_str(&fs::read_to_string(&session_path)?)?;
+        if !session_crypto::is_encrypted(&envelope) {
+            return Err(format!("Session '{}' is not encrypted", name).into());
+        }
+
+        let key = SessionKey::for_envelope(passphrase, &envelope)?;
+        session_crypto::decrypt_session(&envelope, &key)
+            .map_err(|e| format!("Cannot unlock session '{}': {}", name, e))?;
+        self.session_keys.insert(name.to_string(), key);
+        Ok(())
     }
 
-    pub fn delete_session(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// Encrypt a session with a passphrase, or re-encrypt it with a new one.
+    /// An already encrypted session must be unlocked first.
+    pub fn encrypt_session(&mut self, name: &str, passphrase: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.current_session.as_deref() == Some(name) {
+            self.save_current_memory_to_session(name)?;
+        }
+        let session = self.load_session(name)?;
+        self.session_keys.insert(name.to_string(), SessionKey::new(passphrase)?);
+        self.save_session(&session)
+    }
+
+    /// Store an unlocked encrypted session in plain text again.
+    pub fn decrypt_session(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.is_session_encrypted(name) {
+            return Err(format!("Session '{}' is not encrypted", name).into());
+        }
+        let session = self.load_session(name)?;
+        self.session_keys.remove(name);
+        self.save_session(&session)
+    }
+
+    fn archive_dir(&self) -> PathBuf {
+        self.sessions_dir.join("archive")
+    }
+
+    pub fn delete_session(&mut self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
         let session_path = self.get_session_path(name);
         if session_path.exists() {
             fs::remove_file(session_path)?;
+            self.session_keys.remove(name);
             Ok(())
         } else {
             Err(format!("Session '{}' not found", name).into())
@@ -157,34 +536,265 @@ impl SessionManager {
 
     pub fn export_session(&self, name: &str, export_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let session = self.load_session(name)?;
-        
-        let mut content = String::new();
-        content.push_str(&format!("# Session: {}\n", session.name));
-        content.push_str(&format!("Created: {}\n", session.created.format("%Y-%m-%d %H:%M:%S")));
-        content.push_str(&format!("Last Accessed: {}\n\n", session.last_accessed.format("%Y-%m-%d %H:%M:%S")));
+        write_export_file(export_path, &session_to_markdown(&session))
+    }
 
-        // Sort prompts by date
-        let mut prompts: Vec<&Prompt> = session.memory.values().collect();
-        prompts.sort_by(|a, b| a.date.cmp(&b.date));
+    pub fn get_session_stats(&self, name: &str) -> Result<SessionStats, Box<dyn std::error::Error>> {
+        if self.current_session.as_deref() == Some(name) {
+            self.save_current_memory_to_session(name)?;
+        }
+        let session = self.load_session(name)?;
+        Ok(session_stats::compute_stats(&session))
+    }
 
-        for prompt in prompts {
-            content.push_str(&format!("## {} ({})\n", prompt.id, prompt.date.format("%Y-%m-%d %H:%M:%S")));
-            content.push_str(&format!("Type: {:?}\n\n", prompt.ptype));
-            content.push_str(&prompt.value);
-            content.push_str("\n\n---\n\n");
+    /// Write a session and snapshots of the files it referenced into a portable bundle.
+    pub fn pack_session(&self, name: &str, out_path: &str) -> Result<PackReport, Box<dyn std::error::Error>> {
+        if self.current_session.as_deref() == Some(name) {
+            self.save_current_memory_to_session(name)?;
         }
+        if self.is_session_encrypted(name) {
+            return Err(format!("Session '{}' is encrypted; decrypt it before packing", name).into());
+        }
+        let session = self.load_session(name)?;
+        session_bundle::write_bundle(&session, out_path)
+    }
+
+    /// Restore a bundle as a new session, named `name` or the bundled name (numbered if
+    /// taken). File snapshots go to `snapshots/<session>/` next to the sessions directory.
+    /// Returns the session name, the snapshot directory and the restored and skipped files.
+    pub fn unpack_session(&self, bundle_path: &str, name: Option<&str>) -> Result<Unpacked, Box<dyn std::error::Error>> {
+        let contents = session_bundle::read_bundle(bundle_path)?;
+
+        let session_name = match name {
+            Some(name) => {
+                validate_session_name(name)?;
+                if self.get_session_path(name).exists() {
+                    return Err(format!("Session '{}' already exists", name).into());
+                }
+                name.to_string()
+            }
+            None => {
+                let base = contents.session.name.clone();
+                validate_session_name(&base)?;
+                let mut candidate = base.clone();
+                let mut suffix = 1;
+                while self.get_session_path(&candidate).exists() {
+                    suffix += 1;
+                    candidate = format!("{}-{}", base, suffix);
+                }
+                candidate
+            }
+        };
+
+        let mut session = contents.session;
+        session.name = session_name.clone();
+        session.last_accessed = Utc::now();
+
+        let files_dir = self
+            .sessions_dir
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("snapshots")
+            .join(&session_name);
+        let (restored, skipped) = session_bundle::restore_files(&contents.files, &files_dir)?;
+
+        self.save_session(&session)?;
+        Ok((session_name, files_dir, restored, skipped))
+    }
 
-        // Ensure parent directory exists
-        if let Some(parent) = Path::new(export_path).parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent)?;
+    /// Export the current session, or the unsaved memory when no session is active,
+    /// into the exports directory next to the sessions directory.
+    pub fn auto_export(&self, format: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let session = match &self.current_session {
+            Some(name) if self.session_keys.contains_key(name) => {
+                return Err(format!("Session '{}' is encrypted; not exporting it in plain text", name).into());
             }
+            Some(name) => self.load_session(name)?,
+            None => Session {
+                schema_version: SESSION_SCHEMA_VERSION,
+                name: "unsaved".to_string(),
+                created: Utc::now(),
+                last_accessed: Utc::now(),
+                memory: get_memory().lock().unwrap().clone(),
+                config_overrides: None,
+                referenced_files: REFERENCED_FILES.lock().unwrap().clone(),
+            },
+        };
+
+        let (content, extension) = match format {
+            "json" => (serde_json::to_string_pretty(&session)?, "json"),
+            _ => (session_to_markdown(&session), "md"),
+        };
+
+        let mut export_path = self
+            .sessions_dir
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        export_path.push("exports");
+        export_path.push(format!(
+            "{}-{}.{}",
+            session.name,
+            Utc::now().format("%Y%m%d-%H%M%S"),
+            extension
+        ));
+
+        let export_path = export_path.display().to_string();
+        write_export_file(&export_path, &content)?;
+        Ok(export_path)
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.sessions_dir.join("journal.jsonl")
+    }
+
+    /// Append a new prompt to the journal so it survives a crash before the next save.
+    /// Prompts of encrypted sessions are not journaled, as the journal is plain text.
+    pub fn append_to_journal(&self, prompt: &Prompt) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(name) = &self.current_session
+            && self.session_keys.contains_key(name)
+        {
+            return Ok(());
         }
+        let entry = JournalEntry {
+            session: self.current_session.clone(),
+            prompt: prompt.clone(),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.journal_path())?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
 
-        fs::write(export_path, content)?;
+    pub fn clear_journal(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let journal_path = self.journal_path();
+        if journal_path.exists() {
+            fs::remove_file(journal_path)?;
+        }
         Ok(())
     }
 
+    /// Replay a journal left behind by an unclean exit. Prompts are merged into the
+    /// session they belong to and the last active session is switched to again;
+    /// prompts created without a session are restored into memory.
+    /// Returns the number of recovered prompts.
+    pub fn recover_from_journal(&mut self) -> Result<usize, Box<dyn std::error::Error>> {
+        let journal_path = self.journal_path();
+        if !journal_path.exists() {
+            return Ok(0);
+        }
+
+        let mut entries = Vec::new();
+        for line in fs::read_to_string(&journal_path)?.lines() {
+            // A crash mid-write can leave a truncated last line
+            if let Ok(entry) = serde_json::from_str::<JournalEntry>(line) {
+                entries.push(entry);
+            }
+        }
+
+        let mut recovered = 0;
+        let mut unsaved: Vec<Prompt> = Vec::new();
+        let mut by_session: HashMap<String, Vec<Prompt>> = HashMap::new();
+        for entry in &entries {
+            match &entry.session {
+                Some(name) if self.get_session_path(name).exists() => {
+                    by_session.entry(name.clone()).or_default().push(entry.prompt.clone());
+                }
+                _ => unsaved.push(entry.prompt.clone()),
+            }
+        }
+
+        for (name, prompts) in by_session {
+            let mut session = self.load_session(&name)?;
+            for prompt in prompts {
+                if !session.memory.contains_key(&prompt.id) {
+                    session.memory.insert(prompt.id.clone(), prompt);
+                    recovered += 1;
+                }
+            }
+            self.save_session(&session)?;
+        }
+
+        // Switch first: switching replaces the memory, which would drop the unsaved prompts
+        let last_session = entries.last().and_then(|entry| entry.session.clone());
+        if let Some(name) = last_session
+            && self.current_session.is_none()
+            && self.get_session_path(&name).exists()
+        {
+            self.switch_to_session(&name)?;
+        }
+
+        {
+            let mut memory = get_memory().lock().unwrap();
+            for prompt in unsaved {
+                if !memory.contains_key(&prompt.id) {
+                    memory.insert(prompt.id.clone(), prompt);
+                    recovered += 1;
+                }
+            }
+        }
+        if let Some(name) = &self.current_session {
+            self.save_current_memory_to_session(name)?;
+        }
+
+        self.clear_journal()?;
+
+        Ok(recovered)
+    }
+
+    /// Save imported sessions. A session whose name is already taken is merged into
+    /// the existing one when they share prompt ids (the same file imported again),
+    /// otherwise it is saved under a numbered name.
+    pub fn import_sessions(&self, sessions: Vec<Session>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut report = Vec::new();
+
+        for imported in sessions {
+            let mut name = imported.name.clone();
+            let mut suffix = 1;
+
+            loop {
+                if !self.get_session_path(&name).exists() {
+                    let mut session = imported.clone();
+                    session.name = name.clone();
+                    self.save_session(&session)?;
+                    report.push(format!("{} (created, {} prompts)", name, session.memory.len()));
+                    break;
+                }
+
+                let mut existing = self.load_session(&name)?;
+                let same_conversation = imported
+                    .memory
+                    .keys()
+                    .any(|id| existing.memory.contains_key(id));
+
+                if same_conversation {
+                    let mut added = 0;
+                    for (id, prompt) in &imported.memory {
+                        if !existing.memory.contains_key(id) {
+                            existing.memory.insert(id.clone(), prompt.clone());
+                            added += 1;
+                        }
+                    }
+                    if added > 0 {
+                        self.save_session(&existing)?;
+                        report.push(format!("{} (updated, {} new prompts)", name, added));
+                    } else {
+                        report.push(format!("{} (already imported, skipped)", name));
+                    }
+                    break;
+                }
+
+                suffix += 1;
+                name = format!("{}-{}", imported.name, suffix);
+            }
+        }
+
+        Ok(report)
+    }
+
     pub fn get_current_session(&self) -> Option<&String> {
         self.current_session.as_ref()
     }
@@ -193,10 +803,27 @@ impl SessionManager {
         self.load_session(name)
     }
 
+    /// Write the session to a temporary file and rename it over the old one, so a
+    /// crash mid-write never leaves a truncated session behind.
     fn save_session(&self, session: &Session) -> Result<(), Box<dyn std::error::Error>> {
         let session_path = self.get_session_path(&session.name);
-        let json = serde_json::to_string_pretty(session)?;
-        fs::write(session_path, json)?;
+        let mut session = session.clone();
+        session.schema_version = SESSION_SCHEMA_VERSION;
+        let json = match self.session_keys.get(&session.name) {
+            Some(key) => {
+                let envelope = session_crypto::encrypt_session(&serde_json::to_value(&session)?, key)?;
+                serde_json::to_string_pretty(&envelope)?
+            }
+            None => serde_json::to_string_pretty(&session)?,
+        };
+
+        let temp_path = session_path.with_extension("json.tmp");
+        {
+            let mut file = File::create(&temp_path)?;
+            file.write_all(json.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &session_path)?;
         Ok(())
     }
 
@@ -206,9 +833,40 @@ impl SessionManager {
             return Err(format!("Session '{}' not found", name).into());
         }
         
-        let json = fs::read_to_string(session_path)?;
-        let session: Session = serde_json::from_str(&json)?;
-        Ok(session)
+        match read_session_file(&session_path, self.session_keys.get(name)) {
+            Ok((session, migrated)) => {
+                // Write upgraded files back so migrations only run once
+                if migrated {
+                    self.save_session(&session)?;
+                }
+                Ok(session)
+            }
+            Err(SessionLoadError::Corrupted(e)) => {
+                let quarantine_path = self.quarantine_session_file(name, &session_path)?;
+                Err(format!(
+                    "Session '{}' is corrupted ({}); moved to '{}'",
+                    name,
+                    e,
+                    quarantine_path.display()
+                )
+                .into())
+            }
+            Err(SessionLoadError::Other(e)) => Err(e),
+            Err(e @ SessionLoadError::Locked(_)) => Err(Box::new(e)),
+        }
+    }
+
+    /// Move an unreadable session file out of the sessions directory, keeping it for inspection.
+    fn quarantine_session_file(&self, name: &str, session_path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let quarantine_dir = self.sessions_dir.join("corrupted");
+        fs::create_dir_all(&quarantine_dir)?;
+        let quarantine_path = quarantine_dir.join(format!(
+            "{}-{}.json",
+            name,
+            Utc::now().format("%Y%m%d-%H%M%S")
+        ));
+        fs::rename(session_path, &quarantine_path)?;
+        Ok(quarantine_path)
     }
 
     fn get_session_path(&self, name: &str) -> PathBuf {
@@ -218,6 +876,165 @@ impl SessionManager {
     }
 }
 
+#[derive(Debug)]
+enum SessionLoadError {
+    Corrupted(String),
+    Locked(String), // encrypted and no key is known for it
+    Other(Box<dyn std::error::Error>),
+}
+
+impl std::fmt::Display for SessionLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionLoadError::Corrupted(msg) => write!(f, "Session file is corrupted: {}", msg),
+            SessionLoadError::Other(e) => write!(f, "{}", e),
+            SessionLoadError::Locked(name) => write!(f, "Session '{}' is encrypted and locked", name),
+        }
+    }
+}
+
+impl std::error::Error for SessionLoadError {}
+
+/// Read a session file, decrypting it with `key` if it is encrypted and running any
+/// pending schema migrations. Returns the session and whether it was migrated.
+fn read_session_file(path: &Path, key: Option<&SessionKey>) -> Result<(Session, bool), SessionLoadError> {
+    let json = fs::read_to_string(path).map_err(|e| SessionLoadError::Other(e.into()))?;
+    let mut value: Value = serde_json::from_str(&json)
+        .map_err(|e| SessionLoadError::Corrupted(format!("invalid JSON: {}", e)))?;
+    if !value.is_object() {
+        return Err(SessionLoadError::Corrupted("not a JSON object".to_string()));
+    }
+
+    if session_crypto::is_encrypted(&value) {
+        let name = value["name"].as_str().unwrap_or_default().to_string();
+        let key = key.ok_or(SessionLoadError::Locked(name))?;
+        // A failed decryption is never treated as corruption, so a wrong key can't quarantine the file
+        value = session_crypto::decrypt_session(&value, key).map_err(SessionLoadError::Other)?;
+    }
+
+    let version = value["schema_version"].as_u64().unwrap_or(0) as u32;
+    if version > SESSION_SCHEMA_VERSION {
+        return Err(SessionLoadError::Other(
+            format!(
+                "Session schema version {} is newer than supported version {}; please upgrade cai",
+                version, SESSION_SCHEMA_VERSION
+            )
+            .into(),
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut value)
+            .map_err(|e| SessionLoadError::Corrupted(format!("migration failed: {}", e)))?;
+    }
+
+    let mut session: Session = serde_json::from_value(value)
+        .map_err(|e| SessionLoadError::Corrupted(format!("invalid session data: {}", e)))?;
+    session.schema_version = SESSION_SCHEMA_VERSION;
+    Ok((session, version < SESSION_SCHEMA_VERSION))
+}
+
+/// v0 files predate `schema_version`; fill in fields that older builds could omit.
+fn migrate_v0_to_v1(session: &mut Value) -> Result<(), Box<dyn std::error::Error>> {
+    let object = session.as_object_mut().ok_or("session is not an object")?;
+
+    let created = object.get("created").cloned().ok_or("missing 'created'")?;
+    object.entry("last_accessed").or_insert(created);
+    object.entry("config_overrides").or_insert(Value::Null);
+
+    let memory = object
+        .entry("memory")
+        .or_insert_with(|| Value::Object(Default::default()))
+        .as_object_mut()
+        .ok_or("'memory' is not an object")?;
+    for (id, prompt) in memory.iter_mut() {
+        let prompt = prompt.as_object_mut().ok_or("prompt is not an object")?;
+        prompt.entry("id").or_insert_with(|| Value::String(id.clone()));
+        prompt.entry("ptype").or_insert_with(|| Value::String("QUESTION".to_string()));
+    }
+
+    object.insert("schema_version".to_string(), Value::from(1));
+    Ok(())
+}
+
+/// v2 records the files read into prompts, so sessions can be packed with them.
+fn migrate_v1_to_v2(session: &mut Value) -> Result<(), Box<dyn std::error::Error>> {
+    let object = session.as_object_mut().ok_or("session is not an object")?;
+    object
+        .entry("referenced_files")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    object.insert("schema_version".to_string(), Value::from(2));
+    Ok(())
+}
+
+/// v3 adds optional usage stats (model, tokens, cost, latency) to prompts; older
+/// prompts simply have none.
+fn migrate_v2_to_v3(session: &mut Value) -> Result<(), Box<dyn std::error::Error>> {
+    let object = session.as_object_mut().ok_or("session is not an object")?;
+    object.insert("schema_version".to_string(), Value::from(3));
+    Ok(())
+}
+
+fn validate_session_name(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') {
+        return Err("Invalid session name".into());
+    }
+    Ok(())
+}
+
+fn list_session_files(dir: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut sessions = Vec::new();
+
+    if !dir.exists() {
+        return Ok(sessions);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file()
+            && path.extension().is_some_and(|ext| ext == "json")
+            && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+        {
+            sessions.push(name.to_string());
+        }
+    }
+
+    sessions.sort();
+    Ok(sessions)
+}
+
+fn session_to_markdown(session: &Session) -> String {
+    let mut content = String::new();
+    content.push_str(&format!("# Session: {}\n", session.name));
+    content.push_str(&format!("Created: {}\n", session.created.format("%Y-%m-%d %H:%M:%S")));
+    content.push_str(&format!("Last Accessed: {}\n\n", session.last_accessed.format("%Y-%m-%d %H:%M:%S")));
+
+    // Sort prompts by date
+    let mut prompts: Vec<&Prompt> = session.memory.values().collect();
+    prompts.sort_by_key(|prompt| prompt.date);
+
+    for prompt in prompts {
+        content.push_str(&format!("## {} ({})\n", prompt.id, prompt.date.format("%Y-%m-%d %H:%M:%S")));
+        content.push_str(&format!("Type: {:?}\n\n", prompt.ptype));
+        content.push_str(&prompt.value);
+        content.push_str("\n\n---\n\n");
+    }
+    content
+}
+
+fn write_export_file(export_path: &str, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Ensure parent directory exists
+    if let Some(parent) = Path::new(export_path).parent()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(export_path, content)?;
+    Ok(())
+}
+
 // Public API functions
 pub fn get_session_manager() -> &'static Mutex<SessionManager> {
     &SESSION_MANAGER
@@ -229,17 +1046,81 @@ pub fn create_session(name: &str) -> Result<String, Box<dyn std::error::Error>>
     Ok(format!("Session '{}' created successfully", name))
 }
 
+/// Switch sessions. Switching to a locked encrypted session leaves a passphrase
+/// request for the UI and returns a message asking for the passphrase.
 pub fn switch_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut manager = get_session_manager().lock().unwrap();
-    manager.switch_to_session(name)?;
-    Ok(format!("Switched to session '{}'", name))
+    match manager.switch_to_session(name) {
+        Ok(()) => Ok(format!("Switched to session '{}'", name)),
+        Err(e) if matches!(e.downcast_ref::<SessionLoadError>(), Some(SessionLoadError::Locked(_))) => {
+            request_passphrase(name, PassphraseAction::Unlock);
+            Ok(format!("Session '{}' is encrypted. Enter its passphrase to unlock it.", name))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Ask the UI for a passphrase to encrypt a session with.
+pub fn request_session_encryption(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+    if !manager.get_session_path(name).exists() {
+        return Err(format!("Session '{}' not found", name).into());
+    }
+    if manager.is_session_encrypted(name) && !manager.session_keys.contains_key(name) {
+        return Err(format!("Session '{}' is locked; switch to it and enter its passphrase first", name).into());
+    }
+    request_passphrase(name, PassphraseAction::Encrypt);
+    Ok(format!("Enter a passphrase for session '{}'.", name))
+}
+
+pub fn decrypt_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    manager.decrypt_session(name)?;
+    Ok(format!("Session '{}' is no longer encrypted", name))
+}
+
+fn request_passphrase(name: &str, action: PassphraseAction) {
+    *PASSPHRASE_REQUEST.lock().unwrap() = Some(PassphraseRequest {
+        session: name.to_string(),
+        action,
+    });
+}
+
+/// The pending passphrase request, if a command left one behind.
+pub fn take_passphrase_request() -> Option<PassphraseRequest> {
+    PASSPHRASE_REQUEST.lock().unwrap().take()
+}
+
+/// Complete a passphrase request with the passphrase the user entered.
+pub fn submit_passphrase(request: &PassphraseRequest, passphrase: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    match request.action {
+        PassphraseAction::Unlock => {
+            manager.unlock_session(&request.session, passphrase)?;
+            manager.switch_to_session(&request.session)?;
+            Ok(format!("Switched to session '{}'", request.session))
+        }
+        PassphraseAction::Encrypt => {
+            manager.encrypt_session(&request.session, passphrase)?;
+            Ok(format!("Session '{}' is now encrypted", request.session))
+        }
+    }
 }
 
 pub fn list_sessions() -> Result<String, Box<dyn std::error::Error>> {
+    list_sessions_with_archived(false)
+}
+
+pub fn list_sessions_with_archived(include_archived: bool) -> Result<String, Box<dyn std::error::Error>> {
     let manager = get_session_manager().lock().unwrap();
     let sessions = manager.list_sessions()?;
+    let archived = if include_archived {
+        manager.list_archived_sessions()?
+    } else {
+        Vec::new()
+    };
     
-    if sessions.is_empty() {
+    if sessions.is_empty() && archived.is_empty() {
         return Ok("No sessions found".to_string());
     }
 
@@ -251,6 +1132,20 @@ pub fn list_sessions() -> Result<String, Box<dyn std::error::Error>> {
             ""
         };
         
+        // Encrypted sessions are listed from their clear-text header, without decrypting
+        if let Ok(Some((created, last_accessed))) = manager.get_encrypted_session_header(session_name, false) {
+            let state = if manager.session_keys.contains_key(session_name) { "encrypted, unlocked" } else { "encrypted" };
+            result.push_str(&format!(
+                "- {} {} ({})\n  Created: {}, Last accessed: {}\n",
+                session_name,
+                current_marker,
+                state,
+                created.format("%Y-%m-%d %H:%M"),
+                last_accessed.format("%Y-%m-%d %H:%M")
+            ));
+            continue;
+        }
+
         match manager.get_session_info(session_name) {
             Ok(session) => {
                 result.push_str(&format!(
@@ -261,8 +1156,34 @@ pub fn list_sessions() -> Result<String, Box<dyn std::error::Error>> {
                     session.last_accessed.format("%Y-%m-%d %H:%M")
                 ));
             }
-            Err(_) => {
-                result.push_str(&format!("- {} {} (error loading details)\n", session_name, current_marker));
+            Err(e) => {
+                result.push_str(&format!("- {} {} (error: {})\n", session_name, current_marker, e));
+            }
+        }
+    }
+
+    for session_name in &archived {
+        if let Ok(Some((created, last_accessed))) = manager.get_encrypted_session_header(session_name, true) {
+            result.push_str(&format!(
+                "- {} (archived, encrypted)\n  Created: {}, Last accessed: {}\n",
+                session_name,
+                created.format("%Y-%m-%d %H:%M"),
+                last_accessed.format("%Y-%m-%d %H:%M")
+            ));
+            continue;
+        }
+
+        match manager.get_archived_session_info(session_name) {
+            Ok(session) => {
+                result.push_str(&format!(
+                    "- {} (archived)\n  Created: {}, Last accessed: {}\n",
+                    session_name,
+                    session.created.format("%Y-%m-%d %H:%M"),
+                    session.last_accessed.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            Err(e) => {
+                result.push_str(&format!("- {} (archived) (error: {})\n", session_name, e));
             }
         }
     }
@@ -270,8 +1191,103 @@ pub fn list_sessions() -> Result<String, Box<dyn std::error::Error>> {
     Ok(result)
 }
 
-pub fn delete_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+pub fn fork_session(source: &str, new_name: &str, up_to: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    let copied = manager.fork_session(source, new_name, up_to)?;
+    Ok(format!("Session '{}' forked to '{}' ({} prompts)", source, new_name, copied))
+}
+
+pub fn rename_session(old_name: &str, new_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    manager.rename_session(old_name, new_name)?;
+    Ok(format!("Session '{}' renamed to '{}'", old_name, new_name))
+}
+
+pub fn merge_sessions(first: &str, second: &str, into: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    let total = manager.merge_sessions(first, second, into)?;
+    Ok(format!(
+        "Sessions '{}' and '{}' merged into '{}' ({} prompts)",
+        first,
+        second,
+        into.unwrap_or(second),
+        total
+    ))
+}
+
+pub fn archive_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+    manager.archive_session(name)?;
+    Ok(format!("Session '{}' archived", name))
+}
+
+pub fn unarchive_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+    manager.unarchive_session(name)?;
+    Ok(format!("Session '{}' restored from archive", name))
+}
+
+/// Statistics for a session (the current one when no name is given). With `json_path`
+/// they are written there as JSON, otherwise they are also queued for the TUI stats panel.
+pub fn session_stats(name: Option<&str>, json_path: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => manager
+            .get_current_session()
+            .cloned()
+            .ok_or("No active session; pass a session name")?,
+    };
+    let stats = manager.get_session_stats(&name)?;
+
+    match json_path {
+        Some(path) => {
+            write_export_file(path, &serde_json::to_string_pretty(&stats)?)?;
+            Ok(format!("Statistics for session '{}' exported to '{}'", name, path))
+        }
+        None => {
+            let report = session_stats::render_text(&stats);
+            session_stats::show_in_panel(stats);
+            Ok(report)
+        }
+    }
+}
+
+pub fn pack_session(name: &str, out_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+    let report = manager.pack_session(name, out_path)?;
+
+    let mut result = format!(
+        "Session '{}' packed to '{}' with {} file snapshot(s)",
+        name, out_path, report.files
+    );
+    if !report.missing.is_empty() {
+        result.push_str(&format!("\nNo longer present, not included:\n- {}", report.missing.join("\n- ")));
+    }
+    Ok(result)
+}
+
+pub fn unpack_session(bundle_path: &str, name: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
     let manager = get_session_manager().lock().unwrap();
+    let (session_name, files_dir, restored, skipped) = manager.unpack_session(bundle_path, name)?;
+
+    let mut result = format!("Bundle '{}' unpacked as session '{}'", bundle_path, session_name);
+    if restored > 0 {
+        result.push_str(&format!("\n{} file snapshot(s) restored to '{}'", restored, files_dir.display()));
+    }
+    if !skipped.is_empty() {
+        result.push_str(&format!("\nAlready present, not overwritten:\n- {}", skipped.join("\n- ")));
+    }
+    Ok(result)
+}
+
+/// Remember a file read into a prompt, so `@session-pack` can include it.
+pub fn record_file_reference(path: &str) {
+    REFERENCED_FILES.lock().unwrap().insert(path.to_string());
+}
+
+pub fn delete_session(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
     manager.delete_session(name)?;
     Ok(format!("Session '{}' deleted successfully", name))
 }
@@ -290,18 +1306,44 @@ pub fn get_current_session_info() -> Result<String, Box<dyn std::error::Error>>
             match manager.get_session_info(name) {
                 Ok(session) => {
                     let memory_count = session.memory.len();
-                    Ok(format!(
-                        "Current session: '{}'\nCreated: {}\nLast accessed: {}\nMemory items: {}",
+                    let mut info = format!(
+                        "Current session: '{}' ({})\nCreated: {}\nLast accessed: {}\nMemory items: {}",
                         name,
+                        project::current_scope().label(),
                         session.created.format("%Y-%m-%d %H:%M:%S"),
                         session.last_accessed.format("%Y-%m-%d %H:%M:%S"),
                         memory_count
-                    ))
+                    );
+                    if manager.session_keys.contains_key(name) {
+                        info.push_str("\nEncrypted: yes");
+                    }
+                    match &session.config_overrides {
+                        Some(overrides) => {
+                            info.push_str("\nConfig overrides:");
+                            if let Some(model) = &overrides.model {
+                                info.push_str(&format!("\n- Model: {}", model));
+                            }
+                            if let Some(temperature) = overrides.temperature {
+                                info.push_str(&format!("\n- Temperature: {}", temperature));
+                            }
+                            if let Some(max_tokens) = overrides.max_tokens {
+                                info.push_str(&format!("\n- Max Tokens: {}", max_tokens));
+                            }
+                            if let Some(top_p) = overrides.top_p {
+                                info.push_str(&format!("\n- Top P: {}", top_p));
+                            }
+                            if let Some(system_prompt) = &overrides.system_prompt {
+                                info.push_str(&format!("\n- System Prompt: {}", system_prompt));
+                            }
+                        }
+                        None => info.push_str("\nConfig overrides: none"),
+                    }
+                    Ok(info)
                 }
                 Err(e) => Ok(format!("Current session: '{}' (error: {})", name, e))
             }
         }
-        None => Ok("No active session".to_string())
+        None => Ok(format!("No active session ({})", project::current_scope().label()))
     }
 }
 
@@ -315,4 +1357,112 @@ pub fn save_current_session() -> Result<String, Box<dyn std::error::Error>> {
         }
         None => Ok("No active session to save".to_string())
     }
+}
+
+/// Persist the current session config override into the active session's file.
+/// Returns the session name, or None when no session is active.
+pub fn save_config_overrides() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+
+    match manager.get_current_session() {
+        Some(name) => {
+            let overrides = configuration::get_session_config_override()
+                .map(|config| SessionConfig::from_config(&config));
+            manager.save_config_overrides_to_session(name, overrides)?;
+            Ok(Some(name.clone()))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn import_sessions(path: &str, format: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let format = ImportFormat::parse(format)?;
+    let sessions = session_import::parse_import_file(path, &format)?;
+    if sessions.is_empty() {
+        return Ok(format!("No conversations found in '{}'", path));
+    }
+
+    let manager = get_session_manager().lock().unwrap();
+    let report = manager.import_sessions(sessions)?;
+
+    let mut result = format!("Imported {} conversation(s) from '{}':\n", report.len(), path);
+    for line in report {
+        result.push_str(&format!("- {}\n", line));
+    }
+    Ok(result)
+}
+
+/// Record a newly created prompt in the crash-recovery journal.
+pub fn journal_prompt(prompt: &Prompt) {
+    let manager = get_session_manager().lock().unwrap();
+    if let Err(e) = manager.append_to_journal(prompt) {
+        eprintln!("Warning: Could not write session journal: {}", e);
+    }
+}
+
+/// Replay the journal after an unclean exit. Returns a message when anything was recovered.
+pub fn recover_session_journal() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut manager = get_session_manager().lock().unwrap();
+    let recovered = manager.recover_from_journal()?;
+    if recovered == 0 {
+        return Ok(None);
+    }
+
+    match manager.get_current_session() {
+        Some(name) => Ok(Some(format!(
+            "Recovered {} prompt(s) from an unclean exit into session '{}'",
+            recovered, name
+        ))),
+        None => Ok(Some(format!("Recovered {} prompt(s) from an unclean exit", recovered))),
+    }
+}
+
+/// Spawn a background task that saves the current session every `interval_minutes`.
+/// An interval of 0 disables periodic saving.
+pub fn start_auto_save(interval_minutes: u32) {
+    if interval_minutes == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(interval_minutes as u64 * 60);
+        let mut interval = tokio::time::interval(period);
+        // The first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let manager = get_session_manager().lock().unwrap();
+            if !manager.auto_save {
+                continue;
+            }
+            if let Some(name) = manager.get_current_session()
+                && let Err(e) = manager.save_current_memory_to_session(name)
+            {
+                eprintln!("Warning: Auto-save of session '{}' failed: {}", name, e);
+            }
+        }
+    });
+}
+
+/// Save the current session and run the auto-export on a clean exit, then drop the journal.
+pub fn shutdown_session(settings: &MemorySettings) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let manager = get_session_manager().lock().unwrap();
+
+    if let Some(name) = manager.get_current_session() {
+        manager.save_current_memory_to_session(name)?;
+    }
+
+    let mut message = None;
+    if settings.auto_export_on_exit {
+        let has_content = manager.get_current_session().is_some() || !get_memory().lock().unwrap().is_empty();
+        if has_content {
+            let export_path = manager.auto_export(&settings.default_export_format)?;
+            message = Some(format!("Session exported to '{}'", export_path));
+        }
+    }
+
+    manager.clear_journal()?;
+    Ok(message)
 }
\ No newline at end of file
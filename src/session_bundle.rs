@@ -0,0 +1,198 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::session::Session;
+
+const BUNDLE_FORMAT: &str = "cai-session-bundle";
+const BUNDLE_VERSION: u32 = 1;
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SESSION_ENTRY: &str = "session.json";
+
+/// `manifest.json` at the root of a bundle. Every other entry is listed here with
+/// its SHA-256, so a damaged or edited bundle is rejected before anything is restored.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    created: chrono::DateTime<Utc>,
+    session_name: String,
+    session_sha256: String,
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestFile {
+    path: String,  // as referenced in the session
+    entry: String, // entry name inside the bundle
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Debug)]
+pub struct PackReport {
+    pub files: usize,
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct BundleContents {
+    pub session: Session,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+/// Write a session and snapshots of its referenced files into a tar bundle.
+/// Referenced files that no longer exist are left out and reported.
+pub fn write_bundle(session: &Session, out_path: &str) -> Result<PackReport, Box<dyn std::error::Error>> {
+    let session_json = serde_json::to_vec_pretty(session)?;
+
+    let mut snapshots = Vec::new();
+    let mut missing = Vec::new();
+    for path in &session.referenced_files {
+        match fs::read(path) {
+            Ok(data) => snapshots.push((path.clone(), data)),
+            Err(_) => missing.push(path.clone()),
+        }
+    }
+
+    let files = snapshots
+        .iter()
+        .enumerate()
+        .map(|(i, (path, data))| ManifestFile {
+            path: path.clone(),
+            entry: format!("files/{:04}", i + 1),
+            size: data.len() as u64,
+            sha256: sha256_hex(data),
+        })
+        .collect::<Vec<_>>();
+
+    let manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        created: Utc::now(),
+        session_name: session.name.clone(),
+        session_sha256: sha256_hex(&session_json),
+        files,
+    };
+
+    if let Some(parent) = Path::new(out_path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut builder = tar::Builder::new(File::create(out_path)?);
+    append_entry(&mut builder, MANIFEST_ENTRY, &serde_json::to_vec_pretty(&manifest)?)?;
+    append_entry(&mut builder, SESSION_ENTRY, &session_json)?;
+    for (file, (_, data)) in manifest.files.iter().zip(&snapshots) {
+        append_entry(&mut builder, &file.entry, data)?;
+    }
+    builder.into_inner()?.sync_all()?;
+
+    Ok(PackReport {
+        files: snapshots.len(),
+        missing,
+    })
+}
+
+/// Read a bundle and verify every checksum in its manifest.
+pub fn read_bundle(path: &str) -> Result<BundleContents, Box<dyn std::error::Error>> {
+    let mut entries: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.insert(name, data);
+    }
+
+    let manifest: Manifest = serde_json::from_slice(
+        entries
+            .get(MANIFEST_ENTRY)
+            .ok_or("Not a session bundle: manifest.json is missing")?,
+    )?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(format!("Not a session bundle: unknown format '{}'", manifest.format).into());
+    }
+    if manifest.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Bundle version {} is newer than supported version {}; please upgrade cai",
+            manifest.version, BUNDLE_VERSION
+        )
+        .into());
+    }
+
+    let session_json = entries
+        .get(SESSION_ENTRY)
+        .ok_or("Bundle is incomplete: session.json is missing")?;
+    if sha256_hex(session_json) != manifest.session_sha256 {
+        return Err("Checksum mismatch for session.json; the bundle is damaged".into());
+    }
+    let session: Session = serde_json::from_slice(session_json)?;
+
+    let mut files = Vec::new();
+    for file in manifest.files {
+        let data = entries
+            .remove(&file.entry)
+            .ok_or_else(|| format!("Bundle is incomplete: snapshot of '{}' is missing", file.path))?;
+        if data.len() as u64 != file.size || sha256_hex(&data) != file.sha256 {
+            return Err(format!("Checksum mismatch for '{}'; the bundle is damaged", file.path).into());
+        }
+        files.push((file.path, data));
+    }
+
+    Ok(BundleContents { session, files })
+}
+
+/// Write file snapshots below `dir`, keeping their relative paths. Existing files are
+/// never overwritten; their paths are returned as skipped.
+pub fn restore_files(files: &[(String, Vec<u8>)], dir: &Path) -> Result<(usize, Vec<String>), Box<dyn std::error::Error>> {
+    let mut restored = 0;
+    let mut skipped = Vec::new();
+
+    for (path, data) in files {
+        let target = dir.join(snapshot_path(path));
+        if target.exists() {
+            skipped.push(path.clone());
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, data)?;
+        restored += 1;
+    }
+
+    Ok((restored, skipped))
+}
+
+/// Relative location of a snapshot. Root, drive and `..` components are dropped so a
+/// bundle can never write outside the directory it is restored into.
+fn snapshot_path(path: &str) -> PathBuf {
+    Path::new(path)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+fn append_entry(builder: &mut tar::Builder<File>, name: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
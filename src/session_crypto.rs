@@ -0,0 +1,148 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde_json::{Value, json};
+
+const CIPHER: &str = "chacha20poly1305";
+const KDF: &str = "argon2id";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Upper bound on the memory cost read from a file, in KiB, so a crafted file cannot
+/// make key derivation exhaust memory
+const MAX_M_COST: u32 = 1 << 20;
+
+/// A key derived from a session passphrase. Only the derived key is kept in memory,
+/// never the passphrase itself; the salt is reused on every save of the session.
+#[derive(Clone)]
+pub struct SessionKey {
+    salt: [u8; SALT_LEN],
+    params: Params,
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKey { .. }")
+    }
+}
+
+impl SessionKey {
+    /// Derive a key for a newly encrypted session, using a fresh random salt.
+    pub fn new(passphrase: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if passphrase.is_empty() {
+            return Err("Passphrase must not be empty".into());
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt, Params::default())
+    }
+
+    /// Derive the key for an existing encrypted file from its stored salt and KDF parameters.
+    pub fn for_envelope(passphrase: &str, envelope: &Value) -> Result<Self, Box<dyn std::error::Error>> {
+        let encryption = &envelope["encryption"];
+        let salt = decode_field(&encryption["salt"], "salt")?;
+        let salt: [u8; SALT_LEN] = salt.try_into().map_err(|_| "invalid salt length")?;
+        let cost = |name: &str| -> Result<u32, Box<dyn std::error::Error>> {
+            let value = encryption["params"][name].as_u64().ok_or_else(|| format!("missing KDF parameter '{}'", name))?;
+            Ok(u32::try_from(value).map_err(|_| format!("invalid KDF parameter '{}'", name))?)
+        };
+        let m_cost = cost("m_cost")?;
+        if m_cost > MAX_M_COST {
+            return Err(format!("KDF memory cost {} KiB is above the limit of {} KiB", m_cost, MAX_M_COST).into());
+        }
+        let params = Params::new(m_cost, cost("t_cost")?, cost("p_cost")?, None).map_err(|e| format!("invalid KDF parameters: {}", e))?;
+        Self::derive(passphrase, salt, params)
+    }
+
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN], params: Params) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Key derivation failed: {}", e))?;
+        Ok(SessionKey { salt, params, key })
+    }
+}
+
+/// Encrypted session files keep `name`, `created` and `last_accessed` in clear text
+/// so sessions can be listed without a passphrase; everything else is in `ciphertext`.
+pub fn is_encrypted(value: &Value) -> bool {
+    value.get("ciphertext").is_some()
+}
+
+pub fn encrypt_session(session_json: &Value, key: &SessionKey) -> Result<Value, Box<dyn std::error::Error>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(session_json)?;
+
+    let mut envelope = json!({
+        "schema_version": session_json["schema_version"],
+        "name": session_json["name"],
+        "created": session_json["created"],
+        "last_accessed": session_json["last_accessed"],
+        "encryption": {
+            "cipher": CIPHER,
+            "kdf": KDF,
+            "params": {
+                "m_cost": key.params.m_cost(),
+                "t_cost": key.params.t_cost(),
+                "p_cost": key.params.p_cost(),
+            },
+            "salt": STANDARD.encode(key.salt),
+            "nonce": STANDARD.encode(nonce),
+        },
+    });
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &plaintext, aad: associated_data(&envelope).as_bytes() })
+        .map_err(|_| "Session encryption failed")?;
+    envelope["ciphertext"] = Value::from(STANDARD.encode(ciphertext));
+    Ok(envelope)
+}
+
+/// The clear-text header the ciphertext is bound to, so the version, listed name and
+/// times, KDF parameters and salt cannot be changed without failing authentication.
+fn associated_data(envelope: &Value) -> String {
+    let encryption = &envelope["encryption"];
+    let params = &encryption["params"];
+    format!(
+        "{}:{}:{}:{}:{}:{}:{}:{}:{}:{}",
+        envelope["schema_version"],
+        envelope["name"],
+        envelope["created"],
+        envelope["last_accessed"],
+        encryption["cipher"],
+        encryption["kdf"],
+        params["m_cost"],
+        params["t_cost"],
+        params["p_cost"],
+        encryption["salt"]
+    )
+}
+
+/// Decrypt an envelope back into the plain session JSON. A wrong key and a tampered
+/// file, header included, are indistinguishable here; both fail authentication.
+pub fn decrypt_session(envelope: &Value, key: &SessionKey) -> Result<Value, Box<dyn std::error::Error>> {
+    let encryption = &envelope["encryption"];
+    if encryption["cipher"].as_str() != Some(CIPHER) || encryption["kdf"].as_str() != Some(KDF) {
+        return Err("Unsupported session encryption scheme".into());
+    }
+
+    let nonce = decode_field(&encryption["nonce"], "nonce")?;
+    if nonce.len() != NONCE_LEN {
+        return Err("invalid nonce length".into());
+    }
+    let ciphertext = decode_field(&envelope["ciphertext"], "ciphertext")?;
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key.key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: associated_data(envelope).as_bytes() })
+        .map_err(|_| "Incorrect passphrase")?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn decode_field(value: &Value, field: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let text = value.as_str().ok_or_else(|| format!("missing '{}'", field))?;
+    Ok(STANDARD.decode(text).map_err(|e| format!("invalid '{}': {}", field, e))?)
+}
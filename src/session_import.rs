@@ -0,0 +1,303 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::chat::{Prompt, PromptType};
+use crate::session::{SESSION_SCHEMA_VERSION, Session};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportFormat {
+    ChatGpt, // conversations.json from a ChatGPT data export
+    Jsonl,   // one message object per line
+}
+
+impl ImportFormat {
+    pub fn parse(format: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match format.to_lowercase().as_str() {
+            "chatgpt" | "openai" => Ok(ImportFormat::ChatGpt),
+            "jsonl" => Ok(ImportFormat::Jsonl),
+            other => Err(format!("Unknown import format '{}'. Use: chatgpt or jsonl", other).into()),
+        }
+    }
+}
+
+pub fn parse_import_file(path: &str, format: &ImportFormat) -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)?;
+    match format {
+        ImportFormat::ChatGpt => parse_chatgpt_export(&content),
+        ImportFormat::Jsonl => {
+            let default_title = Path::new(path)
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("imported");
+            parse_jsonl_log(&content, default_title)
+        }
+    }
+}
+
+/// Parse a ChatGPT `conversations.json` export. Only the branch ending at
+/// `current_node` is imported, so edited and regenerated messages don't show up twice.
+pub fn parse_chatgpt_export(json: &str) -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+    let root: Value = serde_json::from_str(json)?;
+    let conversations = root
+        .as_array()
+        .ok_or("Expected a JSON array of conversations")?;
+
+    let mut sessions = Vec::new();
+    for conversation in conversations {
+        let conversation_id = conversation["id"]
+            .as_str()
+            .or_else(|| conversation["conversation_id"].as_str())
+            .unwrap_or("");
+        let title = conversation["title"].as_str().unwrap_or("");
+        let created = parse_timestamp(&conversation["create_time"]).unwrap_or_else(Utc::now);
+        let updated = parse_timestamp(&conversation["update_time"]).unwrap_or(created);
+
+        let mapping = match conversation["mapping"].as_object() {
+            Some(mapping) => mapping,
+            None => continue,
+        };
+
+        // Walk from the current node back to the root, then reverse
+        let mut node_ids: Vec<&str> = Vec::new();
+        let mut next = conversation["current_node"].as_str();
+        while let Some(node_id) = next {
+            if node_ids.contains(&node_id) {
+                break;
+            }
+            node_ids.push(node_id);
+            next = mapping.get(node_id).and_then(|node| node["parent"].as_str());
+        }
+        node_ids.reverse();
+
+        // Older exports have no current_node; fall back to every node ordered by time
+        if node_ids.is_empty() {
+            let mut timed: Vec<(&str, f64)> = mapping
+                .iter()
+                .map(|(id, node)| (id.as_str(), node["message"]["create_time"].as_f64().unwrap_or(0.0)))
+                .collect();
+            timed.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            node_ids = timed.into_iter().map(|(id, _)| id).collect();
+        }
+
+        let mut memory = HashMap::new();
+        let mut last_date = created;
+        for node_id in node_ids {
+            let message = match mapping.get(node_id) {
+                Some(node) if node["message"].is_object() => &node["message"],
+                _ => continue,
+            };
+            let ptype = match role_to_prompt_type(message["author"]["role"].as_str().unwrap_or("")) {
+                Some(ptype) => ptype,
+                None => continue,
+            };
+            let value = content_to_text(&message["content"]);
+            if value.trim().is_empty() {
+                continue;
+            }
+            let date = parse_timestamp(&message["create_time"]).unwrap_or(last_date);
+            last_date = date;
+
+            let prompt = Prompt {
+                id: stable_id(&[conversation_id, node_id]),
+                date,
+                value,
+                ptype,
+                stats: None,
+            };
+            memory.insert(prompt.id.clone(), prompt);
+        }
+
+        if memory.is_empty() {
+            continue;
+        }
+
+        sessions.push(Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: session_name_from_title(title, conversation_id),
+            created,
+            last_accessed: updated,
+            memory,
+            config_overrides: None,
+            referenced_files: BTreeSet::new(),
+        });
+    }
+
+    Ok(sessions)
+}
+
+/// Parse a JSONL chat log. Each line is an object with a `role` and `content`, and
+/// optionally `timestamp`, `id` and a conversation id/title used to group lines into sessions.
+pub fn parse_jsonl_log(content: &str, default_title: &str) -> Result<Vec<Session>, Box<dyn std::error::Error>> {
+    // Keep conversations in the order they first appear in the file
+    let mut order: Vec<String> = Vec::new();
+    let mut conversations: HashMap<String, (String, Vec<Prompt>)> = HashMap::new();
+
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let entry: Value = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid JSON on line {}: {}", line_number + 1, e))?;
+
+        let role = first_str(&entry, &["role", "type"])
+            .or_else(|| entry["author"]["role"].as_str())
+            .or_else(|| entry["message"]["role"].as_str())
+            .unwrap_or("");
+        let ptype = match role_to_prompt_type(role) {
+            Some(ptype) => ptype,
+            None => continue,
+        };
+
+        let content_value = if !entry["content"].is_null() {
+            &entry["content"]
+        } else if !entry["text"].is_null() {
+            &entry["text"]
+        } else {
+            &entry["message"]["content"]
+        };
+        let value = content_to_text(content_value);
+        if value.trim().is_empty() {
+            continue;
+        }
+
+        let conversation_id = first_str(&entry, &["conversation_id", "session_id", "conversation"])
+            .unwrap_or(default_title)
+            .to_string();
+        let title = first_str(&entry, &["title", "conversation_title"]).unwrap_or("");
+
+        let line_key = (line_number + 1).to_string();
+        let message_id = first_str(&entry, &["id", "uuid", "message_id"]).unwrap_or(&line_key);
+        let date = ["timestamp", "created_at", "create_time", "time"]
+            .iter()
+            .find_map(|key| parse_timestamp(&entry[*key]));
+
+        if !conversations.contains_key(&conversation_id) {
+            order.push(conversation_id.clone());
+        }
+        let conversation = conversations
+            .entry(conversation_id.clone())
+            .or_insert_with(|| (String::new(), Vec::new()));
+        if conversation.0.is_empty() && !title.is_empty() {
+            conversation.0 = title.to_string();
+        }
+
+        let last_date = conversation.1.last().map(|p| p.date);
+        conversation.1.push(Prompt {
+            id: stable_id(&[&conversation_id, message_id]),
+            date: date.or(last_date).unwrap_or_else(Utc::now),
+            value,
+            ptype,
+            stats: None,
+        });
+    }
+
+    let mut sessions = Vec::new();
+    for conversation_id in order {
+        let (title, prompts) = conversations.remove(&conversation_id).unwrap_or_default();
+        let created = prompts.iter().map(|p| p.date).min().unwrap_or_else(Utc::now);
+        let last_accessed = prompts.iter().map(|p| p.date).max().unwrap_or(created);
+        let title = if title.is_empty() { conversation_id.clone() } else { title };
+
+        sessions.push(Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: session_name_from_title(&title, &conversation_id),
+            created,
+            last_accessed,
+            memory: prompts.into_iter().map(|p| (p.id.clone(), p)).collect(),
+            config_overrides: None,
+            referenced_files: BTreeSet::new(),
+        });
+    }
+
+    Ok(sessions)
+}
+
+/// Turn a conversation title into a valid session name. Whitespace becomes `-` so
+/// the name can be passed to `@session-switch` without quoting.
+pub fn session_name_from_title(title: &str, fallback_id: &str) -> String {
+    let mut name = String::new();
+    for c in title.trim().chars() {
+        if c.is_alphanumeric() || c == '-' || c == '_' || c == '.' {
+            name.push(c);
+        } else if !name.ends_with('-') {
+            name.push('-');
+        }
+    }
+    let name = name.trim_matches(|c| c == '-' || c == '.').to_string();
+
+    if name.is_empty() {
+        format!("imported-{}", stable_id(&[fallback_id]))
+    } else {
+        name
+    }
+}
+
+fn role_to_prompt_type(role: &str) -> Option<PromptType> {
+    match role.to_lowercase().as_str() {
+        "user" | "human" | "question" => Some(PromptType::QUESTION),
+        "assistant" | "ai" | "model" | "bot" | "answer" => Some(PromptType::ANSWER),
+        _ => None,
+    }
+}
+
+fn content_to_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .map(content_to_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(object) => {
+            // ChatGPT: {"content_type": "text", "parts": [...]}, others: {"type": "text", "text": "..."}
+            if let Some(parts) = object.get("parts") {
+                content_to_text(parts)
+            } else if let Some(text) = object.get("text") {
+                content_to_text(text)
+            } else {
+                String::new()
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(number) => {
+            let mut seconds = number.as_f64()?;
+            // Values this large are milliseconds
+            if seconds > 1e11 {
+                seconds /= 1000.0;
+            }
+            Utc.timestamp_opt(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)
+                .single()
+        }
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .map(|date| date.with_timezone(&Utc))
+            .ok()
+            .or_else(|| text.parse::<f64>().ok().and_then(|n| parse_timestamp(&Value::from(n)))),
+        _ => None,
+    }
+}
+
+fn first_str<'a>(entry: &'a Value, keys: &[&str]) -> Option<&'a str> {
+    keys.iter().find_map(|key| entry[*key].as_str())
+}
+
+/// Deterministic 16 hex character id (truncated SHA-256), so importing the same
+/// file twice produces the same prompt ids and duplicates can be detected.
+fn stable_id(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())[..16].to_string()
+}
@@ -0,0 +1,435 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+use crate::chat::{Prompt, PromptStats, PromptType};
+use crate::configuration::{self, LlmSettings};
+use crate::openrouter;
+use crate::session::{self, Session, SessionConfig};
+use crate::session_stats::format_latency;
+
+lazy_static! {
+    // Replays started in this run, by derived session name
+    static ref REPLAYS: Mutex<HashMap<String, ReplayProgress>> = Mutex::new(HashMap::new());
+    // Shared by all replays, so running several at once stays within the limit
+    static ref REQUEST_PERMITS: Arc<Semaphore> = Arc::new(Semaphore::new(
+        configuration::load_configuration()
+            .map(|config| config.replay.max_concurrent_requests)
+            .unwrap_or(2)
+            .max(1) as usize
+    ));
+}
+
+/// Progress of a replay, saved to `replays/<derived-session>.json` next to the sessions
+/// directory after every answer so an interrupted replay can be resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayState {
+    pub source: String,
+    pub model: String,
+    pub derived: String,
+    pub started: DateTime<Utc>,
+    #[serde(default)]
+    pub finished: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub exchanges: Vec<ReplayedExchange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayedExchange {
+    /// Id of the question in the source session; the derived session reuses it
+    pub question_id: String,
+    pub answer_id: String,
+}
+
+/// A question of the source session with the answer it originally got.
+#[derive(Debug, Clone)]
+pub struct ReplayStep {
+    pub question: Prompt,
+    pub original_answer: Option<Prompt>,
+}
+
+#[derive(Debug, Clone)]
+enum ReplayProgress {
+    Running { done: usize, total: usize },
+    Finished(PathBuf),
+    Failed { done: usize, total: usize, error: String },
+}
+
+/// The questions of a session in order, each paired with the first answer that followed it.
+pub fn replay_plan(session: &Session) -> Vec<ReplayStep> {
+    let mut prompts: Vec<&Prompt> = session.memory.values().collect();
+    prompts.sort_by_key(|prompt| prompt.date);
+
+    let mut steps: Vec<ReplayStep> = Vec::new();
+    for prompt in prompts {
+        match prompt.ptype {
+            PromptType::QUESTION => steps.push(ReplayStep {
+                question: prompt.clone(),
+                original_answer: None,
+            }),
+            PromptType::ANSWER => {
+                if let Some(step) = steps.last_mut()
+                    && step.original_answer.is_none()
+                {
+                    step.original_answer = Some(prompt.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    steps
+}
+
+/// Name of the session a replay of `source` against `model` is stored in.
+pub fn derived_session_name(source: &str, model: &str) -> String {
+    let model: String = model
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '-' })
+        .collect();
+    format!("{}-replay-{}", source, model)
+}
+
+/// Start (or resume) replaying the questions of `source` against `model` in the background.
+pub fn start_replay(source: &str, model: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let derived = derived_session_name(source, model);
+    if let Some(ReplayProgress::Running { done, total }) = REPLAYS.lock().unwrap().get(&derived) {
+        return Ok(format!("Replay into '{}' is already running: {}/{} questions", derived, done, total));
+    }
+
+    let config = configuration::load_configuration()?;
+    let (state, steps, session, llm, state_path) = {
+        let mut manager = session::get_session_manager().lock().unwrap();
+        if manager.get_current_session().map(|name| name.as_str()) == Some(source) {
+            manager.save_current_memory_to_session(source)?;
+        }
+        let source_session = manager.get_session_info(source)?;
+        let steps = replay_plan(&source_session);
+        if steps.is_empty() {
+            return Err(format!("Session '{}' has no questions to replay", source).into());
+        }
+
+        // Same settings the source session used, with the candidate model
+        let mut llm = config.llm.clone();
+        if let Some(overrides) = &source_session.config_overrides {
+            overrides.apply_to(&mut llm);
+        }
+        llm.model = model.to_string();
+
+        let state_path = replays_dir(&manager.sessions_dir).join(format!("{}.json", derived));
+        let mut state = if state_path.exists() {
+            let state: ReplayState = serde_json::from_str(&fs::read_to_string(&state_path)?)?;
+            if !manager.list_sessions()?.contains(&derived) {
+                manager.create_derived_session(source, &derived, Some(derived_overrides(&source_session, model)))?;
+            }
+            state
+        } else {
+            manager.create_derived_session(source, &derived, Some(derived_overrides(&source_session, model)))?;
+            ReplayState {
+                source: source.to_string(),
+                model: model.to_string(),
+                derived: derived.clone(),
+                started: Utc::now(),
+                finished: None,
+                exchanges: Vec::new(),
+            }
+        };
+
+        // Answers that did not make it into the derived session are asked again
+        let session = manager.get_session_info(&derived)?;
+        state.exchanges.retain(|exchange| session.memory.contains_key(&exchange.answer_id));
+        (state, steps, session, llm, state_path)
+    };
+
+    let remaining = steps.len() - completed_steps(&state, &steps);
+    if remaining == 0 {
+        let report_path = finish_replay(state, &steps, &session, &state_path)?;
+        REPLAYS.lock().unwrap().insert(derived, ReplayProgress::Finished(report_path.clone()));
+        return Ok(format!("Replay is complete; report written to '{}'", report_path.display()));
+    }
+
+    let done = steps.len() - remaining;
+    REPLAYS.lock().unwrap().insert(derived.clone(), ReplayProgress::Running { done, total: steps.len() });
+    let context_exchanges = config.replay.context_exchanges as usize;
+    tokio::spawn(run_replay(state, steps.clone(), session, llm, context_exchanges, state_path));
+
+    Ok(format!(
+        "Replaying {} questions of '{}' against '{}' into session '{}'{}. Use @session-replay-status() to follow progress.",
+        remaining,
+        source,
+        model,
+        derived,
+        if done > 0 { format!(" (resuming after {} done)", done) } else { String::new() }
+    ))
+}
+
+/// Progress of the replays started in this run, plus interrupted replays found on disk.
+pub fn replay_status() -> Result<String, Box<dyn std::error::Error>> {
+    let replays = REPLAYS.lock().unwrap().clone();
+    let mut lines = Vec::new();
+
+    let mut names: Vec<&String> = replays.keys().collect();
+    names.sort();
+    for name in names {
+        let line = match &replays[name] {
+            ReplayProgress::Running { done, total } => format!("  {}: running, {}/{} questions", name, done, total),
+            ReplayProgress::Finished(report) => format!("  {}: finished, report at '{}'", name, report.display()),
+            ReplayProgress::Failed { done, total, error } => {
+                format!("  {}: stopped after {}/{} questions: {}", name, done, total, error)
+            }
+        };
+        lines.push(line);
+    }
+
+    let replays_dir = replays_dir(&session::get_session_manager().lock().unwrap().sessions_dir);
+    if replays_dir.exists() {
+        let mut interrupted = Vec::new();
+        for entry in fs::read_dir(&replays_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Ok(state) = serde_json::from_str::<ReplayState>(&fs::read_to_string(&path)?) else {
+                continue;
+            };
+            if state.finished.is_none() && !replays.contains_key(&state.derived) {
+                interrupted.push(format!(
+                    "  {}: interrupted after {} questions; resume with @session-replay({}, {})",
+                    state.derived,
+                    state.exchanges.len(),
+                    state.source,
+                    state.model
+                ));
+            }
+        }
+        interrupted.sort();
+        lines.extend(interrupted);
+    }
+
+    if lines.is_empty() {
+        Ok("No session replays".to_string())
+    } else {
+        Ok(format!("Session replays:\n{}", lines.join("\n")))
+    }
+}
+
+async fn run_replay(
+    mut state: ReplayState,
+    steps: Vec<ReplayStep>,
+    mut session: Session,
+    llm: LlmSettings,
+    context_exchanges: usize,
+    state_path: PathBuf,
+) {
+    let total = steps.len();
+    let mut done = completed_steps(&state, &steps);
+    let mut history: Vec<(String, String)> = Vec::new();
+
+    for step in &steps {
+        // Resume: earlier answers only rebuild the context
+        let replayed = state
+            .exchanges
+            .iter()
+            .find(|exchange| exchange.question_id == step.question.id)
+            .and_then(|exchange| session.memory.get(&exchange.answer_id));
+        if let Some(answer) = replayed {
+            history.push((step.question.value.clone(), answer.value.clone()));
+            continue;
+        }
+
+        let context = &history[history.len().saturating_sub(context_exchanges)..];
+        let response = {
+            let _permit = REQUEST_PERMITS.acquire().await.expect("replay semaphore closed");
+            openrouter::call_openrouter_chat(&llm, context, &step.question.value)
+                .await
+                .map_err(|e| e.to_string())
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                REPLAYS.lock().unwrap().insert(state.derived.clone(), ReplayProgress::Failed { done, total, error });
+                return;
+            }
+        };
+
+        let question = Prompt {
+            date: Utc::now(),
+            ..step.question.clone()
+        };
+        let answer = Prompt {
+            id: new_prompt_id(),
+            date: Utc::now(),
+            value: response.content,
+            ptype: PromptType::ANSWER,
+            stats: Some(response.stats),
+        };
+
+        let saved = {
+            let manager = session::get_session_manager().lock().unwrap();
+            manager.append_prompts(&state.derived, &[question.clone(), answer.clone()])
+        }
+        .map_err(|e| e.to_string());
+        state.exchanges.push(ReplayedExchange {
+            question_id: question.id.clone(),
+            answer_id: answer.id.clone(),
+        });
+        let saved = saved.and_then(|_| save_state(&state, &state_path).map_err(|e| e.to_string()));
+        if let Err(error) = saved {
+            REPLAYS.lock().unwrap().insert(state.derived.clone(), ReplayProgress::Failed { done, total, error });
+            return;
+        }
+
+        history.push((question.value.clone(), answer.value.clone()));
+        session.memory.insert(question.id.clone(), question);
+        session.memory.insert(answer.id.clone(), answer);
+        done += 1;
+        REPLAYS.lock().unwrap().insert(state.derived.clone(), ReplayProgress::Running { done, total });
+    }
+
+    let derived = state.derived.clone();
+    let progress = match finish_replay(state, &steps, &session, &state_path) {
+        Ok(report_path) => ReplayProgress::Finished(report_path),
+        Err(e) => ReplayProgress::Failed { done, total, error: e.to_string() },
+    };
+    REPLAYS.lock().unwrap().insert(derived, progress);
+}
+
+/// Mark the replay finished and write the side-by-side report next to its state file.
+fn finish_replay(
+    mut state: ReplayState,
+    steps: &[ReplayStep],
+    session: &Session,
+    state_path: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    state.finished.get_or_insert_with(Utc::now);
+    save_state(&state, state_path)?;
+
+    let report_path = state_path.with_extension("md");
+    fs::write(&report_path, render_report(&state, steps, session))?;
+    Ok(report_path)
+}
+
+/// Markdown report with the original and replayed answer of every question side by side.
+pub fn render_report(state: &ReplayState, steps: &[ReplayStep], session: &Session) -> String {
+    let replayed: HashMap<&str, &Prompt> = state
+        .exchanges
+        .iter()
+        .filter_map(|exchange| {
+            session
+                .memory
+                .get(&exchange.answer_id)
+                .map(|answer| (exchange.question_id.as_str(), answer))
+        })
+        .collect();
+
+    let mut report = format!("# Replay of '{}' against {}\n\n", state.source, state.model);
+    report.push_str(&format!("Derived session: `{}`\n", state.derived));
+    report.push_str(&format!("Started: {}\n", state.started.format("%Y-%m-%d %H:%M:%S")));
+    if let Some(finished) = state.finished {
+        report.push_str(&format!("Finished: {}\n", finished.format("%Y-%m-%d %H:%M:%S")));
+    }
+
+    let original: Vec<&PromptStats> = steps
+        .iter()
+        .filter_map(|step| step.original_answer.as_ref().and_then(|answer| answer.stats.as_ref()))
+        .collect();
+    let new: Vec<&PromptStats> = replayed.values().filter_map(|answer| answer.stats.as_ref()).collect();
+    report.push_str("\n| | Original | Replay |\n| --- | --- | --- |\n");
+    report.push_str(&format!("| Answers | {} | {} |\n", steps.iter().filter(|s| s.original_answer.is_some()).count(), replayed.len()));
+    report.push_str(&format!("| Tokens | {} | {} |\n", total_tokens(&original), total_tokens(&new)));
+    report.push_str(&format!("| Cost | ${:.4} | ${:.4} |\n", total_cost(&original), total_cost(&new)));
+    report.push_str(&format!("| Avg latency | {} | {} |\n", average_latency(&original), average_latency(&new)));
+
+    for (i, step) in steps.iter().enumerate() {
+        let question = step.question.value.lines().find(|line| !line.trim().is_empty()).unwrap_or("").trim();
+        report.push_str(&format!("\n## {}. {} ({})\n\n", i + 1, question, step.question.id));
+
+        let original = step.original_answer.as_ref();
+        let new = replayed.get(step.question.id.as_str()).copied();
+        report.push_str(&format!(
+            "| Original: {} | Replay: {} |\n| --- | --- |\n| {} | {} |\n",
+            original.map_or("no answer".to_string(), answer_label),
+            new.map_or("not replayed".to_string(), answer_label),
+            original.map_or(String::new(), |answer| table_cell(&answer.value)),
+            new.map_or(String::new(), |answer| table_cell(&answer.value))
+        ));
+    }
+    report
+}
+
+fn derived_overrides(source: &Session, model: &str) -> SessionConfig {
+    let mut overrides = source.config_overrides.clone().unwrap_or(SessionConfig {
+        model: None,
+        max_tokens: None,
+        temperature: None,
+        top_p: None,
+        system_prompt: None,
+    });
+    overrides.model = Some(model.to_string());
+    overrides
+}
+
+fn completed_steps(state: &ReplayState, steps: &[ReplayStep]) -> usize {
+    steps
+        .iter()
+        .filter(|step| state.exchanges.iter().any(|exchange| exchange.question_id == step.question.id))
+        .count()
+}
+
+fn replays_dir(sessions_dir: &Path) -> PathBuf {
+    sessions_dir.parent().unwrap_or(sessions_dir).join("replays")
+}
+
+fn save_state(state: &ReplayState, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn new_prompt_id() -> String {
+    uuid::Uuid::new_v4().to_string().split('-').next().unwrap_or("").to_string()
+}
+
+fn answer_label(answer: &Prompt) -> String {
+    match &answer.stats {
+        Some(stats) => format!(
+            "{} ({} tokens, {})",
+            stats.model.as_deref().unwrap_or("unknown"),
+            stats.prompt_tokens.unwrap_or(0) + stats.completion_tokens.unwrap_or(0),
+            format_latency(stats.latency_ms.map(|ms| ms as f64))
+        ),
+        None => "unknown".to_string(),
+    }
+}
+
+fn table_cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace("\r\n", "<br>").replace('\n', "<br>")
+}
+
+fn total_tokens(stats: &[&PromptStats]) -> u64 {
+    stats
+        .iter()
+        .map(|s| s.prompt_tokens.unwrap_or(0) as u64 + s.completion_tokens.unwrap_or(0) as u64)
+        .sum()
+}
+
+fn total_cost(stats: &[&PromptStats]) -> f64 {
+    stats.iter().filter_map(|s| s.cost).sum()
+}
+
+fn average_latency(stats: &[&PromptStats]) -> String {
+    let latencies: Vec<u64> = stats.iter().filter_map(|s| s.latency_ms).collect();
+    if latencies.is_empty() {
+        format_latency(None)
+    } else {
+        format_latency(Some(latencies.iter().sum::<u64>() as f64 / latencies.len() as f64))
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::chat::{Prompt, PromptType};
+use crate::session::Session;
+
+const LARGEST_EXPANSIONS: usize = 5;
+const PREVIEW_CHARS: usize = 60;
+
+lazy_static! {
+    // Stats waiting to be shown in the TUI panel
+    static ref PENDING_PANEL: Mutex<Option<SessionStats>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStats {
+    pub session: String,
+    pub generated: DateTime<Utc>,
+    pub exchanges: usize,
+    pub questions: usize,
+    pub answers: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+    pub average_latency_ms: Option<f64>,
+    pub models: Vec<ModelStats>,
+    pub largest_expansions: Vec<ExpansionStats>,
+    pub activity: Vec<DayActivity>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelStats {
+    pub model: String,
+    pub answers: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+    pub average_latency_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpansionStats {
+    pub prompt_id: String,
+    pub date: DateTime<Utc>,
+    pub bytes: usize,
+    pub preview: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayActivity {
+    pub date: NaiveDate,
+    pub questions: usize,
+    pub answers: usize,
+}
+
+/// Compute statistics from the prompts stored in a session. Answers saved before usage
+/// was recorded are counted under the model "unknown" without tokens or cost.
+pub fn compute_stats(session: &Session) -> SessionStats {
+    let mut prompts: Vec<&Prompt> = session.memory.values().collect();
+    prompts.sort_by_key(|prompt| prompt.date);
+
+    let mut exchanges = 0;
+    let mut awaiting_answer = false;
+    let mut questions = 0;
+    let mut answers = 0;
+    let mut by_model: BTreeMap<String, (ModelStats, Vec<u64>)> = BTreeMap::new();
+    let mut expansions = Vec::new();
+    let mut by_day: BTreeMap<NaiveDate, DayActivity> = BTreeMap::new();
+
+    for prompt in &prompts {
+        let day = by_day.entry(prompt.date.date_naive()).or_insert(DayActivity {
+            date: prompt.date.date_naive(),
+            questions: 0,
+            answers: 0,
+        });
+
+        match prompt.ptype {
+            PromptType::QUESTION => {
+                questions += 1;
+                day.questions += 1;
+                awaiting_answer = true;
+
+                if let Some(bytes) = prompt.stats.as_ref().and_then(|stats| stats.expansion_bytes) {
+                    expansions.push(ExpansionStats {
+                        prompt_id: prompt.id.clone(),
+                        date: prompt.date,
+                        bytes,
+                        preview: preview(&prompt.value),
+                    });
+                }
+            }
+            PromptType::ANSWER => {
+                answers += 1;
+                day.answers += 1;
+                if awaiting_answer {
+                    exchanges += 1;
+                    awaiting_answer = false;
+                }
+
+                let stats = prompt.stats.clone().unwrap_or_default();
+                let model = stats.model.clone().unwrap_or_else(|| "unknown".to_string());
+                let (entry, latencies) = by_model.entry(model.clone()).or_insert_with(|| {
+                    (
+                        ModelStats {
+                            model,
+                            answers: 0,
+                            prompt_tokens: 0,
+                            completion_tokens: 0,
+                            cost: 0.0,
+                            average_latency_ms: None,
+                        },
+                        Vec::new(),
+                    )
+                });
+                entry.answers += 1;
+                entry.prompt_tokens += stats.prompt_tokens.unwrap_or(0) as u64;
+                entry.completion_tokens += stats.completion_tokens.unwrap_or(0) as u64;
+                entry.cost += stats.cost.unwrap_or(0.0);
+                latencies.extend(stats.latency_ms);
+            }
+            _ => {}
+        }
+    }
+
+    let all_latencies: Vec<u64> = by_model.values().flat_map(|(_, latencies)| latencies.clone()).collect();
+    let models: Vec<ModelStats> = by_model
+        .into_values()
+        .map(|(mut model, latencies)| {
+            model.average_latency_ms = average(&latencies);
+            model
+        })
+        .collect();
+
+    expansions.sort_by_key(|expansion| std::cmp::Reverse(expansion.bytes));
+    expansions.truncate(LARGEST_EXPANSIONS);
+
+    SessionStats {
+        session: session.name.clone(),
+        generated: Utc::now(),
+        exchanges,
+        questions,
+        answers,
+        prompt_tokens: models.iter().map(|m| m.prompt_tokens).sum(),
+        completion_tokens: models.iter().map(|m| m.completion_tokens).sum(),
+        cost: models.iter().map(|m| m.cost).sum(),
+        average_latency_ms: average(&all_latencies),
+        models,
+        largest_expansions: expansions,
+        activity: by_day.into_values().collect(),
+    }
+}
+
+/// Plain-text report, used as the command output.
+pub fn render_text(stats: &SessionStats) -> String {
+    let mut report = format!(
+        "Statistics for session '{}':\nExchanges: {} ({} questions, {} answers)\nTokens: {} prompt + {} completion\nCost: ${:.4}\nAverage latency: {}\n",
+        stats.session,
+        stats.exchanges,
+        stats.questions,
+        stats.answers,
+        stats.prompt_tokens,
+        stats.completion_tokens,
+        stats.cost,
+        format_latency(stats.average_latency_ms)
+    );
+
+    if !stats.models.is_empty() {
+        report.push_str("\nBy model:\n");
+        for model in &stats.models {
+            report.push_str(&format!(
+                "- {}: {} answers, {} + {} tokens, ${:.4}, avg {}\n",
+                model.model,
+                model.answers,
+                model.prompt_tokens,
+                model.completion_tokens,
+                model.cost,
+                format_latency(model.average_latency_ms)
+            ));
+        }
+    }
+
+    if !stats.largest_expansions.is_empty() {
+        report.push_str("\nLargest context expansions:\n");
+        for expansion in &stats.largest_expansions {
+            report.push_str(&format!(
+                "- {} ({}): {} bytes - {}\n",
+                expansion.prompt_id,
+                expansion.date.format("%Y-%m-%d %H:%M"),
+                expansion.bytes,
+                expansion.preview
+            ));
+        }
+    }
+
+    if !stats.activity.is_empty() {
+        report.push_str("\nActivity:\n");
+        let busiest = stats.activity.iter().map(|day| day.questions + day.answers).max().unwrap_or(1);
+        for day in &stats.activity {
+            let total = day.questions + day.answers;
+            let width = (total * 30).div_ceil(busiest.max(1));
+            report.push_str(&format!("{} {:>4} {}\n", day.date, total, "#".repeat(width)));
+        }
+    }
+
+    report
+}
+
+pub fn format_latency(latency_ms: Option<f64>) -> String {
+    match latency_ms {
+        Some(ms) if ms >= 1000.0 => format!("{:.1}s", ms / 1000.0),
+        Some(ms) => format!("{:.0}ms", ms),
+        None => "n/a".to_string(),
+    }
+}
+
+/// Leave stats behind for the TUI to show in its stats panel.
+pub fn show_in_panel(stats: SessionStats) {
+    *PENDING_PANEL.lock().unwrap() = Some(stats);
+}
+
+pub fn take_panel_stats() -> Option<SessionStats> {
+    PENDING_PANEL.lock().unwrap().take()
+}
+
+fn average(values: &[u64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<u64>() as f64 / values.len() as f64)
+    }
+}
+
+fn preview(text: &str) -> String {
+    let line = text.lines().find(|line| !line.trim().is_empty()).unwrap_or("").trim();
+    if line.chars().count() > PREVIEW_CHARS {
+        format!("{}...", line.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
@@ -1,8 +1,25 @@
 #[cfg(test)]
 mod tests {
     use crate::session::*;
+    use crate::test_util::TempDir;
+    use std::collections::HashMap;
     use std::fs;
 
+    /// A manager with its own sessions directory, `<dir>/sessions`. Anything kept next
+    /// to the sessions directory, like bundle snapshots, stays inside `<dir>` as well.
+    fn test_manager(name: &str) -> (TempDir, SessionManager) {
+        let dir = TempDir::new(name);
+        let sessions_dir = dir.join("sessions");
+        fs::create_dir_all(&sessions_dir).unwrap();
+        let manager = SessionManager {
+            current_session: None,
+            sessions_dir,
+            auto_save: true,
+            session_keys: HashMap::new(),
+        };
+        (dir, manager)
+    }
+
     #[test]
     fn test_public_session_api() {
         // Test the public API functions
@@ -18,4 +35,470 @@ mod tests {
         let current_info = get_current_session_info().unwrap();
         assert!(current_info.contains("test-api-session"));
     }
+
+    #[test]
+    fn test_import_chatgpt_export_is_deduplicated() {
+        use crate::chat::PromptType;
+        use crate::session_import::parse_chatgpt_export;
+
+        let export = r#"[{
+            "id": "conv-1",
+            "title": "Rust lifetimes",
+            "create_time": 1700000000.5,
+            "update_time": 1700000100.0,
+            "current_node": "c",
+            "mapping": {
+                "root": {"message": null, "parent": null},
+                "a": {"message": {"author": {"role": "user"}, "create_time": 1700000001.0,
+                      "content": {"content_type": "text", "parts": ["What is 'a?"]}}, "parent": "root"},
+                "b": {"message": {"author": {"role": "user"}, "create_time": 1700000002.0,
+                      "content": {"content_type": "text", "parts": ["Edited away"]}}, "parent": "root"},
+                "c": {"message": {"author": {"role": "assistant"}, "create_time": 1700000003.0,
+                      "content": {"content_type": "text", "parts": ["A lifetime."]}}, "parent": "a"}
+            }
+        }]"#;
+
+        let sessions = parse_chatgpt_export(export).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].name, "Rust-lifetimes");
+        assert_eq!(sessions[0].memory.len(), 2);
+        let mut prompts: Vec<_> = sessions[0].memory.values().collect();
+        prompts.sort_by_key(|prompt| prompt.date);
+        assert_eq!(prompts[0].ptype, PromptType::QUESTION);
+        assert_eq!(prompts[1].ptype, PromptType::ANSWER);
+        assert_eq!(prompts[1].date.timestamp(), 1700000003);
+
+        let (_dir, manager) = test_manager("import");
+
+        let first = manager.import_sessions(parse_chatgpt_export(export).unwrap()).unwrap();
+        assert!(first[0].contains("created"));
+        let second = manager.import_sessions(parse_chatgpt_export(export).unwrap()).unwrap();
+        assert!(second[0].contains("already imported"));
+        assert_eq!(manager.list_sessions().unwrap(), vec!["Rust-lifetimes".to_string()]);
+    }
+
+    #[test]
+    fn test_import_jsonl_groups_by_conversation() {
+        use crate::session_import::parse_jsonl_log;
+
+        let log = concat!(
+            r#"{"conversation_id": "x", "title": "First chat", "role": "user", "content": "hi", "timestamp": "2024-01-01T10:00:00Z"}"#, "\n",
+            r#"{"conversation_id": "x", "role": "assistant", "content": [{"type": "text", "text": "hello"}]}"#, "\n",
+            r#"{"conversation_id": "y", "role": "system", "content": "ignored"}"#, "\n",
+            r#"{"conversation_id": "y", "role": "user", "content": "second", "timestamp": 1704103200}"#, "\n",
+        );
+
+        let sessions = parse_jsonl_log(log, "log").unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].name, "First-chat");
+        assert_eq!(sessions[0].memory.len(), 2);
+        assert_eq!(sessions[1].name, "y");
+        assert_eq!(sessions[1].memory.len(), 1);
+    }
+
+    #[test]
+    fn test_session_config_round_trip() {
+        use crate::configuration::Config;
+
+        let mut config = Config::default();
+        config.llm.temperature = 1.2;
+        config.llm.max_tokens = 1000;
+
+        let overrides = SessionConfig::from_config(&config);
+        assert_eq!(overrides.temperature, Some(1.2));
+        assert_eq!(overrides.max_tokens, Some(1000));
+        assert!(overrides.model.is_none());
+        assert!(overrides.top_p.is_none());
+
+        let restored = overrides.to_config();
+        assert_eq!(restored.llm.temperature, 1.2);
+        assert_eq!(restored.llm.max_tokens, 1000);
+        assert_eq!(restored.llm.model, Config::default().llm.model);
+    }
+
+    #[test]
+    fn test_journal_is_replayed_after_unclean_exit() {
+        use crate::chat::{Prompt, PromptType};
+
+        let (_dir, mut manager) = test_manager("journal");
+        let sessions_dir = manager.sessions_dir.clone();
+        manager.create_session("journaled").unwrap();
+        manager.current_session = Some("journaled".to_string());
+
+        let prompt = Prompt {
+            id: "j0urna1".to_string(),
+            value: "unsaved question".to_string(),
+            ptype: PromptType::QUESTION,
+            ..Default::default()
+        };
+        manager.append_to_journal(&prompt).unwrap();
+
+        // A fresh manager simulates the next startup
+        let mut restarted = SessionManager {
+            current_session: None,
+            sessions_dir: sessions_dir.clone(),
+            auto_save: false,
+            session_keys: HashMap::new(),
+        };
+        assert_eq!(restarted.recover_from_journal().unwrap(), 1);
+        assert_eq!(restarted.get_current_session(), Some(&"journaled".to_string()));
+        let session = restarted.get_session_info("journaled").unwrap();
+        assert_eq!(session.memory["j0urna1"].value, "unsaved question");
+        assert!(!sessions_dir.join("journal.jsonl").exists());
+    }
+
+    #[test]
+    fn test_recovered_unsaved_prompts_survive_the_session_switch() {
+        use crate::chat::{Prompt, PromptType, get_memory};
+
+        let (_dir, mut manager) = test_manager("journal");
+        let sessions_dir = manager.sessions_dir.clone();
+        manager.create_session("resumed").unwrap();
+
+        // A prompt asked before any session was active, then one in a session
+        let unsaved = Prompt {
+            id: "uns4ved".to_string(),
+            value: "asked without a session".to_string(),
+            ptype: PromptType::QUESTION,
+            ..Default::default()
+        };
+        manager.append_to_journal(&unsaved).unwrap();
+        manager.current_session = Some("resumed".to_string());
+        let saved = Prompt {
+            id: "s4ved00".to_string(),
+            value: "asked in the session".to_string(),
+            ptype: PromptType::QUESTION,
+            ..Default::default()
+        };
+        manager.append_to_journal(&saved).unwrap();
+
+        let mut restarted = SessionManager {
+            current_session: None,
+            sessions_dir: sessions_dir.clone(),
+            auto_save: false,
+            session_keys: HashMap::new(),
+        };
+        assert_eq!(restarted.recover_from_journal().unwrap(), 2);
+        assert_eq!(restarted.get_current_session(), Some(&"resumed".to_string()));
+        assert!(get_memory().lock().unwrap().contains_key("uns4ved"));
+        let session = restarted.get_session_info("resumed").unwrap();
+        assert_eq!(session.memory["uns4ved"].value, "asked without a session");
+        assert_eq!(session.memory["s4ved00"].value, "asked in the session");
+    }
+
+    #[test]
+    fn test_fork_merge_rename_and_archive() {
+        use crate::chat::{Prompt, PromptType};
+        use chrono::{Duration, Utc};
+
+        let (_dir, mut manager) = test_manager("ops");
+        let sessions_dir = manager.sessions_dir.clone();
+
+        let start = Utc::now();
+        let mut session = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: "base".to_string(),
+            created: start,
+            last_accessed: start,
+            memory: HashMap::new(),
+            config_overrides: None,
+            referenced_files: Default::default(),
+        };
+        for (i, id) in ["p1", "p2", "p3"].iter().enumerate() {
+            session.memory.insert(id.to_string(), Prompt {
+                id: id.to_string(),
+                date: start + Duration::seconds(i as i64),
+                value: format!("prompt {}", i),
+                ptype: PromptType::QUESTION,
+                stats: None,
+            });
+        }
+        fs::write(sessions_dir.join("base.json"), serde_json::to_string(&session).unwrap()).unwrap();
+
+        assert_eq!(manager.fork_session("base", "fork", Some("p2")).unwrap(), 2);
+        assert!(manager.fork_session("base", "fork", None).is_err());
+
+        manager.rename_session("fork", "renamed").unwrap();
+        assert_eq!(manager.list_sessions().unwrap(), vec!["base".to_string(), "renamed".to_string()]);
+
+        assert_eq!(manager.merge_sessions("base", "renamed", Some("merged")).unwrap(), 3);
+
+        manager.archive_session("base").unwrap();
+        assert!(!manager.list_sessions().unwrap().contains(&"base".to_string()));
+        assert_eq!(manager.list_archived_sessions().unwrap(), vec!["base".to_string()]);
+        manager.unarchive_session("base").unwrap();
+        assert!(manager.list_sessions().unwrap().contains(&"base".to_string()));
+    }
+
+    #[test]
+    fn test_old_files_are_migrated_and_corrupted_files_quarantined() {
+        let (_dir, manager) = test_manager("schema");
+        let sessions_dir = manager.sessions_dir.clone();
+
+        // A file written before schema_version, last_accessed and ptype existed
+        fs::write(
+            sessions_dir.join("legacy.json"),
+            r#"{"name": "legacy", "created": "2025-01-14T16:45:30Z",
+                "memory": {"abc123ef": {"date": "2025-01-14T16:45:30Z", "value": "hi"}}}"#,
+        )
+        .unwrap();
+        let session = manager.get_session_info("legacy").unwrap();
+        assert_eq!(session.schema_version, SESSION_SCHEMA_VERSION);
+        assert_eq!(session.memory["abc123ef"].id, "abc123ef");
+        let rewritten = fs::read_to_string(sessions_dir.join("legacy.json")).unwrap();
+        assert!(rewritten.contains("schema_version"));
+
+        fs::write(sessions_dir.join("broken.json"), "{\"name\": \"bro").unwrap();
+        let error = manager.get_session_info("broken").unwrap_err().to_string();
+        assert!(error.contains("corrupted"));
+        assert!(!sessions_dir.join("broken.json").exists());
+        assert_eq!(fs::read_dir(sessions_dir.join("corrupted")).unwrap().count(), 1);
+        assert_eq!(manager.list_sessions().unwrap(), vec!["legacy".to_string()]);
+    }
+
+    #[test]
+    fn test_encrypted_session_round_trip() {
+        use crate::chat::{Prompt, PromptType};
+
+        let (_dir, mut manager) = test_manager("crypto");
+        let sessions_dir = manager.sessions_dir.clone();
+
+        let prompt = Prompt {
+            id: "p1".to_string(),
+            date: chrono::Utc::now(),
+            value: "launch codes".to_string(),
+            ptype: PromptType::QUESTION,
+            stats: None,
+        };
+        manager
+            .import_sessions(vec![Session {
+                schema_version: SESSION_SCHEMA_VERSION,
+                name: "secret".to_string(),
+                created: chrono::Utc::now(),
+                last_accessed: chrono::Utc::now(),
+                memory: HashMap::from([(prompt.id.clone(), prompt)]),
+                config_overrides: None,
+                referenced_files: Default::default(),
+            }])
+            .unwrap();
+
+        manager.encrypt_session("secret", "hunter2").unwrap();
+        let on_disk = fs::read_to_string(sessions_dir.join("secret.json")).unwrap();
+        assert!(!on_disk.contains("launch codes"));
+        assert!(manager.is_session_encrypted("secret"));
+
+        // A fresh process has no key: the header is readable, the content is not
+        manager.session_keys.clear();
+        assert!(manager.get_encrypted_session_header("secret", false).unwrap().is_some());
+        assert!(manager.get_session_info("secret").unwrap_err().to_string().contains("locked"));
+        assert!(manager.unlock_session("secret", "wrong").is_err());
+        assert!(sessions_dir.join("secret.json").exists());
+
+        // The clear-text header is authenticated with the ciphertext
+        let envelope: serde_json::Value = serde_json::from_str(&on_disk).unwrap();
+        let mut tampered = envelope.clone();
+        tampered["schema_version"] = serde_json::Value::from(0);
+        let key = crate::session_crypto::SessionKey::for_envelope("hunter2", &envelope).unwrap();
+        assert!(crate::session_crypto::decrypt_session(&tampered, &key).is_err());
+        for field in ["name", "created", "last_accessed"] {
+            let mut tampered = envelope.clone();
+            tampered[field] = serde_json::Value::from("2001-01-01T00:00:00Z");
+            assert!(crate::session_crypto::decrypt_session(&tampered, &key).is_err(), "{} is not authenticated", field);
+        }
+        assert!(crate::session_crypto::decrypt_session(&envelope, &key).is_ok());
+
+        manager.unlock_session("secret", "hunter2").unwrap();
+        assert_eq!(manager.get_session_info("secret").unwrap().memory["p1"].value, "launch codes");
+
+        manager.decrypt_session("secret").unwrap();
+        assert!(!manager.is_session_encrypted("secret"));
+        assert!(fs::read_to_string(sessions_dir.join("secret.json")).unwrap().contains("launch codes"));
+    }
+
+    #[test]
+    fn test_pack_and_unpack_bundle() {
+        let (dir, manager) = test_manager("bundle");
+        let sessions_dir = manager.sessions_dir.clone();
+        let work_dir = dir.join("work");
+        fs::create_dir_all(&work_dir).unwrap();
+
+        let notes = work_dir.join("notes.md");
+        fs::write(&notes, "# Notes\n").unwrap();
+        let mut session = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: "shared".to_string(),
+            created: chrono::Utc::now(),
+            last_accessed: chrono::Utc::now(),
+            memory: HashMap::new(),
+            config_overrides: None,
+            referenced_files: Default::default(),
+        };
+        session.referenced_files.insert(notes.display().to_string());
+        session.referenced_files.insert("gone.rs".to_string());
+        manager.import_sessions(vec![session]).unwrap();
+
+        let bundle = sessions_dir.join("shared.tar").display().to_string();
+        let report = manager.pack_session("shared", &bundle).unwrap();
+        assert_eq!(report.files, 1);
+        assert_eq!(report.missing, vec!["gone.rs".to_string()]);
+
+        // The name is taken, so the bundle is restored under a numbered name
+        let (name, files_dir, restored, skipped) = manager.unpack_session(&bundle, None).unwrap();
+        assert_eq!(name, "shared-2");
+        assert_eq!((restored, skipped.len()), (1, 0));
+        let snapshot = files_dir.join(notes.strip_prefix("/").unwrap_or(&notes));
+        assert_eq!(fs::read_to_string(snapshot).unwrap(), "# Notes\n");
+        assert!(manager.get_session_info("shared-2").unwrap().referenced_files.contains(&notes.display().to_string()));
+        assert!(manager.unpack_session(&bundle, Some("shared")).is_err());
+
+        // Flip a byte of the snapshot: the checksum no longer matches
+        let mut data = fs::read(&bundle).unwrap();
+        let offset = data.windows(7).position(|w| w == b"# Notes").unwrap();
+        data[offset + 2] = b'X';
+        fs::write(&bundle, data).unwrap();
+        let error = manager.unpack_session(&bundle, Some("tampered")).unwrap_err().to_string();
+        assert!(error.contains("Checksum mismatch"));
+    }
+
+    #[test]
+    fn test_session_stats() {
+        use crate::chat::{Prompt, PromptStats, PromptType};
+        use crate::session_stats::{compute_stats, render_text};
+        use chrono::{Duration, Utc};
+
+        let start = Utc::now() - Duration::days(1);
+        let mut session = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: "stats".to_string(),
+            created: start,
+            last_accessed: start,
+            memory: HashMap::new(),
+            config_overrides: None,
+            referenced_files: Default::default(),
+        };
+        let answer_stats = |model: &str, tokens: u32, cost: f64, latency_ms: u64| PromptStats {
+            model: Some(model.to_string()),
+            prompt_tokens: Some(tokens),
+            completion_tokens: Some(tokens / 2),
+            cost: Some(cost),
+            latency_ms: Some(latency_ms),
+            expansion_bytes: None,
+        };
+        let question_stats = |bytes: usize| PromptStats {
+            expansion_bytes: Some(bytes),
+            ..Default::default()
+        };
+        let prompts = [
+            ("q1", 0, PromptType::QUESTION, Some(question_stats(5000))),
+            ("a1", 1, PromptType::ANSWER, Some(answer_stats("model-a", 100, 0.01, 1000))),
+            ("q2", 2, PromptType::QUESTION, Some(question_stats(20))),
+            ("a2", 3, PromptType::ANSWER, Some(answer_stats("model-b", 300, 0.03, 3000))),
+            ("q3", 86400, PromptType::QUESTION, None),
+            ("a3", 86401, PromptType::ANSWER, Some(answer_stats("model-a", 200, 0.02, 2000))),
+            ("a4", 86402, PromptType::ANSWER, None),
+        ];
+        for (id, offset, ptype, stats) in prompts {
+            session.memory.insert(id.to_string(), Prompt {
+                id: id.to_string(),
+                date: start + Duration::seconds(offset),
+                value: format!("prompt {}", id),
+                ptype,
+                stats,
+            });
+        }
+
+        let stats = compute_stats(&session);
+        assert_eq!(stats.exchanges, 3);
+        assert_eq!((stats.questions, stats.answers), (3, 4));
+        assert_eq!(stats.prompt_tokens, 600);
+        assert_eq!(stats.completion_tokens, 300);
+        assert!((stats.cost - 0.06).abs() < 1e-9);
+        assert_eq!(stats.average_latency_ms, Some(2000.0));
+
+        let models: Vec<(&str, usize)> = stats.models.iter().map(|m| (m.model.as_str(), m.answers)).collect();
+        assert_eq!(models, vec![("model-a", 2), ("model-b", 1), ("unknown", 1)]);
+        assert_eq!(stats.models[0].average_latency_ms, Some(1500.0));
+
+        assert_eq!(stats.largest_expansions[0].prompt_id, "q1");
+        assert_eq!(stats.largest_expansions.len(), 2);
+        assert_eq!(stats.activity.len(), 2);
+        assert_eq!(stats.activity[1].answers, 2);
+
+        let report = render_text(&stats);
+        assert!(report.contains("model-b: 1 answers"));
+        assert!(serde_json::to_value(&stats).unwrap()["models"].is_array());
+    }
+
+    #[test]
+    fn test_session_replay_plan_and_report() {
+        use crate::chat::{Prompt, PromptType};
+        use crate::session_replay::*;
+        use chrono::{Duration, Utc};
+
+        let (_dir, mut manager) = test_manager("replay");
+
+        let start = Utc::now();
+        let mut source = Session {
+            schema_version: SESSION_SCHEMA_VERSION,
+            name: "work".to_string(),
+            created: start,
+            last_accessed: start,
+            memory: HashMap::new(),
+            config_overrides: None,
+            referenced_files: Default::default(),
+        };
+        let prompts = [
+            ("q1", PromptType::QUESTION, "First question"),
+            ("a1", PromptType::ANSWER, "First answer"),
+            ("a1b", PromptType::ANSWER, "Retried answer"),
+            ("q2", PromptType::QUESTION, "Second question"),
+        ];
+        for (i, (id, ptype, value)) in prompts.into_iter().enumerate() {
+            source.memory.insert(id.to_string(), Prompt {
+                id: id.to_string(),
+                date: start + Duration::seconds(i as i64),
+                value: value.to_string(),
+                ptype,
+                stats: None,
+            });
+        }
+
+        let steps = replay_plan(&source);
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].original_answer.as_ref().unwrap().id, "a1");
+        assert!(steps[1].original_answer.is_none());
+
+        let derived = derived_session_name("work", "openai/gpt-4o:free");
+        assert_eq!(derived, "work-replay-openai-gpt-4o-free");
+
+        // The derived session receives replayed answers and keeps them on reload
+        manager.create_derived_session("work", &derived, None).unwrap();
+        assert!(manager.create_derived_session("work", &derived, None).is_err());
+        let answer = Prompt {
+            id: "n1".to_string(),
+            date: Utc::now(),
+            value: "New | answer\nline two".to_string(),
+            ptype: PromptType::ANSWER,
+            stats: None,
+        };
+        manager.append_prompts(&derived, &[steps[0].question.clone(), answer]).unwrap();
+        let session = manager.get_session_info(&derived).unwrap();
+        assert_eq!(session.memory.len(), 2);
+
+        let state = ReplayState {
+            source: "work".to_string(),
+            model: "openai/gpt-4o:free".to_string(),
+            derived: derived.clone(),
+            started: start,
+            finished: None,
+            exchanges: vec![ReplayedExchange {
+                question_id: "q1".to_string(),
+                answer_id: "n1".to_string(),
+            }],
+        };
+        let report = render_report(&state, &steps, &session);
+        assert!(report.contains("## 1. First question (q1)"));
+        assert!(report.contains("| First answer | New \\| answer<br>line two |"));
+        assert!(report.contains("| Replay: not replayed |"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind};
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, Cell, Clear, Paragraph, Row, Table, Wrap};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Flex, Layout, Rect},
+    style::{Style, Stylize, palette::tailwind::SKY},
+};
+
+use crate::session_stats::{SessionStats, format_latency};
+
+/// Popup showing the result of `@session-stats`.
+pub struct StatsPanel {
+    stats: SessionStats,
+}
+
+impl StatsPanel {
+    pub fn new(stats: SessionStats) -> Self {
+        Self { stats }
+    }
+
+    /// Returns true when the panel should be closed.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Esc | KeyCode::Enter)
+    }
+
+    pub fn render_stats_popup(&self, frame: &mut Frame) {
+        let popup_area = self.popup_area(frame.area(), 85, 80);
+        frame.render_widget(Clear, popup_area);
+        let outer = Block::bordered().title(format!(
+            "Session stats: {} ('Esc' to close)",
+            self.stats.session
+        ));
+        let inner = outer.inner(popup_area);
+        frame.render_widget(outer, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(4),
+                Constraint::Min(5),
+                Constraint::Length(10),
+            ])
+            .split(inner);
+        let bottom = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[2]);
+
+        let stats = &self.stats;
+        let summary = Paragraph::new(format!(
+            "Exchanges: {} ({} questions, {} answers)\nTokens: {} prompt + {} completion    Cost: ${:.4}\nAverage latency: {}",
+            stats.exchanges,
+            stats.questions,
+            stats.answers,
+            stats.prompt_tokens,
+            stats.completion_tokens,
+            stats.cost,
+            format_latency(stats.average_latency_ms)
+        ))
+        .block(Block::bordered().title("Summary"));
+        frame.render_widget(summary, rows[0]);
+
+        let model_rows = stats.models.iter().map(|model| {
+            Row::new(vec![
+                Cell::from(model.model.clone()),
+                Cell::from(model.answers.to_string()),
+                Cell::from(format!("{} + {}", model.prompt_tokens, model.completion_tokens)),
+                Cell::from(format!("${:.4}", model.cost)),
+                Cell::from(format_latency(model.average_latency_ms)),
+            ])
+        });
+        let models = Table::new(
+            model_rows,
+            [
+                Constraint::Percentage(40),
+                Constraint::Percentage(12),
+                Constraint::Percentage(20),
+                Constraint::Percentage(14),
+                Constraint::Percentage(14),
+            ],
+        )
+        .header(Row::new(vec!["Model", "Answers", "Tokens", "Cost", "Latency"]).bold())
+        .block(Block::bordered().title("By model"));
+        frame.render_widget(models, rows[1]);
+
+        // Show as many of the most recent days as fit
+        let visible_days = (bottom[0].width.saturating_sub(2) / 6).max(1) as usize;
+        let days = &stats.activity[stats.activity.len().saturating_sub(visible_days)..];
+        let bars: Vec<Bar> = days
+            .iter()
+            .map(|day| {
+                Bar::default()
+                    .value((day.questions + day.answers) as u64)
+                    .label(day.date.format("%m-%d").to_string().into())
+            })
+            .collect();
+        let activity = BarChart::default()
+            .block(Block::bordered().title("Activity per day"))
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(5)
+            .bar_gap(1)
+            .bar_style(Style::new().fg(SKY.c500));
+        frame.render_widget(activity, bottom[0]);
+
+        let expansions = stats
+            .largest_expansions
+            .iter()
+            .map(|expansion| format!("{}  {} bytes  {}", expansion.prompt_id, expansion.bytes, expansion.preview))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let expansions = Paragraph::new(expansions)
+            .block(Block::bordered().title("Largest context expansions"))
+            .wrap(Wrap { trim: true });
+        frame.render_widget(expansions, bottom[1]);
+    }
+
+    fn popup_area(&self, area: Rect, percent_x: u16, percent_y: u16) -> Rect {
+        let vertical = Layout::vertical([Constraint::Percentage(percent_y)]).flex(Flex::Center);
+        let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+        let [area] = vertical.areas(area);
+        let [area] = horizontal.areas(area);
+        area
+    }
+}
@@ -0,0 +1,338 @@
+//! Definitions in source files, found with tree-sitter grammars.
+//!
+//! A symbol is named by its path of enclosing definitions: `WorkflowEngine::execute_step` is
+//! the method in an `impl WorkflowEngine` block, or in a Python `class WorkflowEngine`.
+use crate::configuration::FileSettings;
+use crate::files::files::read_files;
+use std::path::Path;
+use tree_sitter::{Language, Node, Parser};
+
+/// A definition and the lines it takes, counted from 1 and including its doc comments,
+/// attributes and decorators.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub path: String,
+    pub kind: &'static str,
+    /// How many definitions enclose this one
+    pub depth: usize,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Grammar {
+    Rust,
+    Python,
+    TypeScript,
+    Tsx,
+    Go,
+}
+
+impl Grammar {
+    fn for_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        match extension {
+            "rs" => Some(Grammar::Rust),
+            "py" | "pyi" => Some(Grammar::Python),
+            "ts" | "mts" | "cts" => Some(Grammar::TypeScript),
+            // The TSX grammar reads plain JavaScript as well
+            "tsx" | "js" | "jsx" | "mjs" | "cjs" => Some(Grammar::Tsx),
+            "go" => Some(Grammar::Go),
+            _ => None,
+        }
+    }
+
+    fn language(self) -> Language {
+        match self {
+            Grammar::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Grammar::Python => tree_sitter_python::LANGUAGE.into(),
+            Grammar::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Grammar::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Grammar::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+}
+
+/// A definition found in the tree before its path is known.
+struct Definition<'tree> {
+    name: String,
+    kind: &'static str,
+    /// The node whose lines the symbol takes, e.g. with the `export` or decorators
+    outer: Node<'tree>,
+    /// Where nested definitions are, for classes, impl blocks and modules
+    body: Option<Node<'tree>>,
+}
+
+/// All definitions in `source`, outer ones before the ones inside them. `path` only decides
+/// the grammar, by its extension.
+pub fn symbols(path: &str, source: &str) -> Result<Vec<Symbol>, Box<dyn std::error::Error>> {
+    let grammar = Grammar::for_path(path).ok_or_else(|| format!("{}: symbols are read from Rust, Python, TypeScript, JavaScript and Go files", path))?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language())?;
+    let tree = parser.parse(source, None).ok_or_else(|| format!("{}: could not be parsed", path))?;
+
+    let mut found = Vec::new();
+    collect(grammar, tree.root_node(), source, None, &mut found);
+    Ok(found)
+}
+
+/// Whether symbols can be read from the file at `path`, by its extension.
+pub fn is_supported(path: &str) -> bool {
+    Grammar::for_path(path).is_some()
+}
+
+/// The top-level definitions of a file, one per line with the line they start on. The
+/// methods of impl blocks, classes, traits and interfaces are listed with them:
+/// `impl Engine :12 { new, run }`.
+pub fn outline(symbols: &[Symbol]) -> Vec<String> {
+    symbols
+        .iter()
+        .filter(|symbol| symbol.depth == 0)
+        .map(|symbol| {
+            let members: Vec<&str> = symbols
+                .iter()
+                .filter(|member| member.depth == 1 && member.start_line >= symbol.start_line && member.end_line <= symbol.end_line)
+                .filter_map(|member| member.path.rsplit("::").next())
+                .collect();
+            let line = format!("{} {} :{}", symbol.kind, symbol.path, symbol.start_line);
+            if members.is_empty() { line } else { format!("{} {{ {} }}", line, members.join(", ")) }
+        })
+        .collect()
+}
+
+/// The outline of each supported file among `paths`, under its path. Files that cannot be
+/// read or parsed are listed with the reason, and once the output would grow beyond
+/// `max_total_bytes` the remaining files are only counted.
+pub fn outline_files(paths: &[String], settings: &FileSettings) -> Vec<String> {
+    let paths: Vec<&String> = paths.iter().filter(|path| is_supported(path)).collect();
+    let mut lines = Vec::new();
+    let mut size = 0;
+    for (index, path) in paths.iter().enumerate() {
+        let (files, skipped) = read_files(std::slice::from_ref(*path), settings);
+        let entry = match (files.first(), skipped.first()) {
+            (_, Some(file)) => vec![format!("{} (skipped: {})", path, file.reason)],
+            (Some(file), None) => match symbols(path, &file.content) {
+                Ok(found) => std::iter::once(path.to_string())
+                    .chain(outline(&found).into_iter().map(|line| format!("  {}", line)))
+                    .collect(),
+                Err(e) => vec![format!("{} (skipped: {})", path, e)],
+            },
+            (None, None) => continue,
+        };
+        let entry_size: u64 = entry.iter().map(|line| line.len() as u64 + 1).sum();
+        if size + entry_size > settings.max_total_bytes {
+            lines.push(format!(
+                "({} more files left out to stay within {} bytes)",
+                paths.len() - index,
+                settings.max_total_bytes
+            ));
+            break;
+        }
+        size += entry_size;
+        lines.extend(entry);
+    }
+    lines
+}
+
+/// The definitions `query` names: those with exactly that path, otherwise those whose path
+/// ends with it. `.` separates names like `::` does.
+pub fn find<'a>(symbols: &'a [Symbol], query: &str) -> Vec<&'a Symbol> {
+    let query = query.trim().replace('.', "::");
+    let exact: Vec<&Symbol> = symbols.iter().filter(|symbol| symbol.path == query).collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+    let suffix = format!("::{}", query);
+    symbols.iter().filter(|symbol| symbol.path.ends_with(&suffix)).collect()
+}
+
+/// Add the definitions among `node`'s children, and the ones nested in them, to `found`.
+/// `parent` is the enclosing definition.
+fn collect(grammar: Grammar, node: Node, source: &str, parent: Option<&Symbol>, found: &mut Vec<Symbol>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        for definition in definitions(grammar, child, source) {
+            let symbol = Symbol {
+                path: match parent {
+                    Some(parent) => format!("{}::{}", parent.path, definition.name),
+                    None => definition.name,
+                },
+                depth: parent.map_or(0, |parent| parent.depth + 1),
+                kind: match definition.kind {
+                    "function" if parent.is_some_and(|parent| matches!(parent.kind, "impl" | "trait" | "class" | "interface")) => "method",
+                    kind => kind,
+                },
+                start_line: leading_start(definition.outer) + 1,
+                end_line: last_row(definition.outer) + 1,
+            };
+            found.push(symbol.clone());
+            if let Some(body) = definition.body {
+                collect(grammar, body, source, Some(&symbol), found);
+            }
+        }
+    }
+}
+
+fn definitions<'tree>(grammar: Grammar, node: Node<'tree>, source: &str) -> Vec<Definition<'tree>> {
+    let text = |node: Node| node.utf8_text(source.as_bytes()).unwrap_or_default().to_string();
+    let named = |kind: &'static str, body: Option<Node<'tree>>| {
+        node.child_by_field_name("name")
+            .map(|name| Definition { name: text(name), kind, outer: node, body })
+            .into_iter()
+            .collect()
+    };
+    let body = node.child_by_field_name("body");
+
+    match (grammar, node.kind()) {
+        (Grammar::Rust, "function_item" | "function_signature_item") => named("function", None),
+        (Grammar::Rust, "struct_item") => named("struct", None),
+        (Grammar::Rust, "enum_item") => named("enum", None),
+        (Grammar::Rust, "union_item") => named("union", None),
+        (Grammar::Rust, "trait_item") => named("trait", body),
+        (Grammar::Rust, "mod_item") => named("module", body),
+        (Grammar::Rust, "const_item") => named("const", None),
+        (Grammar::Rust, "static_item") => named("static", None),
+        (Grammar::Rust, "type_item") => named("type", None),
+        (Grammar::Rust, "macro_definition") => named("macro", None),
+        (Grammar::Rust, "impl_item") => node
+            .child_by_field_name("type")
+            .map(|self_type| Definition {
+                name: type_name(&text(self_type)),
+                kind: "impl",
+                outer: node,
+                body,
+            })
+            .into_iter()
+            .collect(),
+
+        (Grammar::Python, "function_definition") => named("function", None),
+        (Grammar::Python, "class_definition") => named("class", body),
+        (Grammar::Python, "decorated_definition") => match node.child_by_field_name("definition") {
+            Some(definition) => with_outer(node, definitions(grammar, definition, source)),
+            None => Vec::new(),
+        },
+
+        (Grammar::TypeScript | Grammar::Tsx, "function_declaration" | "generator_function_declaration") => named("function", None),
+        (Grammar::TypeScript | Grammar::Tsx, "method_definition" | "method_signature" | "abstract_method_signature") => {
+            named("function", None)
+        }
+        (Grammar::TypeScript | Grammar::Tsx, "class_declaration" | "abstract_class_declaration") => named("class", body),
+        (Grammar::TypeScript | Grammar::Tsx, "interface_declaration") => named("interface", body),
+        (Grammar::TypeScript | Grammar::Tsx, "type_alias_declaration") => named("type", None),
+        (Grammar::TypeScript | Grammar::Tsx, "enum_declaration") => named("enum", None),
+        (Grammar::TypeScript | Grammar::Tsx, "internal_module" | "module") => named("module", body),
+        (Grammar::TypeScript | Grammar::Tsx, "export_statement") => match node.child_by_field_name("declaration") {
+            Some(declaration) => with_outer(node, definitions(grammar, declaration, source)),
+            None => Vec::new(),
+        },
+        // `const handler = () => {}` defines a function too
+        (Grammar::TypeScript | Grammar::Tsx, "lexical_declaration" | "variable_declaration") => {
+            let mut cursor = node.walk();
+            let declarators: Vec<Node> = node.named_children(&mut cursor).collect();
+            let single = declarators.len() == 1;
+            declarators
+                .into_iter()
+                .filter(|declarator| {
+                    declarator.child_by_field_name("value").is_some_and(|value| {
+                        matches!(value.kind(), "arrow_function" | "function_expression" | "function" | "class")
+                    })
+                })
+                .filter_map(|declarator| {
+                    Some(Definition {
+                        name: text(declarator.child_by_field_name("name")?),
+                        kind: "function",
+                        outer: if single { node } else { declarator },
+                        body: None,
+                    })
+                })
+                .collect()
+        }
+
+        (Grammar::Go, "function_declaration") => named("function", None),
+        // Methods are named after their receiver type, like `Server::Start`
+        (Grammar::Go, "method_declaration") => {
+            let receiver = node
+                .child_by_field_name("receiver")
+                .and_then(|receiver| receiver.named_child(0))
+                .and_then(|parameter| parameter.child_by_field_name("type"))
+                .map(|receiver_type| type_name(&text(receiver_type)));
+            match (receiver, node.child_by_field_name("name")) {
+                (Some(receiver), Some(name)) => vec![Definition {
+                    name: format!("{}::{}", receiver, text(name)),
+                    kind: "method",
+                    outer: node,
+                    body: None,
+                }],
+                _ => named("function", None),
+            }
+        }
+        (Grammar::Go, "type_declaration") => {
+            let mut cursor = node.walk();
+            let specs: Vec<Node> = node
+                .named_children(&mut cursor)
+                .filter(|spec| matches!(spec.kind(), "type_spec" | "type_alias"))
+                .collect();
+            let single = specs.len() == 1;
+            specs
+                .into_iter()
+                .filter_map(|spec| {
+                    let kind = match spec.child_by_field_name("type").map(|spec_type| spec_type.kind()) {
+                        Some("struct_type") => "struct",
+                        Some("interface_type") => "interface",
+                        _ => "type",
+                    };
+                    Some(Definition {
+                        name: text(spec.child_by_field_name("name")?),
+                        kind,
+                        outer: if single { node } else { spec },
+                        body: None,
+                    })
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Give definitions found inside a wrapper, like `export` or decorators, the wrapper's lines.
+fn with_outer<'tree>(outer: Node<'tree>, definitions: Vec<Definition<'tree>>) -> Vec<Definition<'tree>> {
+    let single = definitions.len() == 1;
+    definitions
+        .into_iter()
+        .map(|definition| if single { Definition { outer, ..definition } } else { definition })
+        .collect()
+}
+
+/// A type as written in an impl block or receiver, without references, pointers and
+/// generic arguments: `&mut Engine<'a, T>` is `Engine`.
+fn type_name(text: &str) -> String {
+    let text = text.trim_start_matches(['&', '*']).trim_start();
+    let text = text.strip_prefix("mut ").unwrap_or(text);
+    let end = text.find(['<', '[']).unwrap_or(text.len());
+    text[..end].trim().to_string()
+}
+
+/// The row a definition starts on, counting the comments and attributes right above it.
+fn leading_start(node: Node) -> usize {
+    let mut start = node.start_position().row;
+    let mut previous = node.prev_named_sibling();
+    while let Some(sibling) = previous {
+        let attached = sibling.kind().contains("comment") || matches!(sibling.kind(), "attribute_item" | "decorator");
+        if !attached || last_row(sibling) + 1 < start {
+            break;
+        }
+        // A comment after the end of the previous item is not about this one
+        if sibling.prev_named_sibling().is_some_and(|before| last_row(before) == sibling.start_position().row) {
+            break;
+        }
+        start = sibling.start_position().row;
+        previous = sibling.prev_named_sibling();
+    }
+    start
+}
+
+/// The last row with text of `node`; line comments end at the start of the next row.
+fn last_row(node: Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row { end.row - 1 } else { end.row }
+}
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod tests {
+    use crate::configuration::FileSettings;
+    use crate::symbols::*;
+    use crate::test_util::TempDir;
+
+    fn summary(symbols: &[Symbol]) -> Vec<(String, &'static str, usize, usize)> {
+        symbols.iter().map(|symbol| (symbol.path.clone(), symbol.kind, symbol.start_line, symbol.end_line)).collect()
+    }
+
+    #[test]
+    fn test_rust_symbols() {
+        let source = r#"use std::fmt;
+
+/// The engine
+#[derive(Debug)]
+pub struct WorkflowEngine {
+    steps: Vec<String>,
+}
+
+impl<'a> WorkflowEngine {
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// Run one step
+    fn execute_step(&self) {}
+}
+
+impl fmt::Display for WorkflowEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "engine")
+    }
+}
+
+mod tests {
+    fn helper() {}
+}
+"#;
+        let found = symbols("src/workflow.rs", source).unwrap();
+        assert_eq!(summary(&found), vec![
+            ("WorkflowEngine".to_string(), "struct", 3, 7),
+            ("WorkflowEngine".to_string(), "impl", 9, 16),
+            ("WorkflowEngine::new".to_string(), "method", 10, 12),
+            ("WorkflowEngine::execute_step".to_string(), "method", 14, 15),
+            ("WorkflowEngine".to_string(), "impl", 18, 22),
+            ("WorkflowEngine::fmt".to_string(), "method", 19, 21),
+            ("tests".to_string(), "module", 24, 26),
+            ("tests::helper".to_string(), "function", 25, 25),
+        ]);
+
+        let step = find(&found, "WorkflowEngine::execute_step");
+        assert_eq!(step.len(), 1);
+        assert_eq!(find(&found, "execute_step"), step);
+        // A type is its definition and all its impl blocks
+        assert_eq!(find(&found, "WorkflowEngine").len(), 3);
+        assert!(find(&found, "Engine").is_empty());
+
+        assert_eq!(outline(&found), vec![
+            "struct WorkflowEngine :3",
+            "impl WorkflowEngine :9 { new, execute_step }",
+            "impl WorkflowEngine :18 { fmt }",
+            "module tests :24 { helper }",
+        ]);
+    }
+
+    #[test]
+    fn test_python_typescript_and_go_symbols() {
+        let python = "import os\n\n@dataclass\nclass Engine:\n    def run(self):\n        pass\n\ndef main():\n    Engine().run()\n";
+        let found = symbols("engine.py", python).unwrap();
+        assert_eq!(summary(&found), vec![
+            ("Engine".to_string(), "class", 3, 6),
+            ("Engine::run".to_string(), "method", 5, 6),
+            ("main".to_string(), "function", 8, 9),
+        ]);
+        assert_eq!(find(&found, "Engine.run")[0].start_line, 5);
+
+        let typescript = "// Entry point\nexport class App {\n  start(): void {}\n}\n\nexport const handler = () => 1;\ninterface Options { verbose: boolean }\n";
+        let found = symbols("app.ts", typescript).unwrap();
+        assert_eq!(summary(&found), vec![
+            ("App".to_string(), "class", 1, 4),
+            ("App::start".to_string(), "method", 3, 3),
+            ("handler".to_string(), "function", 6, 6),
+            ("Options".to_string(), "interface", 7, 7),
+        ]);
+
+        let go = "package main\n\ntype Server struct {\n\tport int\n}\n\n// Start listens\nfunc (s *Server) Start() error {\n\treturn nil\n}\n\nfunc main() {}\n";
+        let found = symbols("main.go", go).unwrap();
+        assert_eq!(summary(&found), vec![
+            ("Server".to_string(), "struct", 3, 5),
+            ("Server::Start".to_string(), "method", 7, 10),
+            ("main".to_string(), "function", 12, 12),
+        ]);
+
+        assert!(symbols("notes.txt", "text").is_err());
+    }
+
+    #[test]
+    fn test_outline_files() {
+        let dir = TempDir::new("outline");
+        let path = |name: &str| dir.join(name).display().to_string();
+        std::fs::write(dir.join("a.rs"), "fn first() {}\n").unwrap();
+        std::fs::write(dir.join("b.rs"), format!("// {}\n", "x".repeat(40))).unwrap();
+        std::fs::write(dir.join("c.rs"), "fn third() {}\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "text").unwrap();
+        let paths: Vec<String> = ["a.rs", "b.rs", "notes.txt", "c.rs"].iter().map(|name| path(name)).collect();
+
+        // A file that is skipped is reported and the others are still outlined
+        let settings = FileSettings {
+            max_file_bytes: 20,
+            ..FileSettings::default()
+        };
+        let lines = outline_files(&paths, &settings);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[..2], [path("a.rs"), "  function first :1".to_string()]);
+        assert!(lines[2].starts_with(&format!("{} (skipped: ", path("b.rs"))));
+        assert_eq!(lines[3..], [path("c.rs"), "  function third :1".to_string()]);
+
+        // The output stops before the file that would exceed the limit
+        let settings = FileSettings {
+            max_total_bytes: (path("a.rs").len() + 22) as u64,
+            ..settings
+        };
+        let lines = outline_files(&paths, &settings);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], format!("(2 more files left out to stay within {} bytes)", settings.max_total_bytes));
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers shared by the `*_test.rs` modules.
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// A fresh directory below the temp directory, removed with everything in it on drop, also
+/// when the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create `cai-<name>-<uuid>` in the temp directory.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("cai-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
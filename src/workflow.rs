@@ -1,6 +1,8 @@
 use crate::chat::{self, Prompt, PromptType};
+use crate::command_parser;
 use crate::commands_registry;
 use crate::openrouter;
+use crate::scripting;
 use chrono::{DateTime, Utc};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
@@ -304,7 +306,7 @@ impl WorkflowEngine {
         Ok(steps)
     }
 
-    fn extract_step_from_line(&self, line: &str) -> Option<&str> {
+    fn extract_step_from_line<'a>(&self, line: &'a str) -> Option<&'a str> {
         // Match patterns like "1. Step description" or "Step 1: Description"
         if line.chars().next().unwrap_or(' ').is_ascii_digit() {
             if let Some(pos) = line.find('.') {
@@ -317,16 +319,12 @@ impl WorkflowEngine {
     }
 
     fn extract_command_from_step(&self, step_text: &str) -> Option<String> {
-        // Look for command patterns in step text
-        if step_text.contains("@") && step_text.contains("(") {
-            // Find command pattern like @command(params)
-            if let Some(start) = step_text.find('@') {
-                if let Some(end) = step_text[start..].find(')') {
-                    return Some(step_text[start..start + end + 1].to_string());
-                }
-            }
-        }
-        None
+        // The first well-formed call of a known command or pipeline in the step text, like @command(params)
+        command_parser::scan_prompt(step_text, |sigil, name| commands_registry::find_command(sigil, name).is_some())
+            .pipelines
+            .into_iter()
+            .find_map(Result::ok)
+            .map(|pipeline| step_text[pipeline.span].to_string())
     }
 
     pub async fn execute_step(&self, plan_id: &str, step_id: &str) -> Result<StepResult, WorkflowError> {
@@ -345,6 +343,7 @@ impl WorkflowEngine {
         let step = plan.steps[step_index].clone();
 
         drop(workflows); // Release lock before execution
+        scripting::step_started(plan_id, step_id, &step.description).await;
 
         let result = match step.step_type {
             StepType::McpTool => {
@@ -369,6 +368,13 @@ impl WorkflowEngine {
                 self.execute_verification_step(&step).await
             },
         };
+        match &result {
+            Ok(output) => {
+                let output = output.as_deref().unwrap_or_default();
+                scripting::step_finished(plan_id, step_id, &step.description, true, output).await
+            }
+            Err(e) => scripting::step_finished(plan_id, step_id, &step.description, false, &e.to_string()).await,
+        }
 
         // Update step with result
         let mut workflows = ACTIVE_WORKFLOWS.lock().unwrap();
@@ -403,10 +409,10 @@ impl WorkflowEngine {
     async fn execute_command(&self, command: &str) -> Result<Option<String>, WorkflowError> {
 
         // Use existing command execution system
-        match chat::execute_command(command) {
+        match chat::execute_command(command).await {
             Ok(Some(result)) => {
                 if let Ok(Some(output)) = result.command_output {
-                    Ok(Some(output))
+                    Ok(Some(output.render_llm()))
                 } else {
                     Ok(Some("Command executed successfully".to_string()))
                 }
@@ -508,6 +514,21 @@ impl WorkflowEngine {
             VerificationStrategy::LLMValidation => {
                 self.verify_with_llm(plan).await
             }
+            VerificationStrategy::McpTool(tool) => {
+                // The tool decides: the plan passes when the call succeeds
+                match self.execute_mcp_tool(tool, &None).await {
+                    Ok(result) => Ok(VerificationResult {
+                        success: true,
+                        score: 1.0,
+                        message: result.unwrap_or_default(),
+                    }),
+                    Err(e) => Ok(VerificationResult {
+                        success: false,
+                        score: 0.0,
+                        message: e.to_string(),
+                    }),
+                }
+            }
             VerificationStrategy::Combined => {
                 // Combine multiple verification strategies
                 let file_result = self.verify_files_exist(plan).await?;